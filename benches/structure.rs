@@ -0,0 +1,187 @@
+//! Needs the saves in `production_data`, extracted with `prepare_data.sh 3.4.5.95132`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clausewitz_parser::{
+    root, root_arena, root_tape, Bump, Interner, Parallel, ParseOptions, SectionIndex,
+    StructuralIndex, Tape, Transcoder,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const GAMESTATE: &str = "production_data/3.4.5.95132/2290.03.05/gamestate";
+
+/// The system allocator, counting the bytes held so that [`peak`] can report the most a parse
+/// holds at once.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+impl Counting {
+    fn grown(size: usize) {
+        let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(now, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Counting::grown(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            Counting::grown(new_size);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// The most bytes allocated at once while `f` runs, beyond what was held before it.
+fn peak(f: impl FnOnce()) -> usize {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    f();
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+fn gamestate() -> Option<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(GAMESTATE);
+    match fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) => {
+            eprintln!("skipping, can not read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Prints the peak allocation of each parse, which criterion has no way to measure.
+fn peak_allocation(_: &mut Criterion) {
+    let Some(text) = gamestate() else {
+        return;
+    };
+    let options = ParseOptions::default();
+    let (_, tape) = root_tape(&text, &options).unwrap();
+    let mut cache = vec![];
+    tape.write_cache(&options, &mut cache).unwrap();
+    drop(tape);
+
+    let peaks = [
+        ("root", peak(|| drop(root(&text).unwrap()))),
+        (
+            "root_tape",
+            peak(|| drop(root_tape(&text, &options).unwrap())),
+        ),
+        (
+            "root_arena",
+            peak(|| {
+                let arena = Bump::new();
+                let mut keys = Interner::new();
+                root_arena(&text, &options, &arena, &mut keys).unwrap();
+            }),
+        ),
+        (
+            "root_parallel",
+            peak(|| drop(Parallel::new().root(&text, &options).unwrap())),
+        ),
+        (
+            "tape_from_cache",
+            peak(|| drop(Tape::from_cache(&text, &options, &cache).unwrap())),
+        ),
+        (
+            "transcode",
+            peak(|| {
+                Transcoder::new()
+                    .write(&text, &options, std::io::sink())
+                    .unwrap()
+            }),
+        ),
+        (
+            "structural_index",
+            peak(|| drop(StructuralIndex::new(&text).unwrap())),
+        ),
+        (
+            "section_index",
+            peak(|| drop(SectionIndex::new(&text).unwrap())),
+        ),
+    ];
+    eprintln!("peak allocation, gamestate of {} MiB:", text.len() >> 20);
+    for (name, bytes) in peaks {
+        eprintln!("  {:<20} {:>6} MiB", name, bytes >> 20);
+    }
+}
+
+fn structure(c: &mut Criterion) {
+    let Some(text) = gamestate() else {
+        return;
+    };
+
+    let mut group = c.benchmark_group("gamestate");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("root", |b| b.iter(|| root(&text).unwrap()));
+    group.bench_function("root_tape", |b| {
+        b.iter(|| root_tape(&text, &ParseOptions::default()).unwrap())
+    });
+    group.bench_function("root_arena", |b| {
+        b.iter(|| {
+            let arena = Bump::new();
+            let mut keys = Interner::new();
+            root_arena(&text, &ParseOptions::default(), &arena, &mut keys).unwrap();
+        })
+    });
+    group.bench_function("root_parallel", |b| {
+        b.iter(|| {
+            Parallel::new()
+                .root(&text, &ParseOptions::default())
+                .unwrap()
+        })
+    });
+    group.bench_function("tape_from_cache", |b| {
+        let options = ParseOptions::default();
+        let (_, tape) = root_tape(&text, &options).unwrap();
+        let mut cache = vec![];
+        tape.write_cache(&options, &mut cache).unwrap();
+        b.iter(|| Tape::from_cache(&text, &options, &cache).unwrap())
+    });
+    group.bench_function("transcode", |b| {
+        b.iter(|| {
+            Transcoder::new()
+                .write(&text, &ParseOptions::default(), std::io::sink())
+                .unwrap()
+        })
+    });
+    group.bench_function("structural_index", |b| {
+        b.iter(|| StructuralIndex::new(&text).unwrap())
+    });
+    group.bench_function("structural_index_sections", |b| {
+        b.iter(|| StructuralIndex::new(&text).unwrap().sections().unwrap())
+    });
+    group.bench_function("section_index", |b| {
+        b.iter(|| SectionIndex::new(&text).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, peak_allocation, structure);
+criterion_main!(benches);
@@ -0,0 +1,512 @@
+use bumpalo::Bump;
+
+use super::{
+    bracketed::{key_value, mixed_entry},
+    builder::{index, Builder, Built, Container, Decline, Fits},
+    date::ClausewitzDate,
+    intern::{Interner, Symbol},
+    lexer::{Token, TokenKind},
+    number::Number,
+    options::ParseOptions,
+    shape::{shape, Shape},
+    val::{IndexError, Val},
+    value::value,
+    Res,
+};
+
+/// A value whose containers live in a [`Bump`] arena and whose keys are [`Symbol`]s, so a whole
+/// document is freed at once with the arena. Numbers are kept as their text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArenaVal<'b> {
+    Dict(&'b [(Symbol, ArenaVal<'b>)]),
+    NumberedDict(&'b (i64, &'b [(Symbol, ArenaVal<'b>)])),
+    Array(&'b [(u64, ArenaVal<'b>)]),
+    Set(&'b [ArenaVal<'b>]),
+    Mixed(&'b [(Option<Symbol>, ArenaVal<'b>)]),
+    Tagged(&'b (&'b str, ArenaVal<'b>)),
+    StringLiteral(&'b str),
+    Date(ClausewitzDate),
+    Decimal(&'b str),
+    Integer(&'b str),
+    Boolean(bool),
+    Identifier(&'b str),
+}
+
+impl<'b> ArenaVal<'b> {
+    /// Whether this is a container written as a block, rather than a tag or a scalar.
+    fn is_block(&self) -> bool {
+        matches!(
+            self,
+            ArenaVal::Dict(_)
+                | ArenaVal::NumberedDict(_)
+                | ArenaVal::Array(_)
+                | ArenaVal::Set(_)
+                | ArenaVal::Mixed(_)
+        )
+    }
+
+    /// The value stored under `key`, looking through tags.
+    pub fn get_symbol(&self, key: Symbol) -> Option<&'b ArenaVal<'b>> {
+        let find = |entries: &'b [(Symbol, ArenaVal<'b>)]| {
+            entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+        };
+        match *self {
+            ArenaVal::Dict(entries) => find(entries),
+            ArenaVal::NumberedDict((_, entries)) => find(entries),
+            ArenaVal::Mixed(entries) => entries
+                .iter()
+                .find(|(k, _)| *k == Some(key))
+                .map(|(_, v)| v),
+            ArenaVal::Tagged((_, inner)) => inner.get_symbol(key),
+            _ => None,
+        }
+    }
+
+    /// Looks `p` up like [`Val`] does, comparing symbols instead of strings.
+    pub fn get(&self, keys: &Interner<'b>, p: &str) -> Result<&'b ArenaVal<'b>, IndexError> {
+        match *self {
+            ArenaVal::Dict(_) | ArenaVal::NumberedDict(_) | ArenaVal::Mixed(_) => keys
+                .get(p)
+                .and_then(|key| self.get_symbol(key))
+                .ok_or(IndexError {
+                    err: format!("Expected to find value with key {}", p),
+                }),
+            ArenaVal::Array(pairs) => {
+                let index = p.parse::<u64>().map_err(|_| IndexError {
+                    err: format!("Cannot index an array with {}", p),
+                })?;
+                pairs
+                    .iter()
+                    .find(|(i, _)| *i == index)
+                    .map(|(_, v)| v)
+                    .ok_or(IndexError {
+                        err: format!("Expected to find value with index {}", p),
+                    })
+            }
+            // `color.rgb` names the tag explicitly, `color.0` looks through it
+            ArenaVal::Tagged((tag, inner)) if *tag == p => Ok(inner),
+            ArenaVal::Tagged((_, inner)) => inner.get(keys, p),
+            ArenaVal::Set(_) => Err(IndexError {
+                err: format!("Cannot index a set with index {}", p),
+            }),
+            _ => Err(IndexError {
+                err: "Cannot index terminal values!".to_owned(),
+            }),
+        }
+    }
+
+    pub fn get_at_path(
+        &self,
+        keys: &Interner<'b>,
+        path: &str,
+    ) -> Result<&'b ArenaVal<'b>, IndexError> {
+        let mut segments = path.split('.');
+        let first = self.get(keys, segments.next().unwrap_or_default())?;
+        segments.try_fold(first, |val, p| val.get(keys, p))
+    }
+
+    pub fn to_val(&self, keys: &Interner<'b>) -> Val<'b> {
+        let entries = |entries: &'b [(Symbol, ArenaVal<'b>)]| {
+            entries
+                .iter()
+                .map(|(k, v)| (keys.resolve(*k), v.to_val(keys)))
+                .collect()
+        };
+        match *self {
+            ArenaVal::Dict(e) => Val::Dict(entries(e)),
+            ArenaVal::NumberedDict((number, e)) => Val::NumberedDict(*number, entries(e)),
+            ArenaVal::Array(pairs) => {
+                Val::Array(pairs.iter().map(|(i, v)| (*i, v.to_val(keys))).collect())
+            }
+            ArenaVal::Set(vals) => Val::Set(vals.iter().map(|v| v.to_val(keys)).collect()),
+            ArenaVal::Mixed(e) => Val::Mixed(
+                e.iter()
+                    .map(|(k, v)| (k.map(|k| keys.resolve(k)), v.to_val(keys)))
+                    .collect(),
+            ),
+            ArenaVal::Tagged((tag, inner)) => Val::Tagged(tag, Box::new(inner.to_val(keys))),
+            ArenaVal::StringLiteral(s) => Val::StringLiteral(s),
+            ArenaVal::Date(date) => Val::Date(date),
+            ArenaVal::Decimal(n) => Val::Decimal(Number::new(n)),
+            ArenaVal::Integer(n) => Val::Integer(Number::new(n)),
+            ArenaVal::Boolean(b) => Val::Boolean(b),
+            ArenaVal::Identifier(s) => Val::Identifier(s),
+        }
+    }
+}
+
+/// Parses a document into `arena`, interning its keys in `keys`. Gives the same values as
+/// [`super::root::root_with_options`].
+pub fn root_arena<'b>(
+    input: &'b str,
+    options: &ParseOptions,
+    arena: &'b Bump,
+    keys: &mut Interner<'b>,
+) -> Res<&'b str, ArenaVal<'b>> {
+    ArenaBuilder {
+        builder: Builder::new(input, options),
+        arena,
+        keys,
+        elements: vec![],
+    }
+    .root()
+}
+
+/// Walks the input like [`Builder`]. Elements are gathered on a stack shared by every level and
+/// copied into the arena when their block closes, so each container is one exact slice.
+struct ArenaBuilder<'b, 'o> {
+    builder: Builder<'b, 'o>,
+    arena: &'b Bump,
+    keys: &'o mut Interner<'b>,
+    elements: Vec<(Option<&'b str>, ArenaVal<'b>)>,
+}
+
+impl<'b> ArenaBuilder<'b, '_> {
+    fn root(mut self) -> Res<&'b str, ArenaVal<'b>> {
+        let (input, options) = (self.builder.input, self.builder.options);
+        let mut entries = vec![];
+        let mut end = 0;
+        loop {
+            let start = match entries.is_empty() {
+                true => end,
+                false => {
+                    let start = self.builder.lexer.skip_space(end);
+                    if start == end {
+                        break;
+                    }
+                    start
+                }
+            };
+            match self.entry(start) {
+                Ok((next, entry)) => {
+                    entries.push(entry);
+                    end = next;
+                }
+                Err(Decline) => match key_value(&input[start..], options) {
+                    Ok((rest, (key, val))) => {
+                        entries.push((self.keys.intern(key), self.lay_out(val)));
+                        end = input.len() - rest.len();
+                    }
+                    Err(nom::Err::Error(_)) => break,
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        let entries = self.arena.alloc_slice_copy(&entries);
+        Ok((&input[end..], ArenaVal::Dict(entries)))
+    }
+
+    /// Copies a value the combinators or the builder parsed into the arena.
+    fn lay_out(&mut self, val: Val<'b>) -> ArenaVal<'b> {
+        let arena = self.arena;
+        match val {
+            Val::Dict(entries) => ArenaVal::Dict(self.lay_out_entries(entries)),
+            Val::NumberedDict(number, entries) => {
+                let entries = self.lay_out_entries(entries);
+                ArenaVal::NumberedDict(arena.alloc((number, entries)))
+            }
+            Val::Array(pairs) => ArenaVal::Array(
+                arena.alloc_slice_fill_iter(
+                    pairs
+                        .into_iter()
+                        .map(|(index, val)| (index, self.lay_out(val))),
+                ),
+            ),
+            Val::Set(vals) => ArenaVal::Set(
+                arena.alloc_slice_fill_iter(vals.into_iter().map(|val| self.lay_out(val))),
+            ),
+            Val::Mixed(entries) => {
+                ArenaVal::Mixed(arena.alloc_slice_fill_iter(
+                    entries.into_iter().map(|(key, val)| {
+                        (key.map(|key| self.keys.intern(key)), self.lay_out(val))
+                    }),
+                ))
+            }
+            Val::Tagged(tag, val) => {
+                let val = self.lay_out(*val);
+                ArenaVal::Tagged(arena.alloc((tag, val)))
+            }
+            Val::StringLiteral(s) => ArenaVal::StringLiteral(s),
+            Val::Date(date) => ArenaVal::Date(date),
+            Val::Decimal(n) => ArenaVal::Decimal(arena.alloc_str(n.as_str())),
+            Val::Integer(n) => ArenaVal::Integer(arena.alloc_str(n.as_str())),
+            Val::Boolean(b) => ArenaVal::Boolean(b),
+            Val::Identifier(s) => ArenaVal::Identifier(s),
+        }
+    }
+
+    fn lay_out_entries(
+        &mut self,
+        entries: Vec<(&'b str, Val<'b>)>,
+    ) -> &'b [(Symbol, ArenaVal<'b>)] {
+        let arena = self.arena;
+        arena.alloc_slice_fill_iter(
+            entries
+                .into_iter()
+                .map(|(key, val)| (self.keys.intern(key), self.lay_out(val))),
+        )
+    }
+
+    #[inline(always)]
+    fn entry(&mut self, at: usize) -> Built<(usize, (Symbol, ArenaVal<'b>))> {
+        let (key, at) = self.builder.key(at)?;
+        let (end, val) = self.value(at)?;
+        Ok((end, (self.keys.intern(key), val)))
+    }
+
+    /// A value, read like [`Builder`] reads one.
+    #[inline(always)]
+    fn value(&mut self, at: usize) -> Built<(usize, ArenaVal<'b>)> {
+        let token = self.builder.token(at)?;
+        self.token_value(token)
+    }
+
+    #[inline(always)]
+    fn token_value(&mut self, token: Token<'b>) -> Built<(usize, ArenaVal<'b>)> {
+        let at = token.offset;
+        match token.kind {
+            TokenKind::Open => return self.block(at),
+            TokenKind::String => {
+                if let Ok(val) = self.builder.quoted(token.text) {
+                    return Ok((token.end, self.lay_out(val)));
+                }
+            }
+            TokenKind::Close | TokenKind::Equals => return Err(Decline),
+            TokenKind::Other => {}
+            _ => match self.builder.tag(&token) {
+                Ok(Some(block)) => {
+                    let (end, val) = self.block(block)?;
+                    return Ok((end, ArenaVal::Tagged(self.arena.alloc((token.text, val)))));
+                }
+                Ok(None) => match token.kind {
+                    TokenKind::Integer => return Ok((token.end, ArenaVal::Integer(token.text))),
+                    TokenKind::Decimal => return Ok((token.end, ArenaVal::Decimal(token.text))),
+                    _ => {
+                        if let Ok(val) = self.builder.scalar(&token) {
+                            return Ok((token.end, self.lay_out(val)));
+                        }
+                    }
+                },
+                Err(Decline) => {}
+            },
+        }
+        let (end, val) = self.builder.combinators(at, value)?;
+        Ok((end, self.lay_out(val)))
+    }
+
+    /// A pair or a bare value, pushed as an element of the block being read.
+    #[inline(always)]
+    fn mixed_entry(&mut self, at: usize) -> Built<usize> {
+        let token = self.builder.token(at)?;
+        let (end, element) = match self.builder.pair(&token) {
+            Some((key, at)) => {
+                let (end, val) = self.value(at)?;
+                (end, (Some(key), val))
+            }
+            None if token.kind == TokenKind::Other => {
+                let (end, (key, val)) = self.builder.combinators(at, mixed_entry)?;
+                (end, (key, self.lay_out(val)))
+            }
+            None => {
+                let (end, val) = self.token_value(token)?;
+                (end, (None, val))
+            }
+        };
+        self.elements.push(element);
+        Ok(end)
+    }
+
+    /// The block opening at `at`, read as a mixed block and then narrowed to the container its
+    /// first token suggests if every entry fits it.
+    fn block(&mut self, at: usize) -> Built<(usize, ArenaVal<'b>)> {
+        let body = self.builder.lexer.skip_space(at + 1);
+        let shape = shape(&self.builder.input[at + 1..]);
+        let mark = self.elements.len();
+        let numbered = match shape {
+            Shape::NumberedDict => self.builder.numbered(body).ok(),
+            _ => None,
+        };
+        let read = match numbered {
+            Some((number, inner)) => self.numbered_dict(mark, body, number, inner),
+            None => self
+                .list(body, true)
+                .map(|close| (close, self.narrow(mark, shape))),
+        };
+        let (close, val) = read.inspect_err(|_| self.elements.truncate(mark))?;
+        Ok((self.builder.expect(close, b'}')?, val))
+    }
+
+    /// The body of `{ 14 { key=value } }`, or a mixed block if there is more to it or the inner
+    /// block is not all pairs.
+    fn numbered_dict(
+        &mut self,
+        mark: usize,
+        body: usize,
+        number: i64,
+        inner: usize,
+    ) -> Built<(usize, ArenaVal<'b>)> {
+        let inner_close = self.list(inner, true)?;
+        let close = self.builder.lexer.skip_space(inner_close + 1);
+        let elements = &self.elements[mark..];
+        if self.builder.lexer.byte(close) == Some(b'}') && elements.iter().all(|e| e.0.is_some()) {
+            let keys = &mut *self.keys;
+            let entries: &'b [_] = self.arena.alloc_slice_fill_iter(
+                elements
+                    .iter()
+                    .map(|(key, val)| (keys.intern(key.unwrap_or_default()), *val)),
+            );
+            self.elements.truncate(mark);
+            return Ok((
+                close,
+                ArenaVal::NumberedDict(self.arena.alloc((number, entries))),
+            ));
+        }
+        let number = ArenaVal::Integer(self.builder.token(body)?.text);
+        let block = self.narrow(mark, shape(&self.builder.input[inner..]));
+        self.elements.extend([(None, number), (None, block)]);
+        let close = self.list(inner_close + 1, false)?;
+        Ok((close, self.narrow(mark, Shape::NumberedDict)))
+    }
+
+    /// Copies the elements above `mark` into the arena as the container they fit.
+    fn narrow(&mut self, mark: usize, shape: Shape) -> ArenaVal<'b> {
+        let (arena, keys) = (self.arena, &mut *self.keys);
+        let elements = &self.elements[mark..];
+        let mut fits = Fits::new(shape);
+        for (key, val) in elements {
+            fits.add(*key, val.is_block());
+        }
+        let val = match fits.container(self.builder.options) {
+            Container::Dict => ArenaVal::Dict(
+                arena.alloc_slice_fill_iter(
+                    elements
+                        .iter()
+                        .map(|(key, val)| (keys.intern(key.unwrap_or_default()), *val)),
+                ),
+            ),
+            Container::Array => {
+                let pairs = arena.alloc_slice_fill_iter(
+                    elements
+                        .iter()
+                        .map(|(key, val)| (key.and_then(index).unwrap_or_default(), *val)),
+                );
+                if self.builder.options.sort_arrays {
+                    pairs.sort_by_key(|(index, _)| *index);
+                }
+                ArenaVal::Array(pairs)
+            }
+            Container::Set => {
+                ArenaVal::Set(arena.alloc_slice_fill_iter(elements.iter().map(|(_, val)| *val)))
+            }
+            Container::Mixed => ArenaVal::Mixed(
+                arena.alloc_slice_fill_iter(
+                    elements
+                        .iter()
+                        .map(|(key, val)| (key.map(|key| keys.intern(key)), *val)),
+                ),
+            ),
+        };
+        self.elements.truncate(mark);
+        val
+    }
+
+    /// Elements separated by whitespace up to the closing brace, whose position is returned. The
+    /// elements are pushed on the stack; `first` is whether none was pushed before `from`.
+    #[inline(always)]
+    fn list(&mut self, from: usize, mut first: bool) -> Built<usize> {
+        let mut end = from;
+        loop {
+            let start = match first {
+                true => end,
+                false => {
+                    let start = self.builder.lexer.skip_space(end);
+                    if start == end {
+                        break;
+                    }
+                    start
+                }
+            };
+            if matches!(self.builder.lexer.byte(start), None | Some(b'}')) {
+                break;
+            }
+            end = self.mixed_entry(start)?;
+            first = false;
+        }
+        let close = self.builder.lexer.skip_space(end);
+        self.builder.expect(close, b'}')?;
+        Ok(close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clausewitz::root::root_with_options;
+
+    const TEXT: &str = r###"version="v3.4.5"
+country={
+	0={
+		name="Earth" flags={ a b } color=rgb { 1 2 3 }
+		budget={ income=10.5 debt=-2 }
+		numbered={ 14 { key=value } }
+		sets={ { a=1 } { b=2 } } mixed={ 1 2 key=value }
+	}
+	1={ name="Mars" started=2200.01.01 }
+}
+array={ 1=one 0=zero }
+"###;
+
+    #[test]
+    fn root_arena__document__same_as_val() {
+        for options in [ParseOptions::default(), ParseOptions::raw()] {
+            let arena = Bump::new();
+            let mut keys = Interner::new();
+            let (rest, val) = root_arena(TEXT, &options, &arena, &mut keys).unwrap();
+            let (val_rest, expected) = root_with_options(TEXT, &options).unwrap();
+            assert_eq!(rest, val_rest);
+            assert_eq!(val.to_val(&keys), expected);
+        }
+    }
+
+    #[test]
+    fn get_at_path__interned_keys__found() {
+        let arena = Bump::new();
+        let mut keys = Interner::new();
+        let (_, val) = root_arena(TEXT, &ParseOptions::default(), &arena, &mut keys).unwrap();
+
+        assert_eq!(
+            val.get_at_path(&keys, "country.1.name"),
+            Ok(&ArenaVal::StringLiteral("Mars"))
+        );
+        assert_eq!(
+            val.get_at_path(&keys, "country.0.numbered.key"),
+            Ok(&ArenaVal::Identifier("value"))
+        );
+        assert_eq!(
+            val.get_at_path(&keys, "country.0.color.1"),
+            Err(IndexError {
+                err: "Cannot index a set with index 1".to_owned()
+            })
+        );
+        assert_eq!(
+            val.get_at_path(&keys, "array.1"),
+            Ok(&ArenaVal::Identifier("one"))
+        );
+        assert!(val.get_at_path(&keys, "country.0.unknown").is_err());
+    }
+
+    #[test]
+    fn root_arena__two_documents__share_symbols() {
+        let arena = Bump::new();
+        let mut keys = Interner::new();
+        let (_, first) =
+            root_arena("owner=1", &ParseOptions::default(), &arena, &mut keys).unwrap();
+        let (_, second) =
+            root_arena("owner=2", &ParseOptions::default(), &arena, &mut keys).unwrap();
+
+        assert_eq!(keys.len(), 1);
+        let owner = keys.get("owner").unwrap();
+        assert_eq!(first.get_symbol(owner), Some(&ArenaVal::Integer("1")));
+        assert_eq!(second.get_symbol(owner), Some(&ArenaVal::Integer("2")));
+    }
+}
@@ -1,29 +1,33 @@
 use nom::{
     branch::alt,
-    bytes::complete::take,
-    character::complete::{char, digit1},
-    combinator::{cut, map, map_res, recognize, verify},
+    character::complete::char,
+    combinator::{cut, map, verify},
     error::ParseError,
-    multi::separated_list0,
-    sequence::{delimited, pair, preceded, separated_pair, tuple},
+    sequence::{delimited, preceded, separated_pair},
     IResult, Parser,
 };
 
 use super::{
+    builder::{index, Container, Fits},
+    options::ParseOptions,
     quoted::string_literal_contents,
-    simd::{take_simd_identifier, take_simd_not_token},
+    shape::{shape, Shape},
+    simd::take_simd_identifier,
     space::{opt_space, req_space},
-    unquoted::integer,
+    tables::is_digit,
     val::Val,
     value::value,
     Res,
 };
 
 #[inline(always)]
-pub fn unquoted_key<'a>(input: &'a str) -> Res<&'a str, &'a str> {
-    verify(take_simd_identifier, |s: &str| {
-        !s.is_empty() //&& !(is_digit(s.chars().next().unwrap()))
-    })(input)
+pub fn unquoted_key<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, &'a str> {
+    verify(
+        |i| take_simd_identifier(i, options.dialect),
+        |s: &str| {
+            !s.is_empty() //&& !(is_digit(s.chars().next().unwrap()))
+        },
+    )(input)
 }
 
 #[inline(always)]
@@ -32,68 +36,45 @@ pub fn quoted_key<'a>(input: &'a str) -> Res<&'a str, &'a str> {
 }
 
 #[inline(always)]
-pub fn key<'a>(input: &'a str) -> Res<&'a str, &'a str> {
-    alt((unquoted_key, quoted_key))(input)
+pub fn key<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, &'a str> {
+    alt((|i| unquoted_key(i, options), quoted_key))(input)
 }
 
 #[inline(always)]
-pub fn key_value<'a>(input: &'a str) -> Res<&'a str, (&'a str, Val<'a>)> {
+pub fn key_value<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, (&'a str, Val<'a>)> {
     separated_pair(
-        preceded(opt_space, key),
+        preceded(opt_space, |i| key(i, options)),
         cut(preceded(opt_space, char('='))),
-        preceded(opt_space, value),
+        preceded(opt_space, |i| value(i, options)),
     )(input)
 }
 
-#[inline(always)]
-pub fn hash_map<'a>(input: &'a str) -> Res<&'a str, Vec<(&'a str, Val<'a>)>> {
-    separated_list0(req_space, key_value)(input)
-}
-
-#[inline(always)]
-pub fn dict<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(hash_map, Val::Dict)(input)
+/// A whole document as the combinators read it, which the builders are checked against.
+#[cfg(test)]
+pub fn hash_map<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> Res<&'a str, Vec<(&'a str, Val<'a>)>> {
+    nom::multi::separated_list0(req_space, |i| key_value(i, options))(input)
 }
 
 #[inline(always)]
-pub fn number_value<'a>(input: &'a str) -> Res<&'a str, (u64, Val<'a>)> {
-    separated_pair(
-        preceded(
-            opt_space,
-            map_res(
-                verify(recognize(digit1), |s: &str| !s.is_empty()),
-                str::parse,
-            ),
-        ),
-        cut(preceded(opt_space, char('='))),
-        preceded(opt_space, value),
+pub fn tag<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, &'a str> {
+    verify(
+        |i| take_simd_identifier(i, options.dialect),
+        |s: &str| !s.is_empty() && !(is_digit(s.chars().next().unwrap())),
     )(input)
 }
 
+/// A block preceded by an identifier, like `rgb { 255 0 0 }` or `LIST { a b }`.
 #[inline(always)]
-pub fn array<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
+pub fn tagged<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
     map(
-        separated_list0(req_space, number_value),
-        |mut number_value_pairs| {
-            number_value_pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
-            Val::Array(number_value_pairs)
-        },
+        separated_pair(|i| tag(i, options), opt_space, |i| bracketed(i, options)),
+        |(tag, val)| Val::Tagged(tag, Box::new(val)),
     )(input)
 }
 
-#[inline(always)]
-pub fn set<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    alt((
-        map(separated_list0(req_space, value), |s: Vec<Val>| Val::Set(s)),
-        map(opt_space, |_s: &str| Val::Set(vec![])),
-    ))(input)
-}
-
-#[inline(always)]
-pub fn set_of_collections<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(separated_list0(req_space, bracketed), |vals| Val::Set(vals))(input)
-}
-
 #[inline(always)]
 pub fn triple<I, O1, O2, O3, E: ParseError<I>, F, G, H>(
     mut first: F,
@@ -111,57 +92,197 @@ where
         third.parse(input).map(|(i, o3)| (i, (o1, o2, o3)))
     }
 }
+/// Parses a block's contents once, as a mixed container, and then narrows it to the container
+/// its first token suggested if every entry fits, so that no block is ever parsed twice.
 #[inline(always)]
-pub fn contents<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    let (_remainder, (maybe_key_number_identifier, next_token)) =
-        pair(take_simd_not_token, take(1 as usize))(input)?;
-
-    match next_token {
-        "}" => cut(set)(input),
-        _ => {
-            match (
-                next_token,
-                take_simd_identifier(maybe_key_number_identifier)
-                    .map(|s| s.1.parse::<i64>().is_ok())
-                    .unwrap_or(false),
-            ) {
-                ("=", true) => cut(array)(input),
-                ("=", false) => cut(dict)(input),
-                ("{", true) => cut(numbered_dict)(input),
-                ("{", false) => cut(set_of_collections)(input),
-                (_, _) => {
-                    panic!()
-                }
+pub fn contents<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    let (remainder, (shape, entries)) = entries(input, options)?;
+    let val = match closes_block(remainder) {
+        true => narrow(shape, entries, options),
+        false => Val::Mixed(entries.into_iter().map(|e| e.into_pair(options)).collect()),
+    };
+    Ok((remainder, val))
+}
+
+/// One entry of a block, before the block is narrowed to a container.
+struct Entry<'a> {
+    key: Option<&'a str>,
+    item: Item<'a>,
+    /// Whether the value was a block, as in `{ { .. } { .. } }`.
+    braced: bool,
+}
+
+enum Item<'a> {
+    Val(Val<'a>),
+    /// The block of `14 { key=value }`, kept as entries until it is known whether its pairs are
+    /// those of a numbered dict.
+    Block(Shape, Vec<Entry<'a>>),
+}
+
+impl<'a> Entry<'a> {
+    fn into_pair(self, options: &ParseOptions) -> (Option<&'a str>, Val<'a>) {
+        (self.key, self.item.into_val(options))
+    }
+}
+
+impl<'a> Item<'a> {
+    fn into_val(self, options: &ParseOptions) -> Val<'a> {
+        match self {
+            Item::Val(val) => val,
+            Item::Block(shape, entries) => narrow(shape, entries, options),
+        }
+    }
+}
+
+/// The entries of a block as `separated_list0(req_space, mixed_entry)` finds them, with the block
+/// of a numbered dict kept as entries.
+fn entries<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, (Shape, Vec<Entry<'a>>)> {
+    let shape = shape(input);
+    let mut entries = vec![];
+    if shape == Shape::Empty {
+        return Ok((input, (shape, entries)));
+    }
+    let mut rest = input;
+    loop {
+        let at = match entries.is_empty() {
+            true => rest,
+            false => match req_space(rest) {
+                Ok((at, _)) => at,
+                Err(nom::Err::Error(_)) => break,
+                Err(e) => return Err(e),
+            },
+        };
+        let block = shape == Shape::NumberedDict && entries.len() == 1;
+        match entry(at, options, block) {
+            Ok((after, entry)) => {
+                entries.push(entry);
+                rest = after;
             }
+            Err(nom::Err::Error(_)) => break,
+            Err(e) => return Err(e),
         }
     }
+    Ok((rest, (shape, entries)))
 }
 
 #[inline(always)]
-pub fn bracketed<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    delimited(
-        char('{'),
-        cut(delimited(opt_space, contents, opt_space)),
-        char('}'),
-    )(input)
+fn entry<'a>(input: &'a str, options: &ParseOptions, block: bool) -> Res<&'a str, Entry<'a>> {
+    let (input, _) = opt_space(input)?;
+    let braced = input.starts_with('{');
+    if block && braced {
+        let (rest, (shape, entries)) = delimited(
+            char('{'),
+            cut(delimited(opt_space, |i| entries(i, options), opt_space)),
+            char('}'),
+        )(input)?;
+        let item = Item::Block(shape, entries);
+        return Ok((
+            rest,
+            Entry {
+                key: None,
+                item,
+                braced,
+            },
+        ));
+    }
+    let (rest, (key, val)) = mixed_entry(input, options)?;
+    let item = Item::Val(val);
+    Ok((rest, Entry { key, item, braced }))
+}
+
+/// The container `shape` stands for if the entries fit it, and otherwise a mixed one.
+fn narrow<'a>(shape: Shape, mut entries: Vec<Entry<'a>>, options: &ParseOptions) -> Val<'a> {
+    if shape == Shape::NumberedDict && entries.len() == 2 {
+        let number = match &entries[0] {
+            Entry {
+                key: None,
+                item: Item::Val(Val::Integer(n)),
+                ..
+            } if n.as_str().bytes().all(|b| b.is_ascii_digit()) => n.as_str().parse().ok(),
+            _ => None,
+        };
+        if let (Some(number), Item::Block(_, pairs)) = (number, &entries[1].item) {
+            if pairs.iter().all(|e| e.key.is_some()) {
+                let Some(Entry {
+                    item: Item::Block(_, pairs),
+                    ..
+                }) = entries.pop()
+                else {
+                    unreachable!("the block was matched above")
+                };
+                return Val::NumberedDict(number, self::pairs(pairs, options));
+            }
+        }
+    }
+    let mut fits = Fits::new(shape);
+    for entry in &entries {
+        fits.add(entry.key, entry.braced);
+    }
+    match fits.container(options) {
+        Container::Dict => Val::Dict(pairs(entries, options)),
+        Container::Array => {
+            let mut pairs: Vec<_> = entries
+                .into_iter()
+                .map(|e| {
+                    let index = e.key.and_then(index).unwrap_or_default();
+                    (index, e.item.into_val(options))
+                })
+                .collect();
+            if options.sort_arrays {
+                pairs.sort_by_key(|(index, _)| *index);
+            }
+            Val::Array(pairs)
+        }
+        Container::Set => Val::Set(
+            entries
+                .into_iter()
+                .map(|e| e.item.into_val(options))
+                .collect(),
+        ),
+        Container::Mixed => Val::Mixed(entries.into_iter().map(|e| e.into_pair(options)).collect()),
+    }
+}
+
+fn pairs<'a>(entries: Vec<Entry<'a>>, options: &ParseOptions) -> Vec<(&'a str, Val<'a>)> {
+    entries
+        .into_iter()
+        .map(|e| (e.key.unwrap_or_default(), e.item.into_val(options)))
+        .collect()
 }
 
 #[inline(always)]
-pub fn numbered_dict<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(
-        tuple((
-            map_res(
-                verify(recognize(digit1), |s: &str| !s.is_empty()),
-                str::parse,
-            ),
-            req_space,
-            delimited(
-                char('{'),
-                delimited(opt_space, hash_map, opt_space),
-                char('}'),
+fn closes_block(input: &str) -> bool {
+    opt_space(input)
+        .map(|(remainder, _)| remainder.starts_with('}'))
+        .unwrap_or(false)
+}
+
+#[inline(always)]
+pub fn mixed_entry<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> Res<&'a str, (Option<&'a str>, Val<'a>)> {
+    alt((
+        map(
+            separated_pair(
+                preceded(opt_space, |i| key(i, options)),
+                preceded(opt_space, char('=')),
+                preceded(opt_space, |i| value(i, options)),
             ),
-        )),
-        |(number, _, map): (i64, &str, Vec<(&'a str, Val<'a>)>)| Val::NumberedDict(number, map),
+            |(key, val)| (Some(key), val),
+        ),
+        map(preceded(opt_space, |i| value(i, options)), |val| {
+            (None, val)
+        }),
+    ))(input)
+}
+
+#[inline(always)]
+pub fn bracketed<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    delimited(
+        char('{'),
+        cut(delimited(opt_space, |i| contents(i, options), opt_space)),
+        char('}'),
     )(input)
 }
 
@@ -169,13 +290,14 @@ pub fn numbered_dict<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
 mod tests {
     use super::*;
     use crate::clausewitz::tests::helper::assert_result_ok;
+    use crate::Number;
     #[test]
     fn bracketed__dict__dict() {
         let text = r###"{
 			first="first"
 			second="second"
 	}"###;
-        let result = bracketed(text);
+        let result = bracketed(text, &ParseOptions::default());
         assert_result_ok(result)
     }
 
@@ -185,7 +307,7 @@ mod tests {
 		0="first"
 		1="second"
 	}"###;
-        let result = bracketed(text);
+        let result = bracketed(text, &ParseOptions::default());
         assert_result_ok(result)
     }
 
@@ -195,36 +317,160 @@ mod tests {
 		"first"
 		"second"
 	}"###;
-        let result = bracketed(text);
+        let result = bracketed(text, &ParseOptions::default());
+        assert_result_ok(result)
+    }
+    #[test]
+    fn bracketed__set_of_tagged_blocks__set() {
+        let text = r###"{
+		rgb { 255 0 0 }
+		hsv{ 0.5 0.5 1.0 }
+	}"###;
+        let result = bracketed(text, &ParseOptions::default());
+        assert_result_ok(result)
+    }
+
+    #[test]
+    fn bracketed__values_then_pairs__mixed() {
+        let text = r###"{ 1 2 key=value }"###;
+        let (remainder, val) = bracketed(text, &ParseOptions::default()).unwrap();
+        assert!(remainder.is_empty());
+        assert_eq!(
+            val,
+            Val::Mixed(vec![
+                (None, Val::Integer(Number::new("1"))),
+                (None, Val::Integer(Number::new("2"))),
+                (Some("key"), Val::Identifier("value")),
+            ])
+        );
+    }
+
+    #[test]
+    fn bracketed__numbered_then_named_keys__mixed() {
+        let text = r###"{ 0=a name=b }"###;
+        let (remainder, val) = bracketed(text, &ParseOptions::default()).unwrap();
+        assert!(remainder.is_empty());
+        assert_eq!(
+            val,
+            Val::Mixed(vec![
+                (Some("0"), Val::Identifier("a")),
+                (Some("name"), Val::Identifier("b")),
+            ])
+        );
+    }
+
+    #[test]
+    fn bracketed__pairs_then_values__mixed() {
+        let text = r###"{
+            name="a"
+            "loose string"
+            { nested=yes }
+            rgb { 1 2 3 }
+        }"###;
+        let result = bracketed(text, &ParseOptions::default());
         assert_result_ok(result)
     }
+
+    #[test]
+    fn bracketed__unclosed_mixed__rejected() {
+        let text = r###"{ 1 2 key=value "###;
+        let result = bracketed(text, &ParseOptions::default());
+        assert!(result.is_err())
+    }
+
+    #[test]
+    fn bracketed__deeply_nested_mixed__linear_time() {
+        let depth = 40;
+        let mut text = String::new();
+        for i in 0..depth {
+            text.push_str(&format!("{{ a=1 k{}=", i));
+        }
+        text.push_str("{ x=y 5 }");
+        text.push_str(&" 5 }".repeat(depth));
+        let malformed = text.replacen("x=y 5", "x=y 5 =", 1);
+
+        let start = std::time::Instant::now();
+        let (remainder, val) = bracketed(&text, &ParseOptions::default()).unwrap();
+        assert!(bracketed(&malformed, &ParseOptions::default()).is_err());
+
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert!(remainder.is_empty());
+        assert!(matches!(val, Val::Mixed(_)));
+    }
+
+    #[test]
+    fn bracketed__numbered_dict__numbered_dict() {
+        let text = r###"{ 14 { key=value } }"###;
+        let (_, val) = bracketed(text, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            val,
+            Val::NumberedDict(14, vec![("key", Val::Identifier("value"))])
+        );
+
+        let text = r###"{ 14 { value } }"###;
+        let (_, val) = bracketed(text, &ParseOptions::default()).unwrap();
+        assert!(matches!(val, Val::Mixed(_)));
+    }
+
+    #[test]
+    fn tagged__rgb__tagged_set() {
+        let text = r###"rgb { 255 0 0 }"###;
+        let (remainder, val) = tagged(text, &ParseOptions::default()).unwrap();
+        assert!(remainder.is_empty());
+        assert_eq!(
+            val,
+            Val::Tagged(
+                "rgb",
+                Box::new(Val::Set(vec![
+                    Val::Integer(Number::new("255")),
+                    Val::Integer(Number::new("0")),
+                    Val::Integer(Number::new("0")),
+                ]))
+            )
+        );
+    }
+
+    #[test]
+    fn tagged__number__rejected() {
+        let text = r###"14 { intel=0 }"###;
+        assert!(tagged(text, &ParseOptions::default()).is_err());
+    }
+
     #[cfg(test)]
     mod key_value {
-        use crate::clausewitz::{bracketed::key_value, tests::helper::assert_result_ok};
+        use crate::clausewitz::{
+            bracketed::key_value, options::ParseOptions, tests::helper::assert_result_ok,
+        };
 
         #[test]
         fn key_value__unquoted__accepted() {
             let text = r###"key.0="value""###;
-            let result = key_value(text);
+            let result = key_value(text, &ParseOptions::default());
             assert_result_ok(result)
         }
 
         #[test]
         fn key_value__quoted__accepted() {
             let text = r###""key.0"=0"###;
-            let result = key_value(text);
+            let result = key_value(text, &ParseOptions::default());
             assert_result_ok(result)
         }
         #[test]
         fn key_value__begins_with_number_quoted__accepted() {
             let text = r###""0_key.0"=0"###;
-            let result = key_value(text);
+            let result = key_value(text, &ParseOptions::default());
+            assert_result_ok(result)
+        }
+        #[test]
+        fn key_value__tagged_value__accepted() {
+            let text = r###"color = rgb { 255 0 0 }"###;
+            let result = key_value(text, &ParseOptions::default());
             assert_result_ok(result)
         }
         #[test]
         fn key_value__begins_with_number_unquoted__accepted() {
             let text = r###"0_key.0=0"###;
-            let result = key_value(text);
+            let result = key_value(text, &ParseOptions::default());
             assert_result_ok(result)
         }
     }
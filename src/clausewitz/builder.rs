@@ -0,0 +1,538 @@
+use super::{
+    bracketed::{key_value, mixed_entry},
+    date::ClausewitzDate,
+    lexer::{is_date, Lexer, Token, TokenKind},
+    number::Number,
+    options::{EmptyBlock, InvalidDates, ParseOptions},
+    shape::{shape, Shape},
+    val::Val,
+    value::value,
+    Res,
+};
+
+/// Something the builder does not handle the way the combinators do, like a token it cannot
+/// classify or a malformed block. A token the builder declines is parsed by the combinators in its
+/// place, and a top level entry which still does not parse is parsed again by the combinators, so
+/// that the error is theirs.
+pub(super) struct Decline;
+
+pub(super) type Built<T> = Result<T, Decline>;
+
+/// Builds the same tree as [`super::root::root_with_options`] from one scan of classified tokens,
+/// instead of trying each scalar parser in turn.
+pub fn root<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    Builder::new(input, options).root()
+}
+
+pub(super) struct Builder<'a, 'o> {
+    pub(super) lexer: Lexer<'a>,
+    pub(super) input: &'a str,
+    pub(super) options: &'o ParseOptions,
+}
+
+impl<'a, 'o> Builder<'a, 'o> {
+    pub(super) fn new(input: &'a str, options: &'o ParseOptions) -> Self {
+        Builder {
+            lexer: Lexer::new(input, options.dialect),
+            input,
+            options,
+        }
+    }
+
+    /// Pairs separated by whitespace, stopping at the first thing which is not a pair.
+    fn root(&self) -> Res<&'a str, Val<'a>> {
+        let mut entries = vec![];
+        let mut end = 0;
+        loop {
+            let start = match entries.is_empty() {
+                true => end,
+                false => {
+                    let start = self.lexer.skip_space(end);
+                    if start == end {
+                        break;
+                    }
+                    start
+                }
+            };
+            match self.entry(start) {
+                Ok((next, key, val)) => {
+                    entries.push((key, val));
+                    end = next;
+                }
+                Err(Decline) => match key_value(&self.input[start..], self.options) {
+                    Ok((rest, entry)) => {
+                        entries.push(entry);
+                        end = self.input.len() - rest.len();
+                    }
+                    Err(nom::Err::Error(_)) => break,
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        Ok((&self.input[end..], Val::Dict(entries)))
+    }
+
+    #[inline(always)]
+    pub(super) fn token(&self, at: usize) -> Built<Token<'a>> {
+        match at < self.input.len() {
+            true => Ok(self.lexer.token_at(at)),
+            false => Err(Decline),
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn expect(&self, at: usize, byte: u8) -> Built<usize> {
+        match self.lexer.byte(at) == Some(byte) {
+            true => Ok(at + 1),
+            false => Err(Decline),
+        }
+    }
+
+    /// The key of the pair at `at` and where its value starts.
+    #[inline(always)]
+    pub(super) fn key(&self, at: usize) -> Built<(&'a str, usize)> {
+        let token = self.token(self.lexer.skip_space(at))?;
+        self.pair(&token).ok_or(Decline)
+    }
+
+    /// The key and where the value starts, if `token` is the key of a pair.
+    #[inline(always)]
+    pub(super) fn pair(&self, token: &Token<'a>) -> Option<(&'a str, usize)> {
+        let key = match token.kind {
+            TokenKind::String => token.text,
+            TokenKind::Open | TokenKind::Close | TokenKind::Equals | TokenKind::Other => {
+                return None
+            }
+            _ if token.key => token.text,
+            _ => return None,
+        };
+        let equals = self.lexer.skip_space(token.end);
+        match self.lexer.byte(equals) == Some(b'=') {
+            true => Some((key, self.lexer.skip_space(equals + 1))),
+            false => None,
+        }
+    }
+
+    /// Where the block starts if `token` is a tag like `rgb` in `rgb { 1 2 3 }`, which is tried
+    /// before reading the token as a scalar.
+    #[inline(always)]
+    pub(super) fn tag(&self, token: &Token<'a>) -> Built<Option<usize>> {
+        if token.text.starts_with("@[") {
+            return Err(Decline);
+        }
+        if token.key && !token.text.as_bytes()[0].is_ascii_digit() {
+            let block = self.lexer.skip_space(token.end);
+            if self.lexer.byte(block) == Some(b'{') {
+                return Ok(Some(block));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The number of a numbered dict whose body starts at `body`, and where its pairs start.
+    #[inline(always)]
+    pub(super) fn numbered(&self, body: usize) -> Built<(i64, usize)> {
+        let number = self.token(body)?;
+        if number.kind != TokenKind::Integer || number.text.starts_with('-') {
+            return Err(Decline);
+        }
+        let brace = self.lexer.skip_space(number.end);
+        if brace == number.end {
+            return Err(Decline);
+        }
+        let number = number.text.parse().map_err(|_| Decline)?;
+        Ok((number, self.lexer.skip_space(self.expect(brace, b'{')?)))
+    }
+
+    #[inline(always)]
+    fn entry(&self, at: usize) -> Built<(usize, &'a str, Val<'a>)> {
+        let (key, at) = self.key(at)?;
+        let (end, val) = self.value(at)?;
+        Ok((end, key, val))
+    }
+
+    /// A value. A token the builder declines is parsed by the combinators, but a block is not: a
+    /// block the builder declines does not parse with the combinators either.
+    #[inline(always)]
+    fn value(&self, at: usize) -> Built<(usize, Val<'a>)> {
+        self.token_value(self.token(at)?)
+    }
+
+    #[inline(always)]
+    fn token_value(&self, token: Token<'a>) -> Built<(usize, Val<'a>)> {
+        let at = token.offset;
+        match token.kind {
+            TokenKind::Open => return self.block(at),
+            TokenKind::String => {
+                if let Ok(val) = self.quoted(token.text) {
+                    return Ok((token.end, val));
+                }
+            }
+            TokenKind::Close | TokenKind::Equals => return Err(Decline),
+            TokenKind::Other => {}
+            _ => match self.tag(&token) {
+                Ok(Some(block)) => {
+                    let (end, val) = self.block(block)?;
+                    return Ok((end, Val::Tagged(token.text, Box::new(val))));
+                }
+                Ok(None) => {
+                    if let Ok(val) = self.scalar(&token) {
+                        return Ok((token.end, val));
+                    }
+                }
+                Err(Decline) => {}
+            },
+        }
+        self.combinators(at, value)
+    }
+
+    /// A pair or a bare value, as an element of a block.
+    #[inline(always)]
+    fn mixed_entry(&self, at: usize) -> Built<(usize, (Option<&'a str>, Val<'a>))> {
+        let token = self.token(at)?;
+        if let Some((key, at)) = self.pair(&token) {
+            let (end, val) = self.value(at)?;
+            return Ok((end, (Some(key), val)));
+        }
+        match token.kind {
+            TokenKind::Other => self.combinators(at, mixed_entry),
+            _ => self.token_value(token).map(|(end, val)| (end, (None, val))),
+        }
+    }
+
+    /// Parses what the builder declined at `at` with one of the combinators.
+    #[inline(always)]
+    pub(super) fn combinators<T>(
+        &self,
+        at: usize,
+        parser: fn(&'a str, &ParseOptions) -> Res<&'a str, T>,
+    ) -> Built<(usize, T)> {
+        match parser(&self.input[at..], self.options) {
+            Ok((rest, parsed)) => Ok((self.input.len() - rest.len(), parsed)),
+            Err(_) => Err(Decline),
+        }
+    }
+
+    /// An unquoted scalar token as a value.
+    #[inline(always)]
+    pub(super) fn scalar(&self, token: &Token<'a>) -> Built<Val<'a>> {
+        match token.kind {
+            TokenKind::Integer => Ok(Val::Integer(Number::new(token.text))),
+            TokenKind::Decimal => Ok(Val::Decimal(Number::new(token.text))),
+            TokenKind::Date => self.date(token.text, Val::Identifier),
+            TokenKind::Identifier => Ok(match token.text {
+                "yes" if self.options.booleans => Val::Boolean(true),
+                "no" if self.options.booleans => Val::Boolean(false),
+                text => Val::Identifier(text),
+            }),
+            _ => Err(Decline),
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn quoted(&self, text: &'a str) -> Built<Val<'a>> {
+        // a rejected date rejects the string even when only its start looks like one
+        if self.options.quoted_dates
+            && self.options.invalid_dates == InvalidDates::Reject
+            && text.starts_with(|c: char| c == '-' || c.is_ascii_digit())
+        {
+            return Err(Decline);
+        }
+        match self.options.quoted_dates && is_date(text) {
+            true => self.date(text, Val::StringLiteral),
+            false => Ok(Val::StringLiteral(text)),
+        }
+    }
+
+    #[inline(always)]
+    fn date(&self, text: &'a str, raw: fn(&'a str) -> Val<'a>) -> Built<Val<'a>> {
+        match text.parse() {
+            Ok(date) => Ok(Val::Date(date)),
+            Err(_) => match self.options.invalid_dates {
+                InvalidDates::Zero => Ok(Val::Date(ClausewitzDate::from_ymd(0, 1, 1))),
+                InvalidDates::Raw => Ok(raw(text)),
+                InvalidDates::Reject => Err(Decline),
+            },
+        }
+    }
+
+    /// The block opening at `at`, read once as a mixed block and then narrowed to the container its
+    /// first token suggests if every entry fits it.
+    pub(super) fn block(&self, at: usize) -> Built<(usize, Val<'a>)> {
+        let body = self.lexer.skip_space(at + 1);
+        let shape = shape(&self.input[at + 1..]);
+        let numbered = match shape {
+            Shape::NumberedDict => self.numbered(body).ok(),
+            _ => None,
+        };
+        let (close, val) = match numbered {
+            Some((number, inner)) => self.numbered_dict(body, number, inner)?,
+            None => {
+                let (close, entries) = self.list(body, vec![], Self::mixed_entry)?;
+                (close, self.narrow(shape, entries))
+            }
+        };
+        Ok((self.expect(close, b'}')?, val))
+    }
+
+    /// The body of `{ 14 { key=value } }`, or a mixed block if there is more to it or the inner
+    /// block is not all pairs.
+    fn numbered_dict(&self, body: usize, number: i64, inner: usize) -> Built<(usize, Val<'a>)> {
+        let (inner_close, entries) = self.list(inner, vec![], Self::mixed_entry)?;
+        let close = self.lexer.skip_space(inner_close + 1);
+        if self.lexer.byte(close) == Some(b'}') && entries.iter().all(|(key, _)| key.is_some()) {
+            let entries = entries
+                .into_iter()
+                .map(|(key, val)| (key.unwrap_or_default(), val))
+                .collect();
+            return Ok((close, Val::NumberedDict(number, entries)));
+        }
+        let number = Val::Integer(Number::new(self.token(body)?.text));
+        let block = self.narrow(shape(&self.input[inner..]), entries);
+        let (close, entries) = self.list(
+            inner_close + 1,
+            vec![(None, number), (None, block)],
+            Self::mixed_entry,
+        )?;
+        Ok((close, Val::Mixed(entries)))
+    }
+
+    /// The entries of a block of `shape` as the container they fit.
+    fn narrow(&self, shape: Shape, entries: Vec<(Option<&'a str>, Val<'a>)>) -> Val<'a> {
+        let mut fits = Fits::new(shape);
+        for (key, val) in &entries {
+            fits.add(*key, is_block(val));
+        }
+        match fits.container(self.options) {
+            Container::Dict => Val::Dict(
+                entries
+                    .into_iter()
+                    .map(|(key, val)| (key.unwrap_or_default(), val))
+                    .collect(),
+            ),
+            Container::Array => {
+                let mut pairs: Vec<_> = entries
+                    .into_iter()
+                    .map(|(key, val)| (key.and_then(index).unwrap_or_default(), val))
+                    .collect();
+                if self.options.sort_arrays {
+                    pairs.sort_by_key(|(index, _)| *index);
+                }
+                Val::Array(pairs)
+            }
+            Container::Set => Val::Set(entries.into_iter().map(|(_, val)| val).collect()),
+            Container::Mixed => Val::Mixed(entries),
+        }
+    }
+
+    /// Elements separated by whitespace up to the closing brace, whose position is returned.
+    /// Elements already read before `from` are passed in `items`.
+    #[inline(always)]
+    fn list<T>(
+        &self,
+        from: usize,
+        mut items: Vec<T>,
+        element: impl Fn(&Self, usize) -> Built<(usize, T)>,
+    ) -> Built<(usize, Vec<T>)> {
+        let mut end = from;
+        loop {
+            let start = match items.is_empty() {
+                true => end,
+                false => {
+                    let start = self.lexer.skip_space(end);
+                    if start == end {
+                        break;
+                    }
+                    start
+                }
+            };
+            if matches!(self.lexer.byte(start), None | Some(b'}')) {
+                break;
+            }
+            let (next, item) = element(self, start)?;
+            items.push(item);
+            end = next;
+        }
+        let close = self.lexer.skip_space(end);
+        self.expect(close, b'}')?;
+        Ok((close, items))
+    }
+}
+
+/// The container a block's entries fit, which is the one its shape suggests or a mixed one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Container {
+    Dict,
+    Array,
+    Set,
+    Mixed,
+}
+
+/// Whether the entries of a block seen so far fit the container its shape suggests.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Fits {
+    shape: Shape,
+    fits: bool,
+}
+
+impl Fits {
+    /// A numbered dict is never narrowed to this way, since its entries are those of the block
+    /// inside it.
+    pub(super) fn new(shape: Shape) -> Self {
+        Fits {
+            shape,
+            fits: shape != Shape::NumberedDict,
+        }
+    }
+
+    /// Adds an entry with `key`, whose value is a block if `block`.
+    #[inline(always)]
+    pub(super) fn add(&mut self, key: Option<&str>, block: bool) {
+        self.fits &= match self.shape {
+            Shape::Dict => key.is_some(),
+            Shape::Array => key.and_then(index).is_some(),
+            Shape::Set => key.is_none(),
+            Shape::SetOfCollections => key.is_none() && block,
+            Shape::Empty | Shape::NumberedDict => true,
+        };
+    }
+
+    /// Whether every entry fits.
+    pub(super) fn fit(&self) -> bool {
+        self.fits
+    }
+
+    pub(super) fn container(&self, options: &ParseOptions) -> Container {
+        match (self.shape, self.fits) {
+            (_, false) => Container::Mixed,
+            (Shape::Empty, _) => match options.empty_block {
+                EmptyBlock::Set => Container::Set,
+                EmptyBlock::Dict => Container::Dict,
+                EmptyBlock::Array => Container::Array,
+            },
+            (Shape::Dict, _) => Container::Dict,
+            (Shape::Array, _) => Container::Array,
+            (Shape::Set | Shape::SetOfCollections, _) => Container::Set,
+            (Shape::NumberedDict, _) => Container::Mixed,
+        }
+    }
+}
+
+/// The index of an array element, whose key is only ever digits.
+#[inline(always)]
+pub(super) fn index(key: &str) -> Option<u64> {
+    match !key.is_empty() && key.bytes().all(|b| b.is_ascii_digit()) {
+        true => key.parse().ok(),
+        false => None,
+    }
+}
+
+/// Whether a value was written as a block, rather than tagged or as a scalar.
+#[inline(always)]
+pub(super) fn is_block(val: &Val) -> bool {
+    matches!(
+        val,
+        Val::Dict(_) | Val::NumberedDict(..) | Val::Array(_) | Val::Set(_) | Val::Mixed(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clausewitz::{
+        bracketed::{bracketed, hash_map},
+        root::root_with_options,
+    };
+
+    fn same_as_combinators(text: &str, options: &ParseOptions) {
+        let built = root(text, options);
+        let (rest, entries) = hash_map(text, options).unwrap();
+        assert_eq!(built.unwrap(), (rest, Val::Dict(entries)));
+    }
+
+    #[test]
+    fn root__every_container__same_as_combinators() {
+        let text = r###"version="v3.4.5"
+date="2290.03.05"
+country={
+	0={
+		name="Earth" flags={ a b } color=rgb { 1 2 3 }
+		budget={ income=10.5 debt=-2 }
+		owned={ 1 2 3 } empty={ } numbered={ 14 { key=value } }
+		sets={ { a=1 } { b=2 } } mixed={ 1 2 key=value }
+	}
+	2={ started=2200.01.01 bad="2200.02.30" yes=yes }
+	1={ }
+}
+trailing=yes
+"###;
+        same_as_combinators(text, &ParseOptions::default());
+        same_as_combinators(text, &ParseOptions::raw());
+        same_as_combinators(text, &ParseOptions::new().booleans(true));
+    }
+
+    #[test]
+    fn root__malformed_tail__same_remainder_as_combinators() {
+        same_as_combinators("a=1 b=2 }", &ParseOptions::default());
+        same_as_combinators("a=1b c=2", &ParseOptions::default());
+        same_as_combinators("a={ 1abc } b=2", &ParseOptions::default());
+        same_as_combinators("", &ParseOptions::default());
+        same_as_combinators("  \n", &ParseOptions::default());
+        same_as_combinators("a=1\n\x0bbcdefghijklmnop=5\n", &ParseOptions::default());
+        same_as_combinators("a={ 1 2 }\x0cbcdefghijklmnop=5\n", &ParseOptions::default());
+    }
+
+    #[test]
+    fn block__mixed_and_numbered__built_without_combinators() {
+        let options = ParseOptions::default();
+        for text in [
+            "{ 1 2 key=value }",
+            "{ key=value 1 2 }",
+            "{ 0=a name=b }",
+            "{ { a=1 } b }",
+            "{ 14 { a b } }",
+            "{ 14 { key=value } 5 }",
+            "{ color=rgb { 1 2 x=y } }",
+        ] {
+            let (end, val) = Builder::new(text, &options).block(0).ok().unwrap();
+            assert_eq!(end, text.len());
+            assert_eq!(val, bracketed(text, &options).unwrap().1);
+        }
+    }
+
+    #[test]
+    fn root__deeply_nested_mixed__linear_time() {
+        let depth = 40;
+        let mut text = String::new();
+        for i in 0..depth {
+            text.push_str(&format!("k{}={{ a=1 ", i));
+        }
+        text.push_str("x=y 5");
+        text.push_str(&" } 5".repeat(depth - 1));
+        text.push_str(" }");
+        let malformed = text.replacen("x=y 5", "x=y 5 =", 1);
+
+        let start = std::time::Instant::now();
+        same_as_combinators(&text, &ParseOptions::default());
+        same_as_combinators(&malformed, &ParseOptions::default());
+
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn root__rejected_date_prefix_in_string__err_like_combinators() {
+        let options = ParseOptions::new().invalid_dates(InvalidDates::Reject);
+        assert!(root("name=\"2200.0.01 Colony\"", &options).is_err());
+        same_as_combinators("name=\"2200.01.01 Colony\"", &options);
+    }
+
+    #[test]
+    fn root__missing_equals__failure_like_combinators() {
+        let options = ParseOptions::default();
+        assert!(root("a=1 b c=2", &options).is_err());
+        assert_eq!(
+            root("a=1 b c=2", &options).is_err(),
+            root_with_options("a=1 b c=2", &options).is_err()
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, BufWriter, Write},
+    path::Path,
+    slice,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use memmap::Mmap;
+use rustc_hash::FxHasher;
+
+use super::{
+    date::ClausewitzDate,
+    document::Document,
+    options::ParseOptions,
+    tape::{root_tape, Node, Span, Tape},
+};
+
+const MAGIC: &[u8; 8] = b"CWTAPE\r\n";
+/// Bumped whenever the nodes of a [`Tape`] or their layout here change, so old caches are
+/// rejected rather than misread.
+const VERSION: u32 = 2;
+/// A multiple of the alignment of [`Node`], so that nodes mapped from a file are aligned.
+const HEADER_LEN: usize = 72;
+const NODE_LEN: usize = size_of::<Node>();
+
+/// A cache which does not belong to the text and options it was read for, or is damaged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheError {
+    pub err: String,
+}
+impl Error for CacheError {}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tape cache: {}", self.err)
+    }
+}
+
+fn error<T>(err: &str) -> Result<T, CacheError> {
+    Err(CacheError {
+        err: err.to_owned(),
+    })
+}
+
+/// What a cache is looked up by: a BLAKE3 hash of the text, so that no two saves share one.
+type Key = [u8; 32];
+
+fn key(text: &str) -> Key {
+    *blake3::hash(text.as_bytes()).as_bytes()
+}
+
+impl<'a> Tape<'a> {
+    /// Writes the nodes as they are laid out in memory after a header with the format version,
+    /// a hash of the options and a hash of the input. The input itself is not written.
+    pub fn write_cache<W: Write>(&self, options: &ParseOptions, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&fingerprint(options).to_le_bytes())?;
+        writer.write_all(&key(self.input))?;
+        writer.write_all(&(self.input.len() as u64).to_le_bytes())?;
+        writer.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        for node in self.nodes.iter() {
+            writer.write_all(&encode(node))?;
+        }
+        writer.flush()
+    }
+
+    /// Reads back what [`Tape::write_cache`] wrote for `input` and `options`. Every node is
+    /// checked, so a cache for some other text, a stale version or a damaged file is an error
+    /// rather than a tape which panics on lookups. The nodes are borrowed from `bytes` when it is
+    /// aligned to 8 bytes, as a memory map is, and copied otherwise.
+    pub fn from_cache(
+        input: &'a str,
+        options: &ParseOptions,
+        bytes: &'a [u8],
+    ) -> Result<Tape<'a>, CacheError> {
+        Ok(Tape {
+            input,
+            nodes: decode(input, &key(input), options, bytes)?,
+        })
+    }
+}
+
+/// A [`Document`] with its [`Tape`], read from a cache when the same text was opened before.
+#[derive(Debug, Clone)]
+pub struct CachedDocument {
+    document: Document,
+    nodes: Nodes,
+}
+
+#[derive(Debug, Clone)]
+enum Nodes {
+    /// A checked cache, whose nodes are read where they are mapped.
+    Mapped(Arc<Mmap>),
+    Parsed(Vec<Node>),
+}
+
+impl CachedDocument {
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The document parsed with its [`Document::options`].
+    pub fn tape(&self) -> Tape<'_> {
+        let nodes = match &self.nodes {
+            Nodes::Mapped(map) => view(&map[HEADER_LEN..]).expect("maps are page aligned"),
+            Nodes::Parsed(nodes) => nodes,
+        };
+        Tape {
+            input: self.document.text(),
+            nodes: Cow::Borrowed(nodes),
+        }
+    }
+
+    /// Whether the tape came from the cache rather than from parsing.
+    pub fn cached(&self) -> bool {
+        matches!(self.nodes, Nodes::Mapped(_))
+    }
+}
+
+impl Document {
+    /// Reads a save like [`Document::open`] along with its [`Tape`]. The tape is loaded from
+    /// `cache_dir` if it holds one for the same text, and otherwise parsed and written there. A
+    /// cache which can not be written is only a missed cache, so the parsed tape is still returned.
+    pub fn open_cached<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        cache_dir: Q,
+    ) -> anyhow::Result<CachedDocument> {
+        let document = Document::open(path)?;
+        let options = document.options();
+        let key = key(document.text());
+        let cache = cache_dir.as_ref().join(format!("{}.tape", hex(&key)));
+        if let Some(map) = load(&cache, document.text(), &key, &options) {
+            return Ok(CachedDocument {
+                document,
+                nodes: Nodes::Mapped(Arc::new(map)),
+            });
+        }
+
+        let text = document.text();
+        let (_, tape) = root_tape(text, &options).map_err(|e| {
+            let at = match e {
+                nom::Err::Error(e) | nom::Err::Failure(e) => {
+                    e.errors.first().map(|(rest, _)| text.len() - rest.len())
+                }
+                nom::Err::Incomplete(_) => None,
+            };
+            anyhow::anyhow!(
+                "the document is not valid at byte {}",
+                at.unwrap_or(text.len())
+            )
+        })?;
+        let _ = store(&cache, &tape, &options);
+        let nodes = tape.nodes.into_owned();
+        Ok(CachedDocument {
+            document,
+            nodes: Nodes::Parsed(nodes),
+        })
+    }
+}
+
+fn hex(key: &Key) -> String {
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The cache at `cache`, if it is there, valid and can be read in place.
+fn load(cache: &Path, input: &str, key: &Key, options: &ParseOptions) -> Option<Mmap> {
+    let file = File::open(cache).ok()?;
+    // caches are only ever replaced by renaming, never written in place
+    let map = unsafe { Mmap::map(&file) }.ok()?;
+    let in_place = matches!(decode(input, key, options, &map), Ok(Cow::Borrowed(_)));
+    in_place.then_some(map)
+}
+
+/// Writes next to `cache` and renames, so that no one reads a half written cache. Each call writes
+/// its own partial file, since threads and processes may store the same save at once.
+fn store(cache: &Path, tape: &Tape, options: &ParseOptions) -> io::Result<()> {
+    static STORES: AtomicUsize = AtomicUsize::new(0);
+    if let Some(dir) = cache.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = cache.with_extension(format!(
+        "tape.{}.{}",
+        std::process::id(),
+        STORES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = File::create(&partial)
+        .and_then(|file| tape.write_cache(options, BufWriter::new(file)))
+        .and_then(|_| fs::rename(&partial, cache));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+/// A hash of every option, with the dialect's whole identifier table rather than just its name,
+/// since two dialects may share a name.
+fn fingerprint(options: &ParseOptions) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write_u8(options.quoted_dates as u8);
+    hasher.write_u8(options.booleans as u8);
+    hasher.write_u8(options.invalid_dates as u8);
+    hasher.write_u8(options.sort_arrays as u8);
+    hasher.write_u8(options.empty_block as u8);
+    hasher.write(options.dialect.name().as_bytes());
+    hasher.write(&options.dialect.identifier.map(|allowed| allowed as u8));
+    hasher.write(&options.dialect.identifier_ranges);
+    hasher.finish()
+}
+
+/// The bytes of `node` as [`Node`] lays them out on x86_64, with the padding zeroed.
+fn encode(node: &Node) -> [u8; NODE_LEN] {
+    // SAFETY: a `repr(u32)` enum starts with its discriminant
+    let tag = unsafe { *(node as *const Node as *const u32) };
+    let span = |span: &Span| (span.start as u64 | (span.len as u64) << 32).to_le_bytes();
+    let mut bytes = [0; NODE_LEN];
+    let mut put = |at: usize, field: &[u8]| bytes[at..at + field.len()].copy_from_slice(field);
+    put(0, &tag.to_le_bytes());
+    match node {
+        Node::Dict(len) | Node::Array(len) | Node::Set(len) | Node::Mixed(len) => {
+            put(4, &len.to_le_bytes())
+        }
+        Node::NumberedDict(len, number) => {
+            put(4, &len.to_le_bytes());
+            put(8, &number.to_le_bytes());
+        }
+        Node::Tagged(tag, len) => {
+            put(4, &span(tag));
+            put(12, &len.to_le_bytes());
+        }
+        Node::Key(text)
+        | Node::StringLiteral(text)
+        | Node::Decimal(text)
+        | Node::Integer(text)
+        | Node::Identifier(text) => put(4, &span(text)),
+        Node::Index(index) => put(8, &index.to_le_bytes()),
+        Node::Date(date) => {
+            put(4, &date.year().to_le_bytes());
+            put(
+                8,
+                &[date.month() as u8, date.day() as u8, date.hour() as u8],
+            );
+        }
+        Node::Boolean(b) => put(4, &[*b as u8]),
+    }
+    bytes
+}
+
+fn decode<'b>(
+    input: &str,
+    key: &Key,
+    options: &ParseOptions,
+    bytes: &'b [u8],
+) -> Result<Cow<'b, [Node]>, CacheError> {
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+        return error("not a tape cache");
+    }
+    if u32_at(8) != VERSION {
+        return error(&format!("version {} is not {}", u32_at(8), VERSION));
+    }
+    if u64_at(16) != fingerprint(options) {
+        return error("parsed with other options");
+    }
+    if u64_at(56) != input.len() as u64 || &bytes[24..56] != key {
+        return error("parsed from another text");
+    }
+    let count = usize::try_from(u64_at(64)).or(error("too many nodes"))?;
+    if count
+        .checked_mul(NODE_LEN)
+        .and_then(|len| len.checked_add(HEADER_LEN))
+        != Some(bytes.len())
+    {
+        return error("truncated");
+    }
+
+    let body = &bytes[HEADER_LEN..];
+    for node in body.chunks_exact(NODE_LEN) {
+        valid(input, node)?;
+    }
+    let nodes = match view(body) {
+        Some(nodes) => Cow::Borrowed(nodes),
+        // SAFETY: every node has just been checked to be valid
+        None => Cow::Owned(
+            body.chunks_exact(NODE_LEN)
+                .map(|node| unsafe { (node.as_ptr() as *const Node).read_unaligned() })
+                .collect(),
+        ),
+    };
+    check(&nodes)?;
+    Ok(nodes)
+}
+
+/// Checks that the bytes of one node are a [`Node`] whose text is in `input`, and whose date is
+/// one [`ClausewitzDate`] can hold.
+fn valid(input: &str, node: &[u8]) -> Result<(), CacheError> {
+    let u32_at = |at: usize| u32::from_le_bytes(node[at..at + 4].try_into().unwrap());
+    let span = || {
+        let (start, len) = (u32_at(4) as usize, u32_at(8) as usize);
+        match input.get(start..start + len) {
+            Some(_) => Ok(()),
+            None => error("text out of bounds"),
+        }
+    };
+    match u32_at(0) {
+        0..=4 | 7 => Ok(()),
+        5 | 6 | 8 | 10 | 11 | 13 => span(),
+        9 => {
+            let (month, day, hour) = (node[8] as u32, node[9] as u32, node[10] as u32);
+            match ClausewitzDate::from_ymdh_opt(u32_at(4) as i32, month, day, hour) {
+                Some(_) if hour < 24 => Ok(()),
+                _ => error("invalid date"),
+            }
+        }
+        12 if node[4] <= 1 => Ok(()),
+        _ => error("unknown node"),
+    }
+}
+
+/// `bytes` as the nodes they hold if they are aligned for it. Every node must have been checked.
+fn view(bytes: &[u8]) -> Option<&[Node]> {
+    if !(bytes.as_ptr() as usize).is_multiple_of(align_of::<Node>()) {
+        return None;
+    }
+    // SAFETY: aligned, and every node has a valid discriminant and fields, the rest is padding
+    Some(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const Node, bytes.len() / NODE_LEN) })
+}
+
+/// An open container while checking, and whether it has a key or index waiting for its value.
+struct Open {
+    node: Node,
+    end: usize,
+    labelled: bool,
+}
+
+/// Checks that the nodes nest the way the tape writes them, without recursing, so lookups can
+/// step through them without going out of bounds.
+fn check(nodes: &[Node]) -> Result<(), CacheError> {
+    match nodes.first() {
+        Some(root @ Node::Dict(_)) if root.size() == nodes.len() => {}
+        _ => return error("the root is not a dict spanning the tape"),
+    }
+    let mut open = vec![Open {
+        node: nodes[0],
+        end: nodes.len(),
+        labelled: false,
+    }];
+    let mut at = 1;
+    loop {
+        while let Some(container) = open.last().filter(|container| container.end == at) {
+            if container.labelled {
+                return error("a key without a value");
+            }
+            open.pop();
+        }
+        let Some(container) = open.last_mut() else {
+            return Ok(());
+        };
+        let node = nodes[at];
+        let size = node.size();
+        if size > container.end - at {
+            return error("a container overflows its parent");
+        }
+        let label = match (container.node, node) {
+            (Node::Dict(_) | Node::NumberedDict(..) | Node::Mixed(_), Node::Key(_)) => true,
+            (Node::Array(_), Node::Index(_)) => true,
+            (_, Node::Key(_) | Node::Index(_)) => return error("a misplaced key or index"),
+            _ => false,
+        };
+        let valid = match container.node {
+            Node::Dict(_) | Node::NumberedDict(..) | Node::Array(_) => label != container.labelled,
+            Node::Mixed(_) => !(label && container.labelled),
+            Node::Tagged(..) => at + size == container.end,
+            _ => true,
+        };
+        if !valid {
+            return error("a value without a key or a key without a value");
+        }
+        container.labelled = label;
+        if let Node::Tagged(_, 0) = node {
+            return error("a tag without a value");
+        }
+        if size > 1 {
+            open.push(Open {
+                node,
+                end: at + size,
+                labelled: false,
+            });
+        }
+        at += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clausewitz::dialect::Dialect;
+
+    const TEXT: &str = r###"version="v3.4.5"
+date=2200.01.01
+country={
+	0={ name="Earth" color=rgb { 1 2 3 } budget={ income=10.5 debt=-2 } }
+	1={ flags={ a b } mixed={ a=1 2 } empty={ } }
+}
+array={ 1=one 0=zero }
+numbered={ 14 { a=1 b=-3 } }
+"###;
+
+    fn written(options: &ParseOptions) -> (Tape<'static>, Vec<u8>) {
+        let (_, tape) = root_tape(TEXT, options).unwrap();
+        let mut bytes = vec![];
+        tape.write_cache(options, &mut bytes).unwrap();
+        (tape, bytes)
+    }
+
+    #[test]
+    fn from_cache__written__same_tape() {
+        for options in [ParseOptions::default(), ParseOptions::raw()] {
+            let (tape, bytes) = written(&options);
+            let read = Tape::from_cache(tape.input(), &options, &bytes).unwrap();
+            assert_eq!(read, tape);
+            assert_eq!(read.to_val(), tape.to_val());
+        }
+    }
+
+    #[test]
+    fn from_cache__aligned__borrowed_otherwise_copied() {
+        let options = ParseOptions::default();
+        let (tape, bytes) = written(&options);
+        let mut words = vec![0u64; bytes.len() / 8 + 1];
+        let buffer =
+            unsafe { slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, bytes.len() + 8) };
+        buffer[..bytes.len()].copy_from_slice(&bytes);
+        let aligned = Tape::from_cache(TEXT, &options, &buffer[..bytes.len()]).unwrap();
+        assert!(matches!(aligned.nodes, Cow::Borrowed(_)));
+        assert_eq!(aligned, tape);
+
+        buffer.copy_within(..bytes.len(), 1);
+        let shifted = Tape::from_cache(TEXT, &options, &buffer[1..bytes.len() + 1]).unwrap();
+        assert!(matches!(shifted.nodes, Cow::Owned(_)));
+        assert_eq!(shifted, tape);
+    }
+
+    #[test]
+    fn from_cache__stale_or_damaged__err() {
+        let options = ParseOptions::default();
+        let (tape, bytes) = written(&options);
+        // lookups on whatever is read must not panic
+        let read = |bytes: &[u8]| {
+            Tape::from_cache(tape.input(), &options, bytes).map(|tape| {
+                tape.to_val();
+            })
+        };
+
+        let mut stale = bytes.clone();
+        stale[8] += 1;
+        assert!(read(&stale).is_err());
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Tape::from_cache(&TEXT[1..], &options, &bytes).is_err());
+        assert!(Tape::from_cache(TEXT, &ParseOptions::raw(), &bytes).is_err());
+        static SAME_NAME: Dialect = Dialect::STELLARIS_SAVE.allowing("stellaris_save", b"-", false);
+        let same_name = options.dialect(&SAME_NAME);
+        assert!(Tape::from_cache(TEXT, &same_name, &bytes).is_err());
+        for at in (HEADER_LEN..bytes.len()).step_by(3) {
+            let mut damaged = bytes.clone();
+            damaged[at] ^= 0x5a;
+            let _ = read(&damaged);
+        }
+    }
+
+    #[test]
+    fn open_cached__opened_twice__reads_cache() {
+        let dir = std::env::temp_dir().join(format!("tape-cache-{}", std::process::id()));
+        let save = dir.join("gamestate");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&save, TEXT).unwrap();
+
+        let first = Document::open_cached(&save, dir.join("cache")).unwrap();
+        let second = Document::open_cached(&save, dir.join("cache")).unwrap();
+        assert!(!first.cached());
+        assert!(second.cached());
+        assert_eq!(second.tape(), first.tape());
+        assert_eq!(
+            second.tape().root().get_string_at_path("country.0.name"),
+            Ok("Earth")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn open_cached__cache_not_writable__parsed() {
+        let dir = std::env::temp_dir().join(format!("tape-unwritable-{}", std::process::id()));
+        let save = dir.join("gamestate");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&save, TEXT).unwrap();
+
+        // a directory can not be made under a file
+        let opened = Document::open_cached(&save, save.join("cache")).unwrap();
+        assert!(!opened.cached());
+        assert_eq!(
+            opened.tape().root().get_string_at_path("country.0.name"),
+            Ok("Earth")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn open_cached__same_save_on_threads__all_open() {
+        let dir = std::env::temp_dir().join(format!("tape-threads-{}", std::process::id()));
+        let save = dir.join("gamestate");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&save, TEXT).unwrap();
+
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| Document::open_cached(&save, dir.join("cache"))))
+                .collect();
+            for thread in threads {
+                assert!(thread.join().unwrap().is_ok());
+            }
+        });
+        let left = fs::read_dir(dir.join("cache")).unwrap().count();
+        assert_eq!(left, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
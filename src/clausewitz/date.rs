@@ -0,0 +1,299 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
+};
+
+use chrono::{Datelike, NaiveDate};
+
+const DAYS_IN_MONTH: [u8; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+const DAYS_BEFORE_MONTH: [u16; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+const DAYS_IN_YEAR: i64 = 365;
+const HOURS_IN_DAY: i64 = 24;
+/// Integer encoded dates count hours from the first of January of this year.
+const ENCODED_EPOCH_YEAR: i64 = -5000;
+
+#[derive(Debug, PartialEq)]
+pub struct DateParseError {
+    err: String,
+}
+
+impl Error for DateParseError {}
+
+impl Display for DateParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.err, f)
+    }
+}
+
+/// A date on the game calendar: every year has 365 days, there are no leap years, years may be
+/// negative and some titles add an hour of the day (`1936.1.1.12`). An hour of `0` means the date
+/// was written without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub struct ClausewitzDate {
+    year: i32,
+    month: u8,
+    day: u8,
+    hour: u8,
+}
+
+impl ClausewitzDate {
+    pub const fn from_ymd_opt(year: i32, month: u32, day: u32) -> Option<Self> {
+        if month < 1 || month > 12 || day < 1 || day > DAYS_IN_MONTH[month as usize - 1] as u32 {
+            return None;
+        }
+        Some(ClausewitzDate {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hour: 0,
+        })
+    }
+
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Self {
+        Self::from_ymd_opt(year, month, day).expect("invalid date")
+    }
+
+    /// Hour 24 is the start of the next day, so that it encodes the same as that day.
+    pub fn from_ymdh_opt(year: i32, month: u32, day: u32, hour: u32) -> Option<Self> {
+        let date = Self::from_ymd_opt(year, month, day)?;
+        match hour {
+            0..=23 => Some(ClausewitzDate {
+                hour: hour as u8,
+                ..date
+            }),
+            24 => Some(date.add_days(1)),
+            _ => None,
+        }
+    }
+
+    /// Decodes the integer form dates take in some fields and in binary saves, e.g.
+    /// `flag_date=63568248`.
+    pub fn from_encoded(encoded: i64) -> Option<Self> {
+        if encoded < 0 {
+            return None;
+        }
+        let hour = encoded % HOURS_IN_DAY;
+        let days = encoded / HOURS_IN_DAY;
+        let year = i32::try_from(days / DAYS_IN_YEAR + ENCODED_EPOCH_YEAR).ok()?;
+        let date = Self::from_days(i64::from(year) * DAYS_IN_YEAR + days % DAYS_IN_YEAR);
+        Some(ClausewitzDate {
+            hour: hour as u8,
+            ..date
+        })
+    }
+
+    pub fn to_encoded(&self) -> i64 {
+        let days = self.days() - ENCODED_EPOCH_YEAR * DAYS_IN_YEAR;
+        days * HOURS_IN_DAY + i64::from(self.hour)
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u32 {
+        self.month as u32
+    }
+
+    pub fn day(&self) -> u32 {
+        self.day as u32
+    }
+
+    pub fn hour(&self) -> u32 {
+        self.hour as u32
+    }
+
+    /// The day of the year, starting at 1 for the first of January.
+    pub fn ordinal(&self) -> u32 {
+        DAYS_BEFORE_MONTH[self.month as usize - 1] as u32 + self.day as u32
+    }
+
+    pub fn add_days(&self, days: i64) -> Self {
+        ClausewitzDate {
+            hour: self.hour,
+            ..Self::from_days(self.days() + days)
+        }
+    }
+
+    /// The number of days from `self` until `other`, negative if `other` is earlier.
+    pub fn days_until(&self, other: &ClausewitzDate) -> i64 {
+        other.days() - self.days()
+    }
+
+    /// Converts to a Gregorian date, which only fails for years outside of chrono's range.
+    pub fn to_naive_date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year, self.month(), self.day())
+    }
+
+    fn days(&self) -> i64 {
+        i64::from(self.year) * DAYS_IN_YEAR + i64::from(self.ordinal()) - 1
+    }
+
+    fn from_days(days: i64) -> Self {
+        let year = days.div_euclid(DAYS_IN_YEAR) as i32;
+        let day_of_year = days.rem_euclid(DAYS_IN_YEAR) as u16;
+        let month = DAYS_BEFORE_MONTH
+            .iter()
+            .rposition(|before| *before <= day_of_year)
+            .unwrap();
+        ClausewitzDate {
+            year,
+            month: month as u8 + 1,
+            day: (day_of_year - DAYS_BEFORE_MONTH[month]) as u8 + 1,
+            hour: 0,
+        }
+    }
+}
+
+impl Display for ClausewitzDate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}.{:02}", self.year, self.month, self.day)?;
+        if self.hour != 0 {
+            write!(f, ".{}", self.hour)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ClausewitzDate {
+    type Err = DateParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.');
+        let mut next_part = |name: &str| {
+            parts.next().ok_or(DateParseError {
+                err: format!("{} is missing a {}", s, name),
+            })
+        };
+        let year = next_part("year")?;
+        let month = next_part("month")?;
+        let day = next_part("day")?;
+        let hour = parts.next();
+        if parts.next().is_some() {
+            return Err(DateParseError {
+                err: format!("{} has too many parts", s),
+            });
+        }
+
+        let number = |part: &str| {
+            part.parse::<u32>().map_err(|_| DateParseError {
+                err: format!("{} is not a date", s),
+            })
+        };
+        let year = year.parse::<i32>().map_err(|_| DateParseError {
+            err: format!("{} is not a date", s),
+        })?;
+        let hour = match hour {
+            Some(hour) => number(hour)?,
+            None => 0,
+        };
+        ClausewitzDate::from_ymdh_opt(year, number(month)?, number(day)?, hour).ok_or(
+            DateParseError {
+                err: format!("{} is not a calendar date", s),
+            },
+        )
+    }
+}
+
+impl TryFrom<NaiveDate> for ClausewitzDate {
+    type Error = DateParseError;
+
+    fn try_from(date: NaiveDate) -> Result<Self, Self::Error> {
+        ClausewitzDate::from_ymd_opt(date.year(), date.month(), date.day()).ok_or(DateParseError {
+            err: format!("{} does not exist on the game calendar", date),
+        })
+    }
+}
+
+impl TryFrom<ClausewitzDate> for NaiveDate {
+    type Error = DateParseError;
+
+    fn try_from(date: ClausewitzDate) -> Result<Self, Self::Error> {
+        date.to_naive_date().ok_or(DateParseError {
+            err: format!("{} is out of range", date),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str__year_month_day__date() {
+        let date = "2200.05.01".parse::<ClausewitzDate>().unwrap();
+        assert_eq!(date, ClausewitzDate::from_ymd(2200, 5, 1));
+        assert_eq!(date.hour(), 0);
+    }
+
+    #[test]
+    fn from_str__with_hour__date_with_hour() {
+        let date = "1936.1.1.12".parse::<ClausewitzDate>().unwrap();
+        assert_eq!(date, ClausewitzDate::from_ymdh_opt(1936, 1, 1, 12).unwrap());
+        assert_eq!(date.to_string(), "1936.01.01.12");
+    }
+
+    #[test]
+    fn from_str__negative_year__date() {
+        let date = "-50.3.1".parse::<ClausewitzDate>().unwrap();
+        assert_eq!(date.year(), -50);
+    }
+
+    #[test]
+    fn from_str__leap_day__rejected() {
+        assert!("2200.02.29".parse::<ClausewitzDate>().is_err());
+    }
+
+    #[test]
+    fn from_str__too_short__rejected() {
+        assert!("2200.02".parse::<ClausewitzDate>().is_err());
+    }
+
+    #[test]
+    fn ordering__later_date__greater() {
+        let earlier = ClausewitzDate::from_ymd(2200, 12, 31);
+        let later = ClausewitzDate::from_ymd(2201, 1, 1);
+        assert!(earlier < later);
+    }
+
+    #[test]
+    fn add_days__over_end_of_february__no_leap_day() {
+        let date = ClausewitzDate::from_ymd(2204, 2, 28);
+        assert_eq!(date.add_days(1), ClausewitzDate::from_ymd(2204, 3, 1));
+        assert_eq!(date.add_days(-365), ClausewitzDate::from_ymd(2203, 2, 28));
+    }
+
+    #[test]
+    fn days_until__one_year__365() {
+        let start = ClausewitzDate::from_ymd(2200, 1, 1);
+        let end = ClausewitzDate::from_ymd(2201, 1, 1);
+        assert_eq!(start.days_until(&end), 365);
+        assert_eq!(end.days_until(&start), -365);
+    }
+
+    #[test]
+    fn from_encoded__flag_date__date() {
+        let date = ClausewitzDate::from_encoded(63568248).unwrap();
+        assert_eq!(date, ClausewitzDate::from_ymd(2256, 8, 26));
+        assert_eq!(date.to_encoded(), 63568248);
+    }
+
+    #[test]
+    fn from_ymdh_opt__hour_24__next_day() {
+        let date = ClausewitzDate::from_ymdh_opt(2200, 12, 31, 24).unwrap();
+        assert_eq!(date, ClausewitzDate::from_ymd(2201, 1, 1));
+        assert_eq!(ClausewitzDate::from_encoded(date.to_encoded()), Some(date));
+        assert_eq!(ClausewitzDate::from_ymdh_opt(2200, 12, 31, 25), None);
+    }
+
+    #[test]
+    fn to_naive_date__date__same_day() {
+        let date = ClausewitzDate::from_ymd(2200, 5, 1);
+        assert_eq!(
+            date.to_naive_date(),
+            Some(NaiveDate::from_ymd_opt(2200, 5, 1).unwrap())
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::{self, Debug, Formatter};
+
+use super::tables::identifier_table;
+
+/// The bytes a game allows in unquoted tokens, along with the same class as the byte ranges the
+/// SIMD scanner rejects.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Dialect {
+    name: &'static str,
+    pub(crate) identifier: [bool; 256],
+    pub(crate) identifier_ranges: [u8; 16],
+}
+
+impl Dialect {
+    pub const STELLARIS_SAVE: Dialect = Dialect::new("stellaris_save", identifier_table());
+    pub const STELLARIS_MOD: Dialect =
+        Dialect::STELLARIS_SAVE.allowing("stellaris_mod", b"@$-'%|/", true);
+    pub const EU4: Dialect = Dialect::STELLARIS_SAVE.allowing("eu4", b"-'", true);
+    pub const CK3: Dialect = Dialect::STELLARIS_SAVE.allowing("ck3", b"@$-'", true);
+    pub const HOI4: Dialect = Dialect::STELLARIS_SAVE.allowing("hoi4", b"@-'", true);
+
+    /// Bytes `0x80` and above must all be allowed or all be rejected, so that tokens always end on
+    /// a character boundary.
+    pub const fn new(name: &'static str, identifier: [bool; 256]) -> Dialect {
+        let mut i = 0x80;
+        while i < 256 {
+            if identifier[i] != identifier[0x80] {
+                panic!("non-ASCII bytes must all be allowed or all be rejected");
+            }
+            i += 1;
+        }
+        if identifier[0] {
+            panic!("the NUL byte can not be part of a token");
+        }
+        Dialect {
+            name,
+            identifier,
+            identifier_ranges: reject_ranges(&identifier),
+        }
+    }
+
+    /// A copy of this dialect which also allows `bytes` in tokens, and all of non-ASCII UTF-8 if
+    /// `non_ascii` is set.
+    pub const fn allowing(&self, name: &'static str, bytes: &[u8], non_ascii: bool) -> Dialect {
+        let mut identifier = self.identifier;
+        let mut i = 0;
+        while i < bytes.len() {
+            identifier[bytes[i] as usize] = true;
+            i += 1;
+        }
+        if non_ascii {
+            let mut i = 0x80;
+            while i < 256 {
+                identifier[i] = true;
+                i += 1;
+            }
+        }
+        Dialect::new(name, identifier)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline(always)]
+    pub fn is_identifier_byte(&self, byte: u8) -> bool {
+        self.identifier[byte as usize]
+    }
+}
+
+impl Debug for Dialect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Dialect").field(&self.name).finish()
+    }
+}
+
+/// `_mm_cmpestri` compares against at most 8 inclusive ranges, so a class may only have 8 runs of
+/// rejected bytes.
+const fn reject_ranges(identifier: &[bool; 256]) -> [u8; 16] {
+    let mut ranges = [0u8; 16];
+    let mut count = 0;
+    let mut i = 0;
+    while i < 256 {
+        if identifier[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < 256 && !identifier[i] {
+            i += 1;
+        }
+        if count == 8 {
+            panic!("a dialect can reject at most 8 ranges of bytes");
+        }
+        ranges[count * 2] = start as u8;
+        ranges[count * 2 + 1] = (i - 1) as u8;
+        count += 1;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stellaris_save__ranges__match_hand_written_ranges() {
+        let ranges = [
+            b'\x00', b'\x2d', b'\x2f', b'\x2f', b';', b'@', b'[', b'^', b'`', b'`', b'{', b'\xff',
+            b'\x00', b'\x00', b'\x00', b'\x00',
+        ];
+        assert_eq!(Dialect::STELLARIS_SAVE.identifier_ranges, ranges);
+    }
+
+    #[test]
+    fn stellaris_mod__variable_and_parameter_bytes__allowed() {
+        for byte in b"@$-'%|/" {
+            assert!(Dialect::STELLARIS_MOD.is_identifier_byte(*byte));
+        }
+        assert!(Dialect::STELLARIS_MOD.is_identifier_byte("é".as_bytes()[0]));
+        assert!(!Dialect::STELLARIS_MOD.is_identifier_byte(b'='));
+        assert!(!Dialect::STELLARIS_MOD.is_identifier_byte(b'"'));
+    }
+
+    #[test]
+    fn stellaris_save__variable_bytes__rejected() {
+        assert!(!Dialect::STELLARIS_SAVE.is_identifier_byte(b'@'));
+        assert!(!Dialect::STELLARIS_SAVE.is_identifier_byte("é".as_bytes()[0]));
+    }
+}
@@ -0,0 +1,172 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Result};
+
+use super::{
+    header::{Encoding, Game, Header},
+    options::ParseOptions,
+    root::root_with_options,
+    val::Val,
+    Res,
+};
+
+/// The key/value text of a save, with its header and checksum removed.
+#[derive(Debug, Clone)]
+pub struct Document {
+    header: Header,
+    text: String,
+    /// `Header::metadata_len` counted in the decoded text rather than the file's bytes.
+    metadata_len: Option<usize>,
+}
+
+impl Document {
+    /// Reads a save of any supported game, rejecting binary and compressed ones.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Document> {
+        let path = path.as_ref();
+        Document::from_bytes(fs::read(path)?).map_err(|e| e.context(path.display().to_string()))
+    }
+
+    pub fn from_bytes(mut bytes: Vec<u8>) -> Result<Document> {
+        let header = Header::detect(&bytes)?;
+        match header.encoding {
+            Encoding::Text => {}
+            Encoding::Binary => bail!("binary saves are not supported"),
+            Encoding::Zip => bail!("compressed saves must be extracted first"),
+        }
+        let body = header.body_range();
+        bytes.truncate(body.end);
+        bytes.drain(..body.start);
+        // EU4 writes Windows-1252 rather than UTF-8
+        let (text, metadata_len) = if header.game == Some(Game::Eu4) {
+            let metadata_len = header.metadata_len.map(|len| {
+                bytes[..len]
+                    .iter()
+                    .map(|&b| windows_1252(b).len_utf8())
+                    .sum()
+            });
+            (
+                bytes.iter().map(|&b| windows_1252(b)).collect(),
+                metadata_len,
+            )
+        } else {
+            let metadata_len = header
+                .metadata_len
+                .map(|len| String::from_utf8_lossy(&bytes[..len]).len());
+            let text = String::from_utf8(bytes)
+                .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+            (text, metadata_len)
+        };
+        Ok(Document {
+            header,
+            text,
+            metadata_len,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The metadata block at the start of `SAV` saves.
+    pub fn metadata(&self) -> Option<&str> {
+        self.metadata_len.map(|len| &self.text[..len])
+    }
+
+    /// The default options with the dialect of the detected game.
+    pub fn options(&self) -> ParseOptions {
+        ParseOptions::new().dialect(self.header.dialect())
+    }
+
+    pub fn parse(&self) -> Res<&str, Val<'_>> {
+        self.parse_with_options(&self.options())
+    }
+
+    pub fn parse_with_options<'a>(&'a self, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+        root_with_options(&self.text, options)
+    }
+}
+
+/// The characters Windows-1252 puts at `0x80..0xa0`, where Latin-1 has control codes. The five
+/// bytes it leaves undefined keep their Latin-1 meaning.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Every byte is one character in Windows-1252, and outside `0x80..0xa0` it is the same as Latin-1.
+fn windows_1252(byte: u8) -> char {
+    match byte {
+        0x80..=0x9f => WINDOWS_1252[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clausewitz::header::Game, ClausewitzDate, ClausewitzValue, Dialect};
+
+    #[test]
+    fn from_bytes__eu4txt__parsed_with_eu4_dialect() {
+        let bytes = b"EU4txt\ndate=1444.11.11\nplayer=\"FRA\"\nsome-key=yes\nchecksum \"0a1b2c\"\n";
+        let document = Document::from_bytes(bytes.to_vec()).unwrap();
+        assert_eq!(document.header().game, Some(Game::Eu4));
+        assert_eq!(
+            document.options(),
+            ParseOptions::new().dialect(&Dialect::EU4)
+        );
+
+        let (remainder, val) = document.parse().unwrap();
+        assert_eq!(remainder.trim(), "");
+        assert_eq!(
+            val.get_date_at_path("date").unwrap(),
+            ClausewitzDate::from_ymd(1444, 11, 11)
+        );
+        assert_eq!(val.get_identifier_at_path("some-key").unwrap(), "yes");
+    }
+
+    #[test]
+    fn from_bytes__sav_text__metadata() {
+        let bytes = b"SAV0100a1b2c3d400000011\nmeta_data={ a=b }\ndate=1066.9.15\n";
+        let document = Document::from_bytes(bytes.to_vec()).unwrap();
+        assert_eq!(document.metadata(), Some("meta_data={ a=b }"));
+        assert!(document.parse().is_ok());
+    }
+
+    #[test]
+    fn from_bytes__eu4_windows_1252__one_char_per_byte() {
+        let bytes = b"EU4txt\nname=\"Bogot\xe1\"\nmotto=\"\x93Ave\x94 \x80\"\n";
+        let document = Document::from_bytes(bytes.to_vec()).unwrap();
+        let (_, val) = document.parse().unwrap();
+        assert_eq!(val.get_string_at_path("name").unwrap(), "Bogot\u{e1}");
+        assert_eq!(
+            val.get_string_at_path("motto").unwrap(),
+            "\u{201c}Ave\u{201d} \u{20ac}"
+        );
+    }
+
+    #[test]
+    fn from_bytes__sav_text_not_utf8__metadata_ends_at_body() {
+        let bytes = b"SAV0100a1b2c3d400000011\nmeta_data={ a=\xff }\ndate=1066.9.15\n";
+        let document = Document::from_bytes(bytes.to_vec()).unwrap();
+        assert_eq!(document.metadata(), Some("meta_data={ a=\u{fffd} }"));
+        assert!(document.text()[document.metadata().unwrap().len()..].starts_with("\ndate"));
+    }
+
+    #[test]
+    fn from_bytes__binary__err() {
+        assert!(Document::from_bytes(b"EU4bin\x01\x00".to_vec()).is_err());
+        assert!(Document::from_bytes(b"PK\x03\x04".to_vec()).is_err());
+    }
+
+    #[test]
+    fn open__missing_file__err() {
+        assert!(Document::open("does/not/exist").is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    ops::Range,
+};
+
+use super::dialect::Dialect;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Game {
+    Stellaris,
+    Eu4,
+    Ck3,
+    Hoi4,
+    /// A `SAV` header, which CK3, Victoria 3 and Imperator all write, so the game can't be told.
+    Unknown,
+}
+
+impl Game {
+    pub fn dialect(&self) -> &'static Dialect {
+        match self {
+            Game::Stellaris => &Dialect::STELLARIS_SAVE,
+            Game::Eu4 => &Dialect::EU4,
+            Game::Ck3 => &Dialect::CK3,
+            Game::Hoi4 => &Dialect::HOI4,
+            // CK3's dialect allows every byte the other games' do
+            Game::Unknown => &Dialect::CK3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Text,
+    Binary,
+    /// A zip archive, either the whole file (Stellaris `.sav`) or the part after the metadata.
+    Zip,
+}
+
+#[derive(Debug)]
+pub struct HeaderError {
+    err: String,
+}
+impl Error for HeaderError {}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.err, f)
+    }
+}
+
+/// What was found before and after the key/value text of a save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// `None` when the file starts directly with key/value text, like a Stellaris gamestate.
+    pub game: Option<Game>,
+    pub encoding: Encoding,
+    /// The number of bytes at the start of the body which hold the save's metadata.
+    pub metadata_len: Option<usize>,
+    pub checksum: Option<String>,
+    body: Range<usize>,
+}
+
+impl Header {
+    /// Reads the magic header and checksum trailer of a save, if there are any.
+    pub fn detect(input: &[u8]) -> Result<Header, HeaderError> {
+        let magic = [
+            (&b"EU4txt"[..], Game::Eu4, Encoding::Text),
+            (b"EU4bin", Game::Eu4, Encoding::Binary),
+            (b"CK3txt", Game::Ck3, Encoding::Text),
+            (b"CK3bin", Game::Ck3, Encoding::Binary),
+            (b"HOI4txt", Game::Hoi4, Encoding::Text),
+            (b"HOI4bin", Game::Hoi4, Encoding::Binary),
+        ];
+        let (game, encoding, metadata_len, start) = if input.starts_with(b"PK\x03\x04") {
+            (None, Encoding::Zip, None, 0)
+        } else if input.starts_with(b"SAV") {
+            let (encoding, metadata_len) = sav_header(input)?;
+            (
+                Some(Game::Unknown),
+                encoding,
+                Some(metadata_len),
+                SAV_HEADER_LEN,
+            )
+        } else if let Some((magic, game, encoding)) = magic.iter().find(|m| input.starts_with(m.0))
+        {
+            (Some(*game), *encoding, None, magic.len())
+        } else {
+            (None, Encoding::Text, None, 0)
+        };
+        let start = start + line_break_len(&input[start..]);
+
+        let (checksum, end) = match (game, encoding) {
+            (Some(_), Encoding::Text) => checksum_trailer(&input[start..])
+                .map(|(checksum, at)| (Some(checksum), start + at))
+                .unwrap_or((None, input.len())),
+            _ => (None, input.len()),
+        };
+
+        if metadata_len.is_some_and(|len| start + len > end) {
+            return Err(HeaderError {
+                err: format!(
+                    "the header declares {} bytes of metadata but the file only has {}",
+                    metadata_len.unwrap(),
+                    end - start
+                ),
+            });
+        }
+
+        Ok(Header {
+            game,
+            encoding,
+            metadata_len,
+            checksum,
+            body: start..end,
+        })
+    }
+
+    /// The bytes holding the key/value text, without the header or checksum.
+    pub fn body_range(&self) -> Range<usize> {
+        self.body.clone()
+    }
+
+    pub fn dialect(&self) -> &'static Dialect {
+        self.game
+            .map_or(&Dialect::STELLARIS_SAVE, |game| game.dialect())
+    }
+}
+
+/// `SAV`, then hex encoded version (2), kind (2), random (8) and metadata length (8).
+const SAV_HEADER_LEN: usize = 23;
+
+fn sav_header(input: &[u8]) -> Result<(Encoding, usize), HeaderError> {
+    let hex = |range: Range<usize>| {
+        input
+            .get(range.clone())
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| usize::from_str_radix(digits, 16).ok())
+            .ok_or(HeaderError {
+                err: format!("bytes {:?} of the SAV header are not hexadecimal", range),
+            })
+    };
+    let _version = hex(3..5)?;
+    let encoding = match hex(5..7)? {
+        0 | 4 => Encoding::Text,
+        1 | 5 => Encoding::Binary,
+        2 | 3 => Encoding::Zip,
+        kind => {
+            return Err(HeaderError {
+                err: format!("unknown SAV header kind {}", kind),
+            })
+        }
+    };
+    let _random = hex(7..15)?;
+    Ok((encoding, hex(15..23)?))
+}
+
+fn line_break_len(input: &[u8]) -> usize {
+    match input {
+        [b'\r', b'\n', ..] => 2,
+        [b'\n', ..] | [b'\r', ..] => 1,
+        _ => 0,
+    }
+}
+
+/// Finds a last line like `checksum "0a1b.."` or `checksum="0a1b.."`, returning its value and
+/// where the line starts.
+fn checksum_trailer(input: &[u8]) -> Option<(String, usize)> {
+    let trimmed = input.trim_ascii_end();
+    let line_start = trimmed
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |i| i + 1);
+    let rest = trimmed[line_start..]
+        .trim_ascii_start()
+        .strip_prefix(b"checksum")?
+        .trim_ascii_start();
+    let rest = rest.strip_prefix(b"=").unwrap_or(rest).trim_ascii_start();
+    let value = rest.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    if value.is_empty() || !value.iter().all(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    Some((String::from_utf8_lossy(value).into_owned(), line_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body<'a>(header: &Header, input: &'a [u8]) -> &'a [u8] {
+        &input[header.body_range()]
+    }
+
+    #[test]
+    fn detect__no_header__whole_input() {
+        let input = b"version=\"Herbert v3.4.5\"\nname=\"x\"";
+        let header = Header::detect(input).unwrap();
+        assert_eq!(header.game, None);
+        assert_eq!(header.encoding, Encoding::Text);
+        assert_eq!(body(&header, input), input);
+        assert_eq!(header.dialect(), &Dialect::STELLARIS_SAVE);
+    }
+
+    #[test]
+    fn detect__eu4txt_with_checksum__stripped() {
+        let input = b"EU4txt\ndate=1444.11.11\nplayer=\"FRA\"\nchecksum \"0a1b2c\"\n";
+        let header = Header::detect(input).unwrap();
+        assert_eq!(header.game, Some(Game::Eu4));
+        assert_eq!(header.encoding, Encoding::Text);
+        assert_eq!(header.checksum.as_deref(), Some("0a1b2c"));
+        assert_eq!(body(&header, input), b"date=1444.11.11\nplayer=\"FRA\"\n");
+        assert_eq!(header.dialect(), &Dialect::EU4);
+    }
+
+    #[test]
+    fn detect__hoi4bin__binary() {
+        let header = Header::detect(b"HOI4bin\x01\x00\x02").unwrap();
+        assert_eq!(header.game, Some(Game::Hoi4));
+        assert_eq!(header.encoding, Encoding::Binary);
+    }
+
+    #[test]
+    fn detect__sav_text__metadata_length() {
+        let input = b"SAV0100a1b2c3d400000013\nmeta_data={ a=b }\r\ndate=1066.9.15\n";
+        let header = Header::detect(input).unwrap();
+        assert_eq!(header.game, Some(Game::Unknown));
+        assert_eq!(header.encoding, Encoding::Text);
+        assert_eq!(header.metadata_len, Some(0x13));
+        assert_eq!(&body(&header, input)[..0x13], b"meta_data={ a=b }\r\n");
+    }
+
+    #[test]
+    fn detect__sav_zip__zip() {
+        let header = Header::detect(b"SAV0102a1b2c3d400000000\nPK\x03\x04").unwrap();
+        assert_eq!(header.encoding, Encoding::Zip);
+    }
+
+    #[test]
+    fn detect__sav_not_hex__err() {
+        assert!(Header::detect(b"SAV01zza1b2c3d400000000\n").is_err());
+    }
+
+    #[test]
+    fn detect__sav_metadata_past_end__err() {
+        assert!(Header::detect(b"SAV0100a1b2c3d4000000ff\nmeta_data={}").is_err());
+    }
+
+    #[test]
+    fn detect__zip__zip() {
+        let header = Header::detect(b"PK\x03\x04\x14\x00").unwrap();
+        assert_eq!(header.game, None);
+        assert_eq!(header.encoding, Encoding::Zip);
+    }
+
+    #[test]
+    fn detect__checksum_key_in_body__kept() {
+        let input = b"checksum=\"abc\"\nname=\"x\"";
+        let header = Header::detect(input).unwrap();
+        assert_eq!(header.checksum, None);
+        assert_eq!(body(&header, input), input);
+    }
+}
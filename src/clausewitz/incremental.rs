@@ -0,0 +1,264 @@
+use std::hash::Hasher;
+
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rustc_hash::{FxHashMap, FxHasher};
+
+use super::{
+    number::Number,
+    options::ParseOptions,
+    section::{SectionError, SectionIndex},
+    val::{IndexError, Val},
+};
+use crate::ClausewitzValue;
+
+/// A document parsed section by section, keeping a hash of each section's text. Autosaves a month
+/// apart share most of their sections, so [`Incremental::update`] reuses the values of sections
+/// whose text did not change and only parses the others.
+#[derive(Debug, Clone, Default)]
+pub struct Incremental<'a> {
+    sections: Vec<Parsed<'a>>,
+    /// What the sections were parsed with, since the same text parses differently under others.
+    options: ParseOptions,
+}
+
+#[derive(Debug, Clone)]
+struct Parsed<'a> {
+    key: &'a str,
+    text: &'a str,
+    hash: u64,
+    val: Val<'a>,
+}
+
+/// The sections an update parsed again and those which are gone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes<'a> {
+    /// Keys of the sections which were new or different, in document order.
+    pub changed: Vec<&'a str>,
+    /// Keys of the previous document which the new one does not have.
+    pub removed: Vec<String>,
+    /// How many sections were reused.
+    pub reused: usize,
+}
+
+impl<'a> Incremental<'a> {
+    pub fn parse(input: &'a str, options: &ParseOptions) -> Result<Self, SectionError> {
+        Incremental::default()
+            .update(input, options)
+            .map(|(incremental, _)| incremental)
+    }
+
+    /// Parses `input`, the next save after this one, reusing every section whose text is the same.
+    /// Nothing is reused if `options` are not the ones this was parsed with.
+    pub fn update<'n>(
+        self,
+        input: &'n str,
+        options: &ParseOptions,
+    ) -> Result<(Incremental<'n>, Changes<'n>), SectionError> {
+        let index = SectionIndex::new(input)?;
+        let mut previous: FxHashMap<u64, Vec<Parsed<'a>>> = FxHashMap::default();
+        for parsed in self.sections.into_iter().rev() {
+            previous.entry(parsed.hash).or_default().push(parsed);
+        }
+        let reusable = self.options == *options;
+
+        let mut changes = Changes::default();
+        let mut sections = Vec::with_capacity(index.sections().len());
+        for section in index.sections() {
+            let hash = hash(section.text);
+            let reused = previous
+                .get_mut(&hash)
+                .filter(|_| reusable)
+                .filter(|same| same.last().is_some_and(|old| old.text == section.text))
+                .and_then(Vec::pop)
+                .and_then(|old| rebase(old.val, old.text, section.text));
+            match reused {
+                Some(_) => changes.reused += 1,
+                None => changes.changed.push(section.key),
+            }
+            sections.push((section, hash, reused));
+        }
+
+        for old in previous.into_values().flatten() {
+            if index.get(old.key).is_none() && !changes.removed.iter().any(|k| k == old.key) {
+                changes.removed.push(old.key.to_owned());
+            }
+        }
+
+        let sections = sections
+            .into_par_iter()
+            .map(|(section, hash, reused)| {
+                let val = match reused {
+                    Some(val) => val,
+                    None => index.parse_section(section, options)?,
+                };
+                Ok(Parsed {
+                    key: section.key,
+                    text: section.text,
+                    hash,
+                    val,
+                })
+            })
+            .collect::<Result<_, SectionError>>()?;
+        Ok((
+            Incremental {
+                sections,
+                options: *options,
+            },
+            changes,
+        ))
+    }
+
+    /// The value of the first section with `key`.
+    pub fn get(&self, key: &str) -> Option<&Val<'a>> {
+        self.sections
+            .iter()
+            .find(|parsed| parsed.key == key)
+            .map(|parsed| &parsed.val)
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = (&'a str, &Val<'a>)> + '_ {
+        self.sections.iter().map(|parsed| (parsed.key, &parsed.val))
+    }
+
+    /// The whole document, as [`super::root::cheat_root`] would give it for every key.
+    pub fn into_val(self) -> Val<'a> {
+        Val::Dict(
+            self.sections
+                .into_iter()
+                .map(|parsed| (parsed.key, parsed.val))
+                .collect(),
+        )
+    }
+}
+
+impl<'a> ClausewitzValue<'a> for Incremental<'a> {
+    fn get_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Val<'a>, IndexError> {
+        let (key, rest) = path
+            .split_once('.')
+            .map_or((path, None), |(k, r)| (k, Some(r)));
+        let val = self.get(key).ok_or(IndexError {
+            err: format!("Expected to find value with key {}", key),
+        })?;
+        match rest {
+            Some(rest) => val.get_at_path(rest),
+            None => Ok(val),
+        }
+    }
+}
+
+pub(super) fn hash(text: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(text.as_bytes());
+    hasher.finish()
+}
+
+/// Moves `val`, parsed from `from`, onto the same bytes in `to`. `None` if some of its text is not
+/// from `from`.
+fn rebase<'a, 'n>(val: Val<'a>, from: &'a str, to: &'n str) -> Option<Val<'n>> {
+    let text = |s: &str| moved(s, from, to);
+    let number = |n: Number<'a>| match text(n.as_str()) {
+        Some(s) => Number::new(s),
+        None => n.into_owned(),
+    };
+    let entries = |entries: Vec<(&'a str, Val<'a>)>| {
+        entries
+            .into_iter()
+            .map(|(k, v)| Some((text(k)?, rebase(v, from, to)?)))
+            .collect::<Option<Vec<_>>>()
+    };
+    Some(match val {
+        Val::Dict(e) => Val::Dict(entries(e)?),
+        Val::NumberedDict(n, e) => Val::NumberedDict(n, entries(e)?),
+        Val::Array(pairs) => Val::Array(
+            pairs
+                .into_iter()
+                .map(|(i, v)| Some((i, rebase(v, from, to)?)))
+                .collect::<Option<_>>()?,
+        ),
+        Val::Set(vals) => Val::Set(
+            vals.into_iter()
+                .map(|v| rebase(v, from, to))
+                .collect::<Option<_>>()?,
+        ),
+        Val::Mixed(e) => Val::Mixed(
+            e.into_iter()
+                .map(|(k, v)| {
+                    let k = match k {
+                        Some(k) => Some(text(k)?),
+                        None => None,
+                    };
+                    Some((k, rebase(v, from, to)?))
+                })
+                .collect::<Option<_>>()?,
+        ),
+        Val::Tagged(tag, v) => Val::Tagged(text(tag)?, Box::new(rebase(*v, from, to)?)),
+        Val::StringLiteral(s) => Val::StringLiteral(text(s)?),
+        Val::Date(date) => Val::Date(date),
+        Val::Decimal(n) => Val::Decimal(number(n)),
+        Val::Integer(n) => Val::Integer(number(n)),
+        Val::Boolean(b) => Val::Boolean(b),
+        Val::Identifier(s) => Val::Identifier(text(s)?),
+    })
+}
+
+/// The bytes of `to` at the position `s` has in `from`.
+fn moved<'n>(s: &str, from: &str, to: &'n str) -> Option<&'n str> {
+    let offset = (s.as_ptr() as usize).checked_sub(from.as_ptr() as usize)?;
+    to.get(offset..offset + s.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clausewitz::root::root_with_options;
+
+    const FIRST: &str = "date=\"2200.01.01\"\ncountry={ 0={ name=\"Earth\" budget=10.5 } }\nfleet={ 1={ ships={ 2 3 } } }\nwar={ 4={ name=\"War\" } }\n";
+    const NEXT: &str = "date=\"2200.02.01\"\ncountry={ 0={ name=\"Earth\" budget=10.5 } }\nfleet={ 1={ ships={ 2 3 4 } } }\n";
+
+    #[test]
+    fn update__one_month_later__reuses_unchanged() {
+        let options = ParseOptions::default();
+        let first = Incremental::parse(FIRST, &options).unwrap();
+        let next = NEXT.to_owned();
+
+        let (next_parsed, changes) = first.update(&next, &options).unwrap();
+        assert_eq!(
+            changes,
+            Changes {
+                changed: vec!["date", "fleet"],
+                removed: vec!["war".to_owned()],
+                reused: 1,
+            }
+        );
+        let (_, expected) = root_with_options(&next, &options).unwrap();
+        assert_eq!(
+            next_parsed.get_at_path("country.0.budget"),
+            expected.get_at_path("country.0.budget")
+        );
+        assert_eq!(next_parsed.into_val(), expected);
+    }
+
+    #[test]
+    fn update__other_options__parses_again() {
+        let first = Incremental::parse(FIRST, &ParseOptions::default()).unwrap();
+        let next = NEXT.to_owned();
+
+        let (next_parsed, changes) = first.update(&next, &ParseOptions::raw()).unwrap();
+        assert_eq!(changes.reused, 0);
+        assert_eq!(changes.changed, vec!["date", "country", "fleet"]);
+        let (_, expected) = root_with_options(&next, &ParseOptions::raw()).unwrap();
+        assert_eq!(next_parsed.into_val(), expected);
+    }
+
+    #[test]
+    fn update__reused_section__points_into_new_text() {
+        let options = ParseOptions::default();
+        let first = Incremental::parse(FIRST, &options).unwrap();
+        let next = NEXT.to_owned();
+
+        let (next_parsed, _) = first.update(&next, &options).unwrap();
+        let name = next_parsed.get_string_at_path("country.0.name").unwrap();
+        let start = next.as_ptr() as usize;
+        assert!((start..start + next.len()).contains(&(name.as_ptr() as usize)));
+    }
+}
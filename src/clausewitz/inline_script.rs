@@ -0,0 +1,801 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    bracketed::bracketed,
+    dialect::Dialect,
+    options::ParseOptions,
+    root::root_with_options,
+    simd::take_simd_identifier,
+    tape::{root_tape, Cursor, Tape},
+    val::Val,
+    Res,
+};
+
+/// Deeper nesting than this is assumed to be a script including itself.
+const MAX_DEPTH: usize = 32;
+
+/// A position in a file on disk, with 1-based line and byte column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// An expansion failure, with the provenance of where it happened, innermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineScriptError {
+    pub err: String,
+    pub provenance: Vec<Location>,
+}
+impl Error for InlineScriptError {}
+
+impl Display for InlineScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.err)?;
+        for location in &self.provenance {
+            write!(f, "\n    at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+/// Expands `inline_script` calls against the scripts in `<root>/common/inline_scripts/`.
+///
+/// Comments are blanked out of the expanded text, and `$PARAM$`, `$PARAM|default$`, `[[PARAM] .. ]`
+/// and `[[!PARAM] .. ]` are substituted inside the included scripts before their own calls are
+/// expanded.
+#[derive(Debug, Clone)]
+pub struct InlineScripts {
+    root: PathBuf,
+}
+
+impl InlineScripts {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        InlineScripts { root: root.into() }
+    }
+
+    pub fn expand_file<P: AsRef<Path>>(&self, path: P) -> Result<Expanded, InlineScriptError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| InlineScriptError {
+            err: format!("can not read {}: {}", path.display(), e),
+            provenance: vec![],
+        })?;
+        self.expand_str(path, text)
+    }
+
+    pub fn expand_str<P: Into<PathBuf>>(
+        &self,
+        path: P,
+        text: String,
+    ) -> Result<Expanded, InlineScriptError> {
+        let mut expander = Expander {
+            root: &self.root,
+            sources: vec![],
+            frames: vec![],
+        };
+        let mapped = expander.file(path.into(), text, None, None)?;
+        Ok(Expanded {
+            mapped,
+            sources: expander.sources,
+            frames: expander.frames,
+        })
+    }
+}
+
+/// The text of a file with every `inline_script` expanded, and a map from each byte back to the
+/// files it came from.
+#[derive(Debug, Clone)]
+pub struct Expanded {
+    mapped: Mapped,
+    sources: Vec<Source>,
+    frames: Vec<Frame>,
+}
+
+impl Expanded {
+    pub fn text(&self) -> &str {
+        &self.mapped.text
+    }
+
+    /// A [`Val`] keeps no positions, so only the strings borrowed from it can be traced with
+    /// [`Expanded::provenance_of`]; [`Expanded::parse_tape`] gives values which all can be.
+    pub fn parse(&self) -> Res<&str, Val<'_>> {
+        self.parse_with_options(&Self::options())
+    }
+
+    pub fn parse_with_options<'a>(&'a self, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+        root_with_options(&self.mapped.text, options)
+    }
+
+    /// Parses the expanded text into a [`Tape`], whose values can be traced with
+    /// [`Expanded::provenance_at`].
+    pub fn parse_tape(&self) -> Res<&str, Tape<'_>> {
+        root_tape(&self.mapped.text, &Self::options())
+    }
+
+    fn options() -> ParseOptions {
+        ParseOptions::new().dialect(&Dialect::STELLARIS_MOD)
+    }
+
+    /// Where the byte at `offset` of the expanded text came from: its position in the file that
+    /// wrote it, followed by the `inline_script` call sites which included that file.
+    pub fn provenance(&self, offset: usize) -> Vec<Location> {
+        if self.mapped.text.is_empty() {
+            return vec![];
+        }
+        let (frame, offset) = self.mapped.locate(offset.min(self.mapped.text.len() - 1));
+        chain(&self.sources, &self.frames, frame, offset)
+    }
+
+    /// The provenance of a key, identifier or string borrowed from [`Expanded::text`], such as one
+    /// taken out of the `Val` returned by [`Expanded::parse`].
+    pub fn provenance_of(&self, s: &str) -> Option<Vec<Location>> {
+        let start = self.mapped.text.as_ptr() as usize;
+        let offset = (s.as_ptr() as usize).checked_sub(start)?;
+        if offset + s.len() > self.mapped.text.len() {
+            return None;
+        }
+        Some(self.provenance(offset))
+    }
+
+    /// The provenance of where a value of a tape from [`Expanded::parse_tape`] starts. A block is
+    /// traced from its opening brace, or from the first value inside it if it has no key. `None`
+    /// for a date or boolean in an array or set, or for a cursor into some other text.
+    pub fn provenance_at(&self, cursor: &Cursor) -> Option<Vec<Location>> {
+        if !std::ptr::eq(cursor.input(), self.mapped.text.as_str()) {
+            return None;
+        }
+        Some(self.provenance(cursor.offset()?))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Source {
+    path: PathBuf,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    source: usize,
+    /// The frame and byte offset of the `inline_script` which included this file.
+    call_site: Option<(usize, usize)>,
+}
+
+/// Maps the bytes of `text` from `start` up to the next segment to `offset` in the source of
+/// `frame`; substituted parameters map every byte to the `$` they replaced.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: usize,
+    frame: usize,
+    offset: usize,
+    linear: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Mapped {
+    text: String,
+    segments: Vec<Segment>,
+}
+
+impl Mapped {
+    fn push(&mut self, text: &str, (frame, offset): (usize, usize), linear: bool) {
+        if text.is_empty() {
+            return;
+        }
+        self.segments.push(Segment {
+            start: self.text.len(),
+            frame,
+            offset,
+            linear,
+        });
+        self.text.push_str(text);
+    }
+
+    fn push_from(&mut self, other: &Mapped, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let first = other.segments.partition_point(|s| s.start <= range.start) - 1;
+        for segment in other.segments[first..]
+            .iter()
+            .take_while(|s| s.start < range.end)
+        {
+            let start = segment.start.max(range.start);
+            self.segments.push(Segment {
+                start: self.text.len() + start - range.start,
+                offset: if segment.linear {
+                    segment.offset + start - segment.start
+                } else {
+                    segment.offset
+                },
+                ..*segment
+            });
+        }
+        self.text.push_str(&other.text[range]);
+    }
+
+    fn locate(&self, position: usize) -> (usize, usize) {
+        let segment = self.segments[self.segments.partition_point(|s| s.start <= position) - 1];
+        if segment.linear {
+            (segment.frame, segment.offset + position - segment.start)
+        } else {
+            (segment.frame, segment.offset)
+        }
+    }
+}
+
+fn chain(sources: &[Source], frames: &[Frame], frame: usize, offset: usize) -> Vec<Location> {
+    let mut locations = vec![];
+    let mut at = Some((frame, offset));
+    while let Some((frame, offset)) = at {
+        let source = &sources[frames[frame].source];
+        let before = &source.text[..offset.min(source.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        locations.push(Location {
+            file: source.path.clone(),
+            line: before.matches('\n').count() + 1,
+            column: offset - line_start + 1,
+        });
+        at = frames[frame].call_site;
+    }
+    locations
+}
+
+struct Expander<'r> {
+    root: &'r Path,
+    sources: Vec<Source>,
+    frames: Vec<Frame>,
+}
+
+impl<'r> Expander<'r> {
+    fn error(&self, err: String, (frame, offset): (usize, usize)) -> InlineScriptError {
+        InlineScriptError {
+            err,
+            provenance: chain(&self.sources, &self.frames, frame, offset),
+        }
+    }
+
+    fn file(
+        &mut self,
+        path: PathBuf,
+        text: String,
+        call_site: Option<(usize, usize)>,
+        params: Option<&HashMap<&str, String>>,
+    ) -> Result<Mapped, InlineScriptError> {
+        let blanked = blank_comments(&text);
+        self.sources.push(Source { path, text });
+        self.frames.push(Frame {
+            source: self.sources.len() - 1,
+            call_site,
+        });
+        let mut raw = Mapped::default();
+        raw.push(&blanked, (self.frames.len() - 1, 0), true);
+
+        let substituted = match params {
+            Some(params) => {
+                let mut substituted = Mapped::default();
+                self.substitute(&raw, 0..raw.text.len(), params, &mut substituted)?;
+                substituted
+            }
+            None => raw,
+        };
+        self.expand_calls(&substituted)
+    }
+
+    fn substitute(
+        &self,
+        input: &Mapped,
+        range: Range<usize>,
+        params: &HashMap<&str, String>,
+        out: &mut Mapped,
+    ) -> Result<(), InlineScriptError> {
+        let bytes = input.text.as_bytes();
+        let mut copied = range.start;
+        let mut i = range.start;
+        while i < range.end {
+            if input.text[i..range.end].starts_with("[[") {
+                let Some((negated, name, body)) = conditional(&input.text[..range.end], i) else {
+                    return Err(self.error("unclosed [[ conditional".to_owned(), input.locate(i)));
+                };
+                out.push_from(input, copied..i);
+                if params.contains_key(name) != negated {
+                    self.substitute(input, body.clone(), params, out)?;
+                }
+                i = body.end + 1;
+                copied = i;
+            } else if bytes[i] == b'$' {
+                let Some((name, default, end)) = parameter(&input.text[..range.end], i) else {
+                    i += 1;
+                    continue;
+                };
+                let value = match (params.get(name), default) {
+                    (Some(value), _) => value.as_str(),
+                    (None, Some(default)) => default,
+                    (None, None) => {
+                        return Err(
+                            self.error(format!("parameter {} is not given", name), input.locate(i))
+                        )
+                    }
+                };
+                out.push_from(input, copied..i);
+                out.push(value, input.locate(i), false);
+                i = end;
+                copied = i;
+            } else {
+                i += 1;
+            }
+        }
+        out.push_from(input, copied..range.end);
+        Ok(())
+    }
+
+    fn expand_calls(&mut self, input: &Mapped) -> Result<Mapped, InlineScriptError> {
+        let bytes = input.text.as_bytes();
+        let mut out = Mapped::default();
+        let mut copied = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'"' {
+                i += input.text[i + 1..]
+                    .find('"')
+                    .map_or(bytes.len(), |end| end + 2);
+                continue;
+            }
+            let at_token_start = i == 0 || !Dialect::STELLARIS_MOD.is_identifier_byte(bytes[i - 1]);
+            let call = if at_token_start && input.text[i..].starts_with(INLINE_SCRIPT) {
+                self.call(input, i)?
+            } else {
+                None
+            };
+            match call {
+                Some((len, script, params)) => {
+                    out.push_from(input, copied..i);
+                    let expanded = self.script(&script, &params, input.locate(i))?;
+                    out.push_from(&expanded, 0..expanded.text.len());
+                    i += len;
+                    copied = i;
+                }
+                None => i += 1,
+            }
+        }
+        out.push_from(input, copied..bytes.len());
+        Ok(out)
+    }
+
+    /// Reads `inline_script = path` or `inline_script = { script = path PARAM = value }`.
+    fn call<'a>(
+        &self,
+        input: &'a Mapped,
+        start: usize,
+    ) -> Result<Option<Call<'a>>, InlineScriptError> {
+        let text = &input.text[start + INLINE_SCRIPT.len()..];
+        let Some(rest) = text.trim_start().strip_prefix('=') else {
+            return Ok(None);
+        };
+        let rest = rest.trim_start();
+        let options = ParseOptions::new().dialect(&Dialect::STELLARIS_MOD);
+        let invalid = |err: &str| {
+            self.error(
+                format!("invalid inline_script: {}", err),
+                input.locate(start),
+            )
+        };
+
+        let (remainder, script, params) = if rest.starts_with('{') {
+            let (remainder, val) =
+                bracketed(rest, &options).map_err(|_| invalid("unparsable block"))?;
+            let Val::Dict(entries) = val else {
+                return Err(invalid("expected script = path and parameters"));
+            };
+            let mut script = None;
+            let mut params = HashMap::new();
+            for (key, value) in entries {
+                let value = param_value(&value).ok_or_else(|| invalid(key))?;
+                match key {
+                    "script" => script = Some(value),
+                    _ => {
+                        params.insert(key, value);
+                    }
+                }
+            }
+            let script = script.ok_or_else(|| invalid("missing script"))?;
+            (remainder, script, params)
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or_else(|| invalid("unclosed string"))?;
+            (&quoted[end + 1..], quoted[..end].to_owned(), HashMap::new())
+        } else {
+            let (remainder, script) = take_simd_identifier(rest, &Dialect::STELLARIS_MOD)
+                .map_err(|_| invalid("missing script"))?;
+            if script.is_empty() {
+                return Err(invalid("missing script"));
+            }
+            (remainder, script.to_owned(), HashMap::new())
+        };
+        let len = input.text.len() - start - remainder.len();
+        Ok(Some((len, script, params)))
+    }
+
+    fn script(
+        &mut self,
+        script: &str,
+        params: &HashMap<&str, String>,
+        call_site: (usize, usize),
+    ) -> Result<Mapped, InlineScriptError> {
+        let depth = std::iter::successors(Some(call_site.0), |f| {
+            self.frames[*f].call_site.map(|c| c.0)
+        })
+        .count();
+        if depth > MAX_DEPTH {
+            return Err(self.error(
+                format!(
+                    "inline_script {} is nested more than {} deep",
+                    script, MAX_DEPTH
+                ),
+                call_site,
+            ));
+        }
+        let path = self
+            .root
+            .join("common")
+            .join("inline_scripts")
+            .join(format!("{}.txt", script));
+        let text = fs::read_to_string(&path).map_err(|e| {
+            self.error(
+                format!("can not read inline script {}: {}", path.display(), e),
+                call_site,
+            )
+        })?;
+        self.file(path, text, Some(call_site), Some(params))
+    }
+}
+
+const INLINE_SCRIPT: &str = "inline_script";
+
+/// The length of an `inline_script` call, its script and its parameters.
+type Call<'a> = (usize, String, HashMap<&'a str, String>);
+
+fn param_value(val: &Val) -> Option<String> {
+    match val {
+        Val::Identifier(s) | Val::StringLiteral(s) => Some(s.to_string()),
+        Val::Integer(n) | Val::Decimal(n) => Some(n.to_string()),
+        Val::Date(d) => Some(d.to_string()),
+        Val::Boolean(b) => Some(if *b { "yes" } else { "no" }.to_owned()),
+        _ => None,
+    }
+}
+
+fn is_param_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// `[[NAME] body ]` or `[[!NAME] body ]` starting at `start`, with the range of the body.
+fn conditional(text: &str, start: usize) -> Option<(bool, &str, Range<usize>)> {
+    let rest = &text[start + 2..];
+    let (negated, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let name = &rest[..rest.find(']')?];
+    let body_start = text.len() - rest.len() + name.len() + 1;
+    let mut depth = 1;
+    for (i, b) in text.bytes().enumerate().skip(body_start) {
+        match b {
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Some((negated, name, body_start..i));
+        }
+    }
+    None
+}
+
+/// `$NAME$` or `$NAME|default$` starting at `start`, with the position after it.
+fn parameter(text: &str, start: usize) -> Option<(&str, Option<&str>, usize)> {
+    let rest = &text[start + 1..];
+    let end = rest.find(['$', '\n'])?;
+    if rest.as_bytes()[end] != b'$' {
+        return None;
+    }
+    let (name, default) = match rest[..end].split_once('|') {
+        Some((name, default)) => (name, Some(default)),
+        None => (&rest[..end], None),
+    };
+    is_param_name(name).then_some((name, default, start + end + 2))
+}
+
+/// Replaces `#` comments with spaces so that byte offsets still match the file.
+fn blank_comments(text: &str) -> String {
+    let mut bytes = text.as_bytes().to_vec();
+    let mut in_string = false;
+    let mut in_comment = false;
+    for b in bytes.iter_mut() {
+        match (*b, in_string, in_comment) {
+            (b'\n', _, true) => in_comment = false,
+            (_, _, true) => *b = b' ',
+            (b'"', _, false) => in_string = !in_string,
+            (b'#', false, false) => {
+                in_comment = true;
+                *b = b' ';
+            }
+            _ => {}
+        }
+    }
+    String::from_utf8(bytes).expect("only ASCII bytes are replaced")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+
+    use super::*;
+    use crate::{ClausewitzValue, Number};
+
+    /// A mod directory under the system temp dir, removed when dropped.
+    struct ModDir(PathBuf);
+
+    impl Deref for ModDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for ModDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A mod directory holding `files`, unique to this test and this run.
+    fn mod_dir(name: &str, files: &[(&str, &str)]) -> ModDir {
+        let root = std::env::temp_dir().join(format!(
+            "clausewitz_inline_script_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, text) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        ModDir(root)
+    }
+
+    #[test]
+    fn expand_str__parameters__substituted() {
+        let root = mod_dir(
+            "parameters",
+            &[(
+                "common/inline_scripts/ships/cost.txt",
+                "cost = $COST$ # the cost\nname = \"$NAME$ Class\"\nupkeep = $UPKEEP|1.5$",
+            )],
+        );
+        let text = "ship = {\n    inline_script = { script = ships/cost COST = 100 NAME = Big }\n}";
+        let expanded = InlineScripts::new(&*root)
+            .expand_str("ships.txt", text.to_owned())
+            .unwrap();
+
+        let (_, val) = expanded.parse().unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![(
+                "ship",
+                Val::Dict(vec![
+                    ("cost", Val::Integer(Number::new("100"))),
+                    ("name", Val::StringLiteral("Big Class")),
+                    ("upkeep", Val::Decimal(Number::new("1.5"))),
+                ])
+            )])
+        );
+    }
+
+    #[test]
+    fn expand_str__conditionals__included_when_given() {
+        let root = mod_dir(
+            "conditionals",
+            &[(
+                "common/inline_scripts/flag.txt",
+                "[[FLAG] has_flag = $FLAG$ cost = @[ 1 + 2 ] ]\n[[!FLAG] no_flag = yes ]",
+            )],
+        );
+        let scripts = InlineScripts::new(&*root);
+
+        let with_flag = scripts
+            .expand_str(
+                "a.txt",
+                "inline_script = { script = flag FLAG = red }".to_owned(),
+            )
+            .unwrap();
+        let (_, val) = with_flag.parse().unwrap();
+        assert_eq!(val.get_identifier_at_path("has_flag").unwrap(), "red");
+        assert_eq!(val.get_identifier_at_path("cost").unwrap(), "@[ 1 + 2 ]");
+        assert!(val.get_at_path("no_flag").is_err());
+
+        let without_flag = scripts
+            .expand_str("a.txt", "inline_script = flag".to_owned())
+            .unwrap();
+        let (_, val) = without_flag.parse().unwrap();
+        assert_eq!(val.get_identifier_at_path("no_flag").unwrap(), "yes");
+        assert!(val.get_at_path("has_flag").is_err());
+    }
+
+    #[test]
+    fn provenance_of__nested_script__call_chain() {
+        let root = mod_dir(
+            "nested",
+            &[
+                (
+                    "common/inline_scripts/outer.txt",
+                    "outer = yes\ninline_script = { script = inner VALUE = $VALUE$ }",
+                ),
+                ("common/inline_scripts/inner.txt", "\ninner = $VALUE$"),
+            ],
+        );
+        let expanded = InlineScripts::new(&*root)
+            .expand_str(
+                "events.txt",
+                "a = b\ninline_script = { script = outer VALUE = deep }".to_owned(),
+            )
+            .unwrap();
+        let (_, val) = expanded.parse().unwrap();
+        let Val::Dict(entries) = &val else { panic!() };
+        let (key, value) = &entries[2];
+        assert_eq!(*key, "inner");
+
+        let scripts = root.join("common").join("inline_scripts");
+        assert_eq!(
+            expanded.provenance_of(key).unwrap(),
+            vec![
+                Location {
+                    file: scripts.join("inner.txt"),
+                    line: 2,
+                    column: 1
+                },
+                Location {
+                    file: scripts.join("outer.txt"),
+                    line: 2,
+                    column: 1
+                },
+                Location {
+                    file: PathBuf::from("events.txt"),
+                    line: 2,
+                    column: 1
+                },
+            ]
+        );
+        let Val::Identifier(value) = value else {
+            panic!()
+        };
+        assert_eq!(
+            expanded.provenance_of(value).unwrap()[0],
+            Location {
+                file: scripts.join("inner.txt"),
+                line: 2,
+                column: 9
+            }
+        );
+    }
+
+    #[test]
+    fn provenance_at__block_from_script__call_chain() {
+        let root = mod_dir(
+            "tape",
+            &[(
+                "common/inline_scripts/ship.txt",
+                "\nship = {\n\tbuilt = 2200.01.01\n\tsizes = { { 1 } 2200.01.01 }\n}",
+            )],
+        );
+        let expanded = InlineScripts::new(&*root)
+            .expand_str("fleets.txt", "inline_script = ship".to_owned())
+            .unwrap();
+        let (_, tape) = expanded.parse_tape().unwrap();
+        let ship = tape.root().get("ship").unwrap();
+        let at = |cursor: &Cursor| {
+            let chain = expanded.provenance_at(cursor).unwrap();
+            (chain[0].line, chain[0].column, chain.len())
+        };
+
+        let script = root.join("common/inline_scripts/ship.txt");
+        assert_eq!(
+            expanded.provenance_at(&ship).unwrap(),
+            vec![
+                Location {
+                    file: script,
+                    line: 2,
+                    column: 8
+                },
+                Location {
+                    file: PathBuf::from("fleets.txt"),
+                    line: 1,
+                    column: 1
+                },
+            ]
+        );
+        assert_eq!(at(&ship.get("built").unwrap()), (3, 10, 2));
+        let sizes = ship.get("sizes").unwrap();
+        let mut elements = sizes.children();
+        assert_eq!(at(&elements.next().unwrap()), (4, 14, 2));
+        assert!(expanded.provenance_at(&elements.next().unwrap()).is_none());
+    }
+
+    #[test]
+    fn expand_str__missing_parameter__located() {
+        let root = mod_dir(
+            "missing_parameter",
+            &[("common/inline_scripts/cost.txt", "cost = $COST$")],
+        );
+        let err = InlineScripts::new(&*root)
+            .expand_str("ships.txt", "\n  inline_script = cost".to_owned())
+            .unwrap_err();
+        assert_eq!(err.err, "parameter COST is not given");
+        assert_eq!(
+            err.provenance,
+            vec![
+                Location {
+                    file: root.join("common/inline_scripts/cost.txt"),
+                    line: 1,
+                    column: 8
+                },
+                Location {
+                    file: PathBuf::from("ships.txt"),
+                    line: 2,
+                    column: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_str__missing_script__err() {
+        let root = mod_dir("missing_script", &[]);
+        let err = InlineScripts::new(&*root)
+            .expand_str("ships.txt", "inline_script = nowhere".to_owned())
+            .unwrap_err();
+        assert!(err.err.starts_with("can not read inline script"));
+    }
+
+    #[test]
+    fn expand_str__self_include__err() {
+        let root = mod_dir(
+            "self_include",
+            &[("common/inline_scripts/loop.txt", "inline_script = loop")],
+        );
+        let err = InlineScripts::new(&*root)
+            .expand_str("a.txt", "inline_script = loop".to_owned())
+            .unwrap_err();
+        assert!(err.err.contains("nested more than"));
+    }
+
+    #[test]
+    fn expand_str__inline_script_in_string__kept() {
+        let expanded = InlineScripts::new("unused")
+            .expand_str(
+                "a.txt",
+                "desc = \"inline_script = x\" my_inline_script = y".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            expanded.text(),
+            "desc = \"inline_script = x\" my_inline_script = y"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use rustc_hash::FxHashMap;
+
+/// A key stored once in an [`Interner`], compared as a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Gives every distinct key a [`Symbol`]. A save has a few thousand distinct keys repeated
+/// millions of times. The keys are borrowed from the parsed text, so one interner can only be
+/// shared by documents whose text outlives it.
+#[derive(Debug, Clone, Default)]
+pub struct Interner<'a> {
+    symbols: FxHashMap<&'a str, Symbol>,
+    keys: Vec<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    #[inline(always)]
+    pub fn intern(&mut self, key: &'a str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(key) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.keys.len() as u32);
+        self.keys.push(key);
+        self.symbols.insert(key, symbol);
+        symbol
+    }
+
+    /// The symbol of a key which has been interned, without adding it.
+    pub fn get(&self, key: &str) -> Option<Symbol> {
+        self.symbols.get(key).copied()
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &'a str {
+        self.keys[symbol.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern__repeated_key__same_symbol() {
+        let mut keys = Interner::new();
+        let owner = keys.intern("owner");
+        let position = keys.intern("position");
+
+        assert_eq!(owner, keys.intern("owner"));
+        assert_ne!(owner, position);
+        assert_eq!(keys.resolve(position), "position");
+        assert_eq!(keys.get("owner"), Some(owner));
+        assert_eq!(keys.get("modifier"), None);
+        assert_eq!(keys.len(), 2);
+    }
+}
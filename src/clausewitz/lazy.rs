@@ -0,0 +1,253 @@
+use std::sync::OnceLock;
+
+use super::{
+    builder::{Builder, Container, Fits},
+    options::ParseOptions,
+    root::root_with_options,
+    shape::{shape, Shape},
+    skip::value_end,
+    val::{IndexError, Val},
+    value::value,
+};
+use crate::ClausewitzValue;
+
+/// The container a skimmed block's keys fit and its children.
+type Skimmed<'a> = (Container, Vec<(&'a str, LazyVal<'a>)>);
+
+/// A value which is only parsed once something looks at it. A dict or array is first skimmed for
+/// the keys and spans of its children, and each child is parsed on its first access and kept, so
+/// `get_at_path("country.0.budget")` parses just the values along that path. Anything which does
+/// not skim as plain `key=value` pairs is parsed whole instead.
+#[derive(Debug)]
+pub struct LazyVal<'a> {
+    text: &'a str,
+    options: ParseOptions,
+    /// A document rather than a single value.
+    root: bool,
+    children: OnceLock<Option<Skimmed<'a>>>,
+    val: OnceLock<Result<Val<'a>, IndexError>>,
+}
+
+impl<'a> LazyVal<'a> {
+    /// A whole document, which is not touched until it is looked at.
+    pub fn root(input: &'a str, options: &ParseOptions) -> Self {
+        LazyVal {
+            root: true,
+            ..LazyVal::new(input, options)
+        }
+    }
+
+    fn new(text: &'a str, options: &ParseOptions) -> Self {
+        LazyVal {
+            text,
+            options: *options,
+            root: false,
+            children: OnceLock::new(),
+            val: OnceLock::new(),
+        }
+    }
+
+    /// The text of the value.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// The keys of the children, skimming the value if it has not been yet. `None` if it is not a
+    /// dict or an array.
+    pub fn keys(&self) -> Option<impl Iterator<Item = &'a str> + '_> {
+        Some(self.children()?.1.iter().map(|(key, _)| *key))
+    }
+
+    /// The first child with `key`, or an array element with index `key`.
+    pub fn get(&self, key: &str) -> Result<&LazyVal<'a>, IndexError> {
+        let (container, children) = self.children().ok_or(IndexError {
+            err: format!("Cannot lazily index {} with {}", self.kind(), key),
+        })?;
+        match *container != Container::Array {
+            true => children
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, child)| child)
+                .ok_or(IndexError {
+                    err: format!("Expected to find value with key {}", key),
+                }),
+            false => {
+                let index = key.parse::<u64>().map_err(|_| IndexError {
+                    err: format!("Cannot index an array with {}", key),
+                })?;
+                children
+                    .iter()
+                    .find(|(k, _)| k.parse::<u64>() == Ok(index))
+                    .map(|(_, child)| child)
+                    .ok_or(IndexError {
+                        err: format!("Expected to find value with index {}", key),
+                    })
+            }
+        }
+    }
+
+    /// Parses the whole value, once.
+    pub fn val(&self) -> Result<&Val<'a>, IndexError> {
+        self.val
+            .get_or_init(|| {
+                let parsed = match self.root {
+                    true => root_with_options(self.text, &self.options),
+                    false => value(self.text, &self.options),
+                };
+                match parsed {
+                    Ok((rest, val)) if self.root || rest.is_empty() => Ok(val),
+                    _ => Err(IndexError {
+                        err: format!("Cannot parse {}", self.kind()),
+                    }),
+                }
+            })
+            .as_ref()
+            .map_err(IndexError::clone)
+    }
+
+    /// Whether the whole value has been parsed.
+    pub fn is_parsed(&self) -> bool {
+        self.val.get().is_some()
+    }
+
+    fn kind(&self) -> &'static str {
+        match self.root {
+            true => "the document",
+            false => "the value",
+        }
+    }
+
+    fn children(&self) -> Option<&Skimmed<'a>> {
+        self.children.get_or_init(|| self.skim()).as_ref()
+    }
+
+    /// The children of a dict or array and their spans, found without parsing them, and the
+    /// container their keys fit as [`Builder`] would decide it.
+    fn skim(&self) -> Option<Skimmed<'a>> {
+        let (from, to, mut fits) = match self.root {
+            true => (0, self.text.len(), Fits::new(Shape::Dict)),
+            false => match shape(self.text.strip_prefix('{')?) {
+                shape @ (Shape::Dict | Shape::Array) if self.text.ends_with('}') => {
+                    (1, self.text.len() - 1, Fits::new(shape))
+                }
+                _ => return None,
+            },
+        };
+        let builder = Builder::new(self.text, &self.options);
+        let bytes = self.text.as_bytes();
+        let mut children = vec![];
+        let mut start = from;
+        while builder.lexer.skip_space(start) < to {
+            let (key, value_at) = builder.key(start).ok()?;
+            let end = value_end(bytes, value_at).filter(|end| *end <= to)?;
+            fits.add(Some(key), bytes[value_at] == b'{');
+            children.push((key, LazyVal::new(&self.text[value_at..end], &self.options)));
+            start = builder.lexer.skip_space(end);
+            if start == end && start < to {
+                return None;
+            }
+        }
+        Some((fits.container(&self.options), children))
+    }
+}
+
+impl<'a> ClausewitzValue<'a> for LazyVal<'a> {
+    fn get_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Val<'a>, IndexError> {
+        let mut node = self;
+        let mut rest = Some(path);
+        while let Some(path) = rest {
+            if node.children().is_none() {
+                return node.val()?.get_at_path(path);
+            }
+            let (key, tail) = path
+                .split_once('.')
+                .map_or((path, None), |(k, t)| (k, Some(t)));
+            node = node.get(key)?;
+            rest = tail;
+        }
+        node.val()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r###"version="v3.4.5"
+country={
+	0={
+		name="Earth" color=rgb { 1 2 3 }
+		budget={ income=10.5 debt=-2 }
+		flags={ a b }
+	}
+	1={ name="Mars" started=2200.01.01 }
+}
+array={ 1=one 0=zero }
+mixed={ 0=a b=c }
+"###;
+
+    #[test]
+    fn get_at_path__nested_key__parses_only_the_path() {
+        let lazy = LazyVal::root(TEXT, &ParseOptions::default());
+
+        assert_eq!(lazy.get_number_at_path("country.0.budget.income"), Ok(10.5));
+        assert_eq!(lazy.get_string_at_path("country.1.name"), Ok("Mars"));
+        assert!(!lazy.is_parsed());
+        assert!(!lazy.get("version").unwrap().is_parsed());
+        assert!(!lazy.get("country").unwrap().is_parsed());
+        assert!(lazy
+            .get("country")
+            .unwrap()
+            .get("1")
+            .unwrap()
+            .get("name")
+            .unwrap()
+            .is_parsed());
+    }
+
+    #[test]
+    fn get_at_path__through_unskimmable__same_as_val() {
+        let lazy = LazyVal::root(TEXT, &ParseOptions::default());
+        let (_, val) = root_with_options(TEXT, &ParseOptions::default()).unwrap();
+
+        for path in [
+            "country.0.color.rgb",
+            "country.0.color.1",
+            "country.0.flags",
+            "country.0.flags.0",
+            "country.1.started",
+            "array.1",
+            "array.2",
+            "country.0.unknown",
+            "mixed.b",
+            "mixed.0",
+        ] {
+            assert_eq!(lazy.get_at_path(path), val.get_at_path(path), "{}", path);
+        }
+        assert_eq!(lazy.val(), Ok(&val));
+    }
+
+    #[test]
+    fn get_at_path__shared_between_threads__parsed_once() {
+        let lazy = LazyVal::root(TEXT, &ParseOptions::default());
+
+        let budgets: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| lazy.get_at_path("country.0.budget").unwrap()))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(budgets
+            .iter()
+            .all(|budget| std::ptr::eq(*budget, budgets[0])));
+    }
+
+    #[test]
+    fn keys__dict__skimmed_keys() {
+        let lazy = LazyVal::root(TEXT, &ParseOptions::default());
+
+        let keys: Vec<_> = lazy.keys().unwrap().collect();
+        assert_eq!(keys, vec!["version", "country", "array", "mixed"]);
+        assert!(lazy.get("version").unwrap().keys().is_none());
+    }
+}
@@ -0,0 +1,288 @@
+use super::{dialect::Dialect, tables::space_table};
+
+/// What a token is, decided in the same scan that finds where it ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Open,
+    Close,
+    Equals,
+    /// A quoted string; the token text is its contents.
+    String,
+    /// `2200.01.01`, or `1936.1.1.12` with an hour.
+    Date,
+    Integer,
+    Decimal,
+    /// Made only of the dialect's identifier bytes and not starting with a digit.
+    Identifier,
+    /// Anything else, like `1abc`, or a string without its closing quote.
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Where the token starts, including the opening quote of a string.
+    pub offset: usize,
+    /// Where the token ends, including the closing quote of a string.
+    pub end: usize,
+    /// Whether every byte is an identifier byte, so the token can be an unquoted key.
+    pub key: bool,
+}
+
+const SPACE: [bool; 256] = space_table();
+
+const fn delimiter_table() -> [bool; 256] {
+    let mut table = space_table();
+    table[b'{' as usize] = true;
+    table[b'}' as usize] = true;
+    table[b'=' as usize] = true;
+    table[b'"' as usize] = true;
+    table
+}
+const DELIMITER: [bool; 256] = delimiter_table();
+
+/// Splits a document into tokens, classifying each one as it is scanned.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    input: &'a str,
+    dialect: &'a Dialect,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str, dialect: &'a Dialect) -> Self {
+        Lexer {
+            input,
+            dialect,
+            position: 0,
+        }
+    }
+
+    pub fn input(&self) -> &'a str {
+        self.input
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    #[inline(always)]
+    pub fn skip_space(&self, from: usize) -> usize {
+        let bytes = self.input.as_bytes();
+        let mut i = from;
+        while i < bytes.len() && SPACE[bytes[i] as usize] {
+            i += 1;
+        }
+        i
+    }
+
+    #[inline(always)]
+    pub fn byte(&self, at: usize) -> Option<u8> {
+        self.input.as_bytes().get(at).copied()
+    }
+
+    /// The token starting at `at`, which must not be whitespace or the end of the input.
+    #[inline(always)]
+    pub fn token_at(&self, at: usize) -> Token<'a> {
+        let bytes = self.input.as_bytes();
+        let operator = |kind| Token {
+            kind,
+            text: &self.input[at..at + 1],
+            offset: at,
+            end: at + 1,
+            key: false,
+        };
+        match bytes[at] {
+            b'{' => return operator(TokenKind::Open),
+            b'}' => return operator(TokenKind::Close),
+            b'=' => return operator(TokenKind::Equals),
+            b'"' => {
+                return match bytes[at + 1..].iter().position(|b| *b == b'"') {
+                    Some(length) => Token {
+                        kind: TokenKind::String,
+                        text: &self.input[at + 1..at + 1 + length],
+                        offset: at,
+                        end: at + length + 2,
+                        key: false,
+                    },
+                    None => Token {
+                        kind: TokenKind::Other,
+                        text: &self.input[at..],
+                        offset: at,
+                        end: bytes.len(),
+                        key: false,
+                    },
+                }
+            }
+            _ => {}
+        }
+
+        let identifier = &self.dialect.identifier;
+        let mut end = at;
+        let mut key = true;
+        let mut shape = NumberShape::new(bytes[at]);
+        while end < bytes.len() && !DELIMITER[bytes[end] as usize] {
+            let b = bytes[end];
+            key &= identifier[b as usize];
+            if end > at || b != b'-' {
+                shape.push(b);
+            }
+            end += 1;
+        }
+        let kind = match shape.kind() {
+            Some(kind) => kind,
+            None if key && !bytes[at].is_ascii_digit() => TokenKind::Identifier,
+            None => TokenKind::Other,
+        };
+        Token {
+            kind,
+            text: &self.input[at..end],
+            offset: at,
+            end,
+            key,
+        }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let at = self.skip_space(self.position);
+        if at >= self.input.len() {
+            self.position = at;
+            return None;
+        }
+        let token = self.token_at(at);
+        self.position = token.end;
+        Some(token)
+    }
+}
+
+/// Whether `text` is shaped like a date, so a quoted string can be read as one.
+#[inline(always)]
+pub fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let Some(first) = bytes.first() else {
+        return false;
+    };
+    let mut shape = NumberShape::new(*first);
+    let digits = if *first == b'-' { &bytes[1..] } else { bytes };
+    digits.iter().for_each(|b| shape.push(*b));
+    shape.kind() == Some(TokenKind::Date)
+}
+
+/// Tracks whether a token is `-?digits(.digits)*` and how many dots it has.
+struct NumberShape {
+    numeric: bool,
+    dots: u8,
+    digits: bool,
+}
+
+impl NumberShape {
+    #[inline(always)]
+    fn new(first: u8) -> Self {
+        NumberShape {
+            numeric: first == b'-' || first.is_ascii_digit(),
+            dots: 0,
+            digits: false,
+        }
+    }
+
+    #[inline(always)]
+    fn push(&mut self, b: u8) {
+        match b {
+            b'0'..=b'9' => self.digits = true,
+            b'.' => {
+                self.numeric &= self.digits;
+                self.dots = self.dots.saturating_add(1);
+                self.digits = false;
+            }
+            _ => self.numeric = false,
+        }
+    }
+
+    #[inline(always)]
+    fn kind(&self) -> Option<TokenKind> {
+        match (self.numeric && self.digits, self.dots) {
+            (true, 0) => Some(TokenKind::Integer),
+            (true, 1) => Some(TokenKind::Decimal),
+            (true, 2 | 3) => Some(TokenKind::Date),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(TokenKind, &str)> {
+        Lexer::new(text, &Dialect::STELLARIS_SAVE)
+            .map(|token| (token.kind, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn lexer__scalars__classified_once() {
+        assert_eq!(
+            kinds("a=-12 b=1.5 c=2200.01.01 d=1936.1.1.12 e=\"x y\""),
+            vec![
+                (TokenKind::Identifier, "a"),
+                (TokenKind::Equals, "="),
+                (TokenKind::Integer, "-12"),
+                (TokenKind::Identifier, "b"),
+                (TokenKind::Equals, "="),
+                (TokenKind::Decimal, "1.5"),
+                (TokenKind::Identifier, "c"),
+                (TokenKind::Equals, "="),
+                (TokenKind::Date, "2200.01.01"),
+                (TokenKind::Identifier, "d"),
+                (TokenKind::Equals, "="),
+                (TokenKind::Date, "1936.1.1.12"),
+                (TokenKind::Identifier, "e"),
+                (TokenKind::Equals, "="),
+                (TokenKind::String, "x y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer__malformed_numbers__other() {
+        assert_eq!(
+            kinds("1abc 1. 1.2.3.4.5 -"),
+            vec![
+                (TokenKind::Other, "1abc"),
+                (TokenKind::Other, "1."),
+                (TokenKind::Other, "1.2.3.4.5"),
+                (TokenKind::Other, "-"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer__braces_and_unclosed_string__operators_then_other() {
+        assert_eq!(
+            kinds("x={}\"open"),
+            vec![
+                (TokenKind::Identifier, "x"),
+                (TokenKind::Equals, "="),
+                (TokenKind::Open, "{"),
+                (TokenKind::Close, "}"),
+                (TokenKind::Other, "\"open"),
+            ]
+        );
+    }
+
+    #[test]
+    fn is_date__quoted_contents__only_whole_dates() {
+        assert!(is_date("2200.01.01"));
+        assert!(!is_date("2200.01.01 Colony"));
+        assert!(!is_date("2200.011"));
+    }
+}
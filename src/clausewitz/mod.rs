@@ -9,14 +9,39 @@ pub(self) mod tests;
 ))]
 pub(crate) mod simd;
 
+pub(crate) mod arena;
 pub mod bracketed;
+pub(crate) mod builder;
+pub(crate) mod cache;
+pub(crate) mod date;
+pub(crate) mod dialect;
+pub(crate) mod document;
+pub(crate) mod header;
+pub(crate) mod incremental;
+pub(crate) mod inline_script;
+pub(crate) mod intern;
+pub(crate) mod lazy;
+pub(crate) mod lexer;
+pub(crate) mod number;
+pub(crate) mod options;
+pub(crate) mod parallel;
+pub(crate) mod path;
+pub(crate) mod projection;
 pub(crate) mod quoted;
 pub mod root;
+pub(crate) mod scripted_variables;
+pub(crate) mod section;
+pub(crate) mod shape;
 pub mod skim;
+pub(crate) mod skip;
 pub(crate) mod space;
+pub(crate) mod structure;
 pub(crate) mod tables;
+pub(crate) mod tape;
+pub(crate) mod transcode;
 pub(crate) mod unquoted;
 pub(crate) mod val;
 pub(crate) mod value;
+pub(crate) mod walk;
 
 pub(crate) type Res<T, S> = IResult<T, S, VerboseError<T>>;
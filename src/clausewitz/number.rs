@@ -0,0 +1,201 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    ops::{Add, Neg, Sub},
+};
+
+/// A number exactly as it was written in the document. Nothing is lost by parsing it, so ids that
+/// overflow an `i64` and values like `25.50000` survive a round trip; the accessors interpret the
+/// lexeme on demand.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Number<'a>(Cow<'a, str>);
+
+impl<'a> Number<'a> {
+    pub fn new(lexeme: &'a str) -> Self {
+        Number(Cow::Borrowed(lexeme))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !self.0.contains('.')
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.0.parse().ok()
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.0.parse().ok()
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        self.0.parse().ok()
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.0.parse().ok()
+    }
+
+    /// The value as a fixed point number with `SCALE` decimal places, if it can be represented
+    /// exactly.
+    pub fn as_fixed<const SCALE: u32>(&self) -> Option<Fixed<SCALE>> {
+        let (negative, digits) = match self.0.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, &self.0[..]),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let significant = fraction.trim_end_matches('0');
+        if significant.len() > SCALE as usize || whole.is_empty() {
+            return None;
+        }
+        let mut raw = whole.parse::<i64>().ok()?;
+        for i in 0..SCALE as usize {
+            let digit = significant.as_bytes().get(i).map_or(0, |d| d - b'0');
+            raw = raw.checked_mul(10)?.checked_add(i64::from(digit))?;
+        }
+        Some(Fixed(if negative { -raw } else { raw }))
+    }
+
+    pub fn into_owned(self) -> Number<'static> {
+        Number(Cow::Owned(self.0.into_owned()))
+    }
+}
+
+impl<'a> Display for Number<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<i64> for Number<'static> {
+    fn from(value: i64) -> Self {
+        Number(Cow::Owned(value.to_string()))
+    }
+}
+
+impl From<f64> for Number<'static> {
+    fn from(value: f64) -> Self {
+        Number(Cow::Owned(value.to_string()))
+    }
+}
+
+/// An exact decimal with `SCALE` digits after the point, like the 3 and 5 decimal values the
+/// games write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed<const SCALE: u32>(i64);
+
+impl<const SCALE: u32> Fixed<SCALE> {
+    const ONE: i64 = 10i64.pow(SCALE);
+
+    pub const fn from_raw(raw: i64) -> Self {
+        Fixed(raw)
+    }
+
+    /// The value multiplied by `10^SCALE`.
+    pub const fn raw(&self) -> i64 {
+        self.0
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64 / Self::ONE as f64
+    }
+}
+
+impl<const SCALE: u32> Add for Fixed<SCALE> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Fixed(self.0 + rhs.0)
+    }
+}
+
+impl<const SCALE: u32> Sub for Fixed<SCALE> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Fixed(self.0 - rhs.0)
+    }
+}
+
+impl<const SCALE: u32> Neg for Fixed<SCALE> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Fixed(-self.0)
+    }
+}
+
+impl<const SCALE: u32> Display for Fixed<SCALE> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let one = Self::ONE as u64;
+        if SCALE == 0 {
+            write!(f, "{}{}", sign, abs)
+        } else {
+            write!(
+                f,
+                "{}{}.{:0width$}",
+                sign,
+                abs / one,
+                abs % one,
+                width = SCALE as usize
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn as_u64__max_u64__accepted() {
+        let number = Number::new("18446744073709551615");
+        assert_eq!(number.as_i64(), None);
+        assert_eq!(number.as_u64(), Some(u64::MAX));
+        assert_eq!(number.as_i128(), Some(u64::MAX as i128));
+    }
+
+    #[test]
+    fn as_str__trailing_zeros__kept() {
+        let number = Number::new("25.50000");
+        assert_eq!(number.as_str(), "25.50000");
+        assert_eq!(number.as_f64(), Some(25.5));
+    }
+
+    #[test]
+    fn as_fixed__five_decimals__exact() {
+        let number = Number::new("-25.50001");
+        let fixed = number.as_fixed::<5>().unwrap();
+        assert_eq!(fixed.raw(), -2550001);
+        assert_eq!(fixed.to_string(), "-25.50001");
+    }
+
+    #[test]
+    fn as_fixed__too_many_decimals__rejected() {
+        let number = Number::new("0.0001");
+        assert_eq!(number.as_fixed::<3>(), None);
+    }
+
+    #[test]
+    fn as_fixed__integer__scaled() {
+        let number = Number::new("12");
+        assert_eq!(number.as_fixed::<3>(), Some(Fixed::from_raw(12000)));
+    }
+
+    #[test]
+    fn fixed__add__exact() {
+        let a = Number::new("0.1").as_fixed::<3>().unwrap();
+        let b = Number::new("0.2").as_fixed::<3>().unwrap();
+        assert_eq!((a + b).to_string(), "0.300");
+    }
+
+    #[test]
+    fn eq__owned_and_borrowed__equal() {
+        assert_eq!(Number::from(42), Number::new("42"));
+    }
+}
@@ -0,0 +1,98 @@
+use super::dialect::Dialect;
+
+/// What to do with a date-shaped token which is not a real calendar date, such as `2200.02.30`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidDates {
+    /// Replace the date with year zero, the historical behaviour.
+    Zero,
+    /// Keep the token as it was written, as a string literal or identifier.
+    Raw,
+    /// Fail the parse.
+    Reject,
+}
+
+/// How an empty block `{}` should be represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyBlock {
+    Set,
+    Dict,
+    Array,
+}
+
+/// Controls how scalars and containers are interpreted while parsing.
+///
+/// The default matches the behaviour of [`crate::root`]; [`ParseOptions::raw`] keeps the
+/// document as close to the text as possible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub(crate) quoted_dates: bool,
+    pub(crate) booleans: bool,
+    pub(crate) invalid_dates: InvalidDates,
+    pub(crate) sort_arrays: bool,
+    pub(crate) empty_block: EmptyBlock,
+    pub(crate) dialect: &'static Dialect,
+}
+
+impl ParseOptions {
+    pub const fn new() -> Self {
+        ParseOptions {
+            quoted_dates: true,
+            booleans: false,
+            invalid_dates: InvalidDates::Zero,
+            sort_arrays: true,
+            empty_block: EmptyBlock::Set,
+            dialect: &Dialect::STELLARIS_SAVE,
+        }
+    }
+
+    pub const fn raw() -> Self {
+        ParseOptions {
+            quoted_dates: false,
+            booleans: false,
+            invalid_dates: InvalidDates::Raw,
+            sort_arrays: false,
+            empty_block: EmptyBlock::Set,
+            dialect: &Dialect::STELLARIS_SAVE,
+        }
+    }
+
+    /// Parse quoted strings shaped like `"2200.05.01"` into [`crate::Val::Date`].
+    pub const fn quoted_dates(mut self, quoted_dates: bool) -> Self {
+        self.quoted_dates = quoted_dates;
+        self
+    }
+
+    /// Parse the identifiers `yes` and `no` into [`crate::Val::Boolean`].
+    pub const fn booleans(mut self, booleans: bool) -> Self {
+        self.booleans = booleans;
+        self
+    }
+
+    pub const fn invalid_dates(mut self, invalid_dates: InvalidDates) -> Self {
+        self.invalid_dates = invalid_dates;
+        self
+    }
+
+    /// Sort the entries of `{ 0=a 1=b }` style arrays by their index.
+    pub const fn sort_arrays(mut self, sort_arrays: bool) -> Self {
+        self.sort_arrays = sort_arrays;
+        self
+    }
+
+    pub const fn empty_block(mut self, empty_block: EmptyBlock) -> Self {
+        self.empty_block = empty_block;
+        self
+    }
+
+    /// Which bytes may appear in unquoted keys, values and tags.
+    pub const fn dialect(mut self, dialect: &'static Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::new()
+    }
+}
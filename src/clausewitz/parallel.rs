@@ -0,0 +1,281 @@
+use std::ops::Range;
+
+use rayon::{
+    prelude::{IntoParallelIterator, ParallelIterator},
+    ThreadPool,
+};
+
+use super::{
+    builder::{index, Builder},
+    options::ParseOptions,
+    root::{cheat_root_with_options, root_with_options},
+    section::SectionIndex,
+    shape::{shape, Shape},
+    skip::{skip_space, string_end, token_end},
+    structure::StructuralIndex,
+    val::Val,
+    Res,
+};
+
+/// Parses big documents with blocks split at entry boundaries, so that one huge section like
+/// `ships` or `pop` does not end up on a single thread. Pieces are parsed on a rayon pool and
+/// put back together in document order. Whenever the text is not plain `key=value` pairs where
+/// it is split, the whole document is parsed in one go instead, so results always match
+/// [`root_with_options`].
+#[derive(Debug, Clone, Copy)]
+pub struct Parallel<'p> {
+    threshold: usize,
+    pool: Option<&'p ThreadPool>,
+}
+
+impl Default for Parallel<'_> {
+    fn default() -> Self {
+        Parallel {
+            threshold: 1 << 18,
+            pool: None,
+        }
+    }
+}
+
+impl<'p> Parallel<'p> {
+    pub fn new() -> Self {
+        Parallel::default()
+    }
+
+    /// Blocks of at least this many bytes are split, into pieces of about this many bytes.
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes.max(1);
+        self
+    }
+
+    /// Parses on `pool` rather than on rayon's global pool.
+    pub fn pool(mut self, pool: &'p ThreadPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn root<'a>(&self, input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+        self.install(|| {
+            if input.len() >= self.threshold {
+                let splitter = Splitter::new(input, options, self.threshold);
+                if let Some((entries, end)) = splitter.and_then(|s| s.entries(0, input.len())) {
+                    return Ok((&input[end..], Val::Dict(entries)));
+                }
+            }
+            root_with_options(input, options)
+        })
+    }
+
+    /// Like [`cheat_root_with_options`], with big sections split as well.
+    pub fn cheat_root<'a>(
+        &self,
+        input: &'a str,
+        keys: Vec<&str>,
+        options: &ParseOptions,
+    ) -> Res<&'a str, Val<'a>> {
+        self.install(|| {
+            let sections = SectionIndex::new(input);
+            let splitter = Splitter::new(input, options, self.threshold);
+            if let (Ok(index), Some(splitter)) = (sections, splitter) {
+                let sections: Vec<_> = index
+                    .sections()
+                    .iter()
+                    .filter(|section| keys.contains(&section.key))
+                    .collect();
+                let dict: Option<Vec<_>> = sections
+                    .into_par_iter()
+                    .map(
+                        |section| match splitter.entries(section.range.start, section.range.end) {
+                            Some((mut entries, _)) if entries.len() == 1 => entries.pop(),
+                            _ => None,
+                        },
+                    )
+                    .collect();
+                if let Some(dict) = dict {
+                    return Ok(("", Val::Dict(dict)));
+                }
+            }
+            cheat_root_with_options(input, keys, options)
+        })
+    }
+
+    fn install<T: Send>(&self, parse: impl FnOnce() -> T + Send) -> T {
+        match self.pool {
+            Some(pool) => pool.install(parse),
+            None => parse(),
+        }
+    }
+}
+
+/// Consecutive small entries, parsed together, or one big block parsed in pieces of its own.
+enum Piece<'a> {
+    Run(Range<usize>),
+    Block(&'a str, Range<usize>),
+}
+
+/// Finds the pairs to split with the builder's lexer, stepping over blocks with the structural
+/// index so that nested blocks are not scanned again at every level.
+struct Splitter<'a, 'o> {
+    builder: Builder<'a, 'o>,
+    index: StructuralIndex<'a>,
+    threshold: usize,
+}
+
+impl<'a, 'o> Splitter<'a, 'o> {
+    fn new(input: &'a str, options: &'o ParseOptions, threshold: usize) -> Option<Self> {
+        Some(Splitter {
+            builder: Builder::new(input, options),
+            index: StructuralIndex::new(input).ok()?,
+            threshold,
+        })
+    }
+
+    /// Parses the pairs from `from` up to `to`, returning them with the end of the last one.
+    fn entries(&self, from: usize, to: usize) -> Option<(Vec<(&'a str, Val<'a>)>, usize)> {
+        let (pieces, end) = self.split(from, to)?;
+        let parsed: Option<Vec<_>> = pieces
+            .into_par_iter()
+            .map(|piece| match piece {
+                Piece::Run(range) => {
+                    match root_with_options(&self.builder.input[range], self.builder.options) {
+                        Ok(("", Val::Dict(entries))) => Some(entries),
+                        _ => None,
+                    }
+                }
+                Piece::Block(key, block) => Some(vec![(key, self.block(block)?)]),
+            })
+            .collect();
+        Some((parsed?.into_iter().flatten().collect(), end))
+    }
+
+    /// Cuts the pairs from `from` up to `to` into pieces, or gives up if they are not all pairs
+    /// separated by whitespace.
+    fn split(&self, from: usize, to: usize) -> Option<(Vec<Piece<'a>>, usize)> {
+        let bytes = self.builder.input.as_bytes();
+        let mut pieces = vec![];
+        let mut run: Option<Range<usize>> = None;
+        let mut start = from;
+        let mut end;
+        loop {
+            let (key, value_at) = self.builder.key(start).ok()?;
+            end = self.value_end(value_at).filter(|end| *end <= to)?;
+            let splittable = bytes[value_at] == b'{'
+                && end - value_at >= self.threshold
+                && matches!(
+                    shape(&self.builder.input[value_at + 1..]),
+                    Shape::Dict | Shape::Array
+                );
+            if splittable {
+                pieces.extend(run.take().map(Piece::Run));
+                pieces.push(Piece::Block(key, value_at..end));
+            } else {
+                let pairs = run.get_or_insert(start..end);
+                pairs.end = end;
+                if pairs.len() >= self.threshold {
+                    pieces.extend(run.take().map(Piece::Run));
+                }
+            }
+            start = self.builder.lexer.skip_space(end);
+            if start >= to {
+                break;
+            }
+            if start == end {
+                return None;
+            }
+        }
+        pieces.extend(run.map(Piece::Run));
+        Some((pieces, end))
+    }
+
+    /// Like [`super::skip::value_end`], looking up where blocks close.
+    fn value_end(&self, at: usize) -> Option<usize> {
+        let bytes = self.builder.input.as_bytes();
+        match bytes.get(at)? {
+            b'{' => self.index.block_end(at),
+            b'"' => string_end(bytes, at),
+            b'}' | b'=' => None,
+            _ => {
+                let end = token_end(bytes, at);
+                let next = skip_space(bytes, end);
+                match bytes.get(next) {
+                    Some(b'{') => self.index.block_end(next),
+                    _ => Some(end),
+                }
+            }
+        }
+    }
+
+    /// Parses a dict or array block spanning `block` in pieces.
+    fn block(&self, block: Range<usize>) -> Option<Val<'a>> {
+        let shape = shape(&self.builder.input[block.start + 1..]);
+        let (entries, _) = self.entries(block.start + 1, block.end - 1)?;
+        match shape {
+            Shape::Dict => Some(Val::Dict(entries)),
+            Shape::Array => {
+                let mut pairs = entries
+                    .into_iter()
+                    .map(|(key, val)| Some((index(key)?, val)))
+                    .collect::<Option<Vec<(u64, Val<'a>)>>>()?;
+                if self.builder.options.sort_arrays {
+                    pairs.sort_by_key(|(index, _)| *index);
+                }
+                Some(Val::Array(pairs))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::ThreadPoolBuilder;
+
+    use super::*;
+    use crate::clausewitz::root::cheat_root;
+
+    const TEXT: &str = r###"version="v3.4.5"
+ships={
+	0={ name="Alpha" modules={ 0=shipyard 1=trading_hub } }
+	1={ name="Beta" color=rgb { 1 2 3 } }
+	2={ name="Gamma" flags={ a b } }
+	3=none
+}
+pop={ 7={ species=1 } 2={ species=2 } 5={ species=3 } }
+country={ 0={ budget={ income=10.5 } intel={ { 14 { intel=0 } } } } }
+"###;
+
+    #[test]
+    fn root__small_threshold__same_as_sequential() {
+        for options in [ParseOptions::default(), ParseOptions::raw()] {
+            for threshold in [1, 16, 64] {
+                let parallel = Parallel::new().threshold(threshold);
+                assert_eq!(
+                    parallel.root(TEXT, &options),
+                    root_with_options(TEXT, &options)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn root__not_all_pairs__falls_back() {
+        let text = "mixed={ a=1 2 b=3 }\nsticky={ a=\"x\"b=2 }\nrest { }";
+        let parallel = Parallel::new().threshold(1);
+        assert_eq!(
+            parallel.root(text, &ParseOptions::default()),
+            root_with_options(text, &ParseOptions::default())
+        );
+    }
+
+    #[test]
+    fn cheat_root__own_pool__same_as_sequential() {
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let parallel = Parallel::new().threshold(16).pool(&pool);
+        let keys = vec!["country", "ships"];
+
+        assert_eq!(
+            parallel.cheat_root(TEXT, keys.clone(), &ParseOptions::default()),
+            cheat_root(TEXT, keys)
+        );
+    }
+}
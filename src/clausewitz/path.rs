@@ -0,0 +1,133 @@
+use std::fmt::{self, Display, Formatter};
+
+/// One step from a container to a child: a key, or the position of a bare value in a set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Any,
+}
+
+impl Segment {
+    #[inline(always)]
+    fn matches(&self, step: &Step) -> bool {
+        match (self, step) {
+            (Segment::Any, _) => true,
+            (Segment::Key(key), Step::Key(step)) => key == step,
+            (Segment::Key(key), Step::Index(index)) => key.parse() == Ok(*index),
+        }
+    }
+}
+
+/// A dotted path like `country.*.budget`, where `*` matches any single key or index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        PathPattern {
+            segments: pattern
+                .split('.')
+                .filter(|segment| !segment.is_empty())
+                .map(|segment| match segment {
+                    "*" => Segment::Any,
+                    key => Segment::Key(key.to_owned()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Whether the pattern has no wildcards, so it can match at most one path.
+    pub fn is_exact(&self) -> bool {
+        !self.segments.contains(&Segment::Any)
+    }
+
+    /// Whether `path` is exactly what the pattern describes.
+    pub fn matches(&self, path: &[Step]) -> bool {
+        self.segments.len() == path.len() && self.is_prefix_of(path)
+    }
+
+    /// Whether the pattern matches the start of `path`, so `path` is inside a match.
+    pub fn is_prefix_of(&self, path: &[Step]) -> bool {
+        self.segments.len() <= path.len()
+            && self
+                .segments
+                .iter()
+                .zip(path)
+                .all(|(segment, step)| segment.matches(step))
+    }
+
+    /// Whether `path` is on the way to a match, but not one yet.
+    pub fn extends(&self, path: &[Step]) -> bool {
+        self.segments.len() > path.len()
+            && self
+                .segments
+                .iter()
+                .zip(path)
+                .all(|(segment, step)| segment.matches(step))
+    }
+}
+
+impl From<&str> for PathPattern {
+    fn from(pattern: &str) -> Self {
+        PathPattern::new(pattern)
+    }
+}
+
+impl Display for PathPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            match segment {
+                Segment::Key(key) => f.write_str(key)?,
+                Segment::Any => f.write_str("*")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches__wildcard__any_key_or_index() {
+        let pattern = PathPattern::new("country.*.budget");
+        assert!(pattern.matches(&[Step::Key("country"), Step::Key("12"), Step::Key("budget")]));
+        assert!(pattern.matches(&[Step::Key("country"), Step::Index(0), Step::Key("budget")]));
+        assert!(!pattern.matches(&[Step::Key("country"), Step::Key("12")]));
+        assert!(!pattern.is_exact());
+    }
+
+    #[test]
+    fn is_prefix_of__deeper_path__true() {
+        let pattern = PathPattern::new("country.0");
+        assert!(pattern.is_prefix_of(&[Step::Key("country"), Step::Index(0), Step::Key("name")]));
+        assert!(!pattern.is_prefix_of(&[Step::Key("country")]));
+        assert!(pattern.extends(&[Step::Key("country")]));
+        assert!(!pattern.extends(&[Step::Key("species")]));
+    }
+
+    #[test]
+    fn display__pattern__round_trips() {
+        assert_eq!(PathPattern::new("a.*.b").to_string(), "a.*.b");
+    }
+}
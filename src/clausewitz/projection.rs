@@ -0,0 +1,292 @@
+use nom::error::{VerboseError, VerboseErrorKind};
+
+use super::{
+    options::ParseOptions,
+    path::{PathPattern, Step},
+    shape::Shape,
+    skip::value_end,
+    val::Val,
+    value::value,
+    walk::{self, block, tagged, Block, Entries, Visitor, Walked},
+    Res,
+};
+
+/// Which parts of a document to parse. Everything under an included path is kept, unless it is
+/// under an excluded path; with no includes, everything is included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Projection {
+    include: Vec<PathPattern>,
+    exclude: Vec<PathPattern>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Skip,
+    /// Some children may be wanted; `included` is set when the value itself is wanted too.
+    Descend {
+        included: bool,
+    },
+    Keep,
+}
+
+impl Projection {
+    pub fn new() -> Self {
+        Projection::default()
+    }
+
+    pub fn include<P: Into<PathPattern>>(mut self, pattern: P) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    pub fn exclude<P: Into<PathPattern>>(mut self, pattern: P) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    fn decide(&self, path: &[Step]) -> Decision {
+        if self.exclude.iter().any(|p| p.is_prefix_of(path)) {
+            return Decision::Skip;
+        }
+        let included = self.include.is_empty() || self.include.iter().any(|p| p.is_prefix_of(path));
+        let excluded_below = self.exclude.iter().any(|p| p.extends(path));
+        match (included, excluded_below) {
+            (true, false) => Decision::Keep,
+            (true, true) => Decision::Descend { included: true },
+            (false, _) if self.include.iter().any(|p| p.extends(path)) => {
+                Decision::Descend { included: false }
+            }
+            (false, _) => Decision::Skip,
+        }
+    }
+}
+
+/// Parses only the parts of `input` the projection asks for. Everything else is stepped over
+/// without being parsed, and containers keep the shape they would have in a full parse.
+pub fn root_projected<'a>(
+    input: &'a str,
+    projection: &Projection,
+    options: &ParseOptions,
+) -> Res<&'a str, Val<'a>> {
+    let mut projector = Projector {
+        input,
+        projection,
+        options,
+        path: vec![],
+    };
+    match walk::entries(&mut projector, 0, false) {
+        Ok((_, entries)) if entries.iter().all(|(key, _)| key.is_some()) => Ok((
+            "",
+            Val::Dict(
+                entries
+                    .into_iter()
+                    .filter_map(|(key, val)| Some((key?, val)))
+                    .collect(),
+            ),
+        )),
+        Ok((_, entries)) => Ok(("", Val::Mixed(entries))),
+        Err((offset, err)) => Err(nom::Err::Failure(VerboseError {
+            errors: vec![(&input[offset..], VerboseErrorKind::Context(err))],
+        })),
+    }
+}
+
+struct Projector<'a, 'p> {
+    input: &'a str,
+    projection: &'p Projection,
+    options: &'p ParseOptions,
+    path: Vec<Step<'a>>,
+}
+
+impl<'a> Visitor<'a> for Projector<'a, '_> {
+    type Item = Val<'a>;
+
+    fn input(&self) -> &'a str {
+        self.input
+    }
+
+    fn path(&mut self) -> &mut Vec<Step<'a>> {
+        &mut self.path
+    }
+
+    fn visit(&mut self, _: Option<&'a str>, start: usize) -> Walked<(usize, Option<Val<'a>>)> {
+        match self.projection.decide(&self.path) {
+            Decision::Skip => Ok((self.skip(start)?, None)),
+            Decision::Keep => self.parse(start),
+            Decision::Descend { included } => self.descend(start, included),
+        }
+    }
+}
+
+impl<'a> Projector<'a, '_> {
+    fn skip(&self, from: usize) -> Walked<usize> {
+        value_end(self.input.as_bytes(), from).ok_or((from, "unclosed value"))
+    }
+
+    fn parse(&self, from: usize) -> Walked<(usize, Option<Val<'a>>)> {
+        let (rest, val) =
+            value(&self.input[from..], self.options).map_err(|_| (from, "invalid value"))?;
+        Ok((self.input.len() - rest.len(), Some(val)))
+    }
+
+    /// The wanted parts of the value at `from`, or `None` when nothing in it is wanted.
+    fn descend(&mut self, from: usize, included: bool) -> Walked<(usize, Option<Val<'a>>)> {
+        if self.input.as_bytes().get(from) != Some(&b'{') {
+            if let Some((tag, open)) = tagged(self.input, from) {
+                let (end, val) = self.descend(open, included)?;
+                return Ok((end, val.map(|val| Val::Tagged(tag, Box::new(val)))));
+            }
+            return match included {
+                true => self.parse(from),
+                false => Ok((self.skip(from)?, None)),
+            };
+        }
+
+        let (end, block) = block(self, from)?;
+        let val = match block {
+            Block::Empty if !included => None,
+            Block::Empty => self.parse(from)?.1,
+            Block::Numbered(number, entries) => {
+                let number = number.parse().map_err(|_| (from, "invalid number"))?;
+                let entries: Vec<_> = entries
+                    .into_iter()
+                    .filter_map(|(key, val)| Some((key?, val)))
+                    .collect();
+                (included || !entries.is_empty()).then_some(Val::NumberedDict(number, entries))
+            }
+            Block::Shaped(_, entries) if !included && entries.is_empty() => None,
+            Block::Shaped(shape, entries) => Some(self.assemble(shape, entries)),
+        };
+        Ok((end, val))
+    }
+
+    /// The container a full parse would have built for a block of `shape`, holding `entries`.
+    fn assemble(&self, shape: Shape, entries: Entries<'a, Val<'a>>) -> Val<'a> {
+        let keyed = entries.iter().all(|(key, _)| key.is_some());
+        let bare = entries.iter().all(|(key, _)| key.is_none());
+        match shape {
+            Shape::Dict if keyed => Val::Dict(
+                entries
+                    .into_iter()
+                    .filter_map(|(key, val)| Some((key?, val)))
+                    .collect(),
+            ),
+            Shape::Array if keyed && entries.iter().all(|(key, _)| is_index(key)) => {
+                let mut pairs: Vec<_> = entries
+                    .into_iter()
+                    .filter_map(|(key, val)| Some((key?.parse::<u64>().ok()?, val)))
+                    .collect();
+                if self.options.sort_arrays {
+                    pairs.sort_by_key(|(index, _)| *index);
+                }
+                Val::Array(pairs)
+            }
+            Shape::Set | Shape::SetOfCollections if bare => {
+                Val::Set(entries.into_iter().map(|(_, val)| val).collect())
+            }
+            _ => Val::Mixed(entries),
+        }
+    }
+}
+
+fn is_index(key: &Option<&str>) -> bool {
+    key.is_some_and(|key| key.parse::<u64>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{root, Number};
+
+    const TEXT: &str = r###"version="v3.4.5"
+country={
+	0={
+		name="Earth"
+		budget={ income=10 }
+		flags={ a b }
+	}
+	1={
+		name="Mars }"
+		budget={ income=5 }
+	}
+}
+species={ { name=human } { name=blorg } }
+"###;
+
+    #[test]
+    fn root_projected__wildcard__only_matching_branches() {
+        let projection = Projection::new().include("country.*.budget");
+        let (_, val) = root_projected(TEXT, &projection, &ParseOptions::default()).unwrap();
+        let budget = |income: &'static str| {
+            Val::Dict(vec![(
+                "budget",
+                Val::Dict(vec![("income", Val::Integer(Number::new(income)))]),
+            )])
+        };
+        assert_eq!(
+            val,
+            Val::Dict(vec![(
+                "country",
+                Val::Array(vec![(0, budget("10")), (1, budget("5"))])
+            )])
+        );
+    }
+
+    #[test]
+    fn root_projected__exclude__branch_dropped() {
+        let projection = Projection::new()
+            .include("country")
+            .exclude("country.*.budget");
+        let (_, val) = root_projected(TEXT, &projection, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![(
+                "country",
+                Val::Array(vec![
+                    (
+                        0,
+                        Val::Dict(vec![
+                            ("name", Val::StringLiteral("Earth")),
+                            (
+                                "flags",
+                                Val::Set(vec![Val::Identifier("a"), Val::Identifier("b")])
+                            ),
+                        ])
+                    ),
+                    (1, Val::Dict(vec![("name", Val::StringLiteral("Mars }"))])),
+                ])
+            )])
+        );
+    }
+
+    #[test]
+    fn root_projected__set_index__element_kept() {
+        let projection = Projection::new().include("species.1.name");
+        let (_, val) = root_projected(TEXT, &projection, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![(
+                "species",
+                Val::Set(vec![Val::Dict(vec![("name", Val::Identifier("blorg"))])])
+            )])
+        );
+    }
+
+    #[test]
+    fn root_projected__no_patterns__same_as_root() {
+        let (_, projected) =
+            root_projected(TEXT, &Projection::new(), &ParseOptions::default()).unwrap();
+        assert_eq!(projected, root(TEXT).unwrap().1);
+    }
+
+    #[test]
+    fn root_projected__unclosed_skipped_block__err() {
+        let projection = Projection::new().include("version");
+        let result = root_projected(
+            "version=1\ncountry={ 0={ }",
+            &projection,
+            &ParseOptions::default(),
+        );
+        assert!(result.is_err());
+    }
+}
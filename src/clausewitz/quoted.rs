@@ -1,68 +1,51 @@
-use super::{simd::take_simd_string_literal, val::Val, Res};
-use chrono::NaiveDate;
+use super::{
+    date::ClausewitzDate,
+    options::{InvalidDates, ParseOptions},
+    simd::take_simd_string_literal,
+    val::Val,
+    Res,
+};
 use nom::{
     branch::alt,
     character::complete::{char, digit1},
-    combinator::{cut, map, map_res, recognize},
-    sequence::{delimited, tuple},
-};
-use std::{
-    error::Error,
-    fmt::{self, Debug, Display, Formatter},
+    combinator::{cut, map, opt, peek, recognize},
+    error::{ErrorKind, ParseError, VerboseError},
+    sequence::{delimited, pair, terminated, tuple},
 };
 
-#[derive(Debug, PartialEq)]
-pub struct DateParseError {
-    err: String,
-}
-
-impl Error for DateParseError {}
-
-impl Display for DateParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Debug::fmt(&self.err, f)
-    }
-}
-
 #[inline(always)]
-pub fn date<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(
-        map_res(
-            recognize(tuple((digit1, char('.'), digit1, char('.'), digit1))),
-            map_to_date,
-        ),
-        |date: NaiveDate| Val::Date(date),
-    )(input)
+pub fn date<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    date_or_raw(input, options, Val::StringLiteral)
 }
 
+/// Parses a date, falling back to `raw` for tokens which are shaped like a date but are not one
+/// when the options ask to keep them.
 #[inline(always)]
-pub fn map_to_date<'a>(s: &'a str) -> anyhow::Result<NaiveDate> {
-    let parts: Vec<&'a str> = s.split(".").collect();
-
-    let year = parts
-        .get(0)
-        .ok_or(DateParseError {
-            err: String::from("Too Short"),
-        })?
-        .parse()?;
-    let month = parts
-        .get(1)
-        .ok_or(DateParseError {
-            err: String::from("Too Short"),
-        })?
-        .parse()?;
-    let day = parts
-        .get(2)
-        .ok_or(DateParseError {
-            err: String::from("Too Short"),
-        })?
-        .parse()?;
-
-    //TODO: if the date really matters, find a way to allow leap years, ie feb 29th
-    Ok(match NaiveDate::from_ymd_opt(year, month, day) {
-        Some(date) => date,
-        None => NaiveDate::from_ymd(0, 1, 1),
-    })
+pub fn date_or_raw<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+    raw: fn(&'a str) -> Val<'a>,
+) -> Res<&'a str, Val<'a>> {
+    let (remainder, text) = recognize(tuple((
+        opt(char('-')),
+        digit1,
+        char('.'),
+        digit1,
+        char('.'),
+        digit1,
+        opt(pair(char('.'), digit1)),
+    )))(input)?;
+    match text.parse() {
+        Ok(date) => Ok((remainder, Val::Date(date))),
+        Err(_) => match options.invalid_dates {
+            InvalidDates::Zero => Ok((remainder, Val::Date(ClausewitzDate::from_ymd(0, 1, 1)))),
+            InvalidDates::Raw => Ok((remainder, raw(text))),
+            InvalidDates::Reject => Err(nom::Err::Failure(VerboseError::from_error_kind(
+                input,
+                ErrorKind::MapRes,
+            ))),
+        },
+    }
 }
 
 #[inline(always)]
@@ -76,8 +59,19 @@ pub fn string_literal<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
 }
 
 #[inline(always)]
-pub fn quoted<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    delimited(char('\"'), cut(alt((date, string_literal))), char('\"'))(input)
+pub fn quoted<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    if options.quoted_dates {
+        delimited(
+            char('\"'),
+            cut(alt((
+                terminated(|i| date(i, options), peek(char('\"'))),
+                string_literal,
+            ))),
+            char('\"'),
+        )(input)
+    } else {
+        delimited(char('\"'), cut(string_literal), char('\"'))(input)
+    }
 }
 
 #[cfg(test)]
@@ -87,17 +81,57 @@ mod tests {
     #[test]
     fn quoted__date__date() {
         let text = "\"2200.01.01\"";
-        let (_remainder, parse_output) = quoted(text).unwrap();
-        assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(2200, 1, 1)));
+        let (_remainder, parse_output) = quoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            parse_output,
+            Val::Date(ClausewitzDate::from_ymd(2200, 1, 1))
+        );
     }
 
     #[test]
     fn quoted__not_date__string() {
         let text = "\"2200.011\"";
-        let (_remainder, parse_output) = quoted(text).unwrap();
+        let (_remainder, parse_output) = quoted(text, &ParseOptions::default()).unwrap();
         assert_eq!(parse_output, Val::StringLiteral("2200.011"));
     }
 
+    #[test]
+    fn quoted__starts_with_date__string() {
+        let text = "\"2200.01.01 Colony\"";
+        let (_remainder, parse_output) = quoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(parse_output, Val::StringLiteral("2200.01.01 Colony"));
+    }
+
+    #[test]
+    fn quoted__date_without_quoted_dates__string() {
+        let text = "\"2200.01.01\"";
+        let options = ParseOptions::new().quoted_dates(false);
+        let (_remainder, parse_output) = quoted(text, &options).unwrap();
+        assert_eq!(parse_output, Val::StringLiteral("2200.01.01"));
+    }
+
+    #[test]
+    fn quoted__leap_day__year_zero() {
+        let text = "\"2200.02.29\"";
+        let (_remainder, parse_output) = quoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(parse_output, Val::Date(ClausewitzDate::from_ymd(0, 1, 1)));
+    }
+
+    #[test]
+    fn quoted__leap_day_with_raw_invalid_dates__string() {
+        let text = "\"2200.02.29\"";
+        let options = ParseOptions::new().invalid_dates(InvalidDates::Raw);
+        let (_remainder, parse_output) = quoted(text, &options).unwrap();
+        assert_eq!(parse_output, Val::StringLiteral("2200.02.29"));
+    }
+
+    #[test]
+    fn quoted__leap_day_with_rejected_invalid_dates__failure() {
+        let text = "\"2200.02.29\"";
+        let options = ParseOptions::new().invalid_dates(InvalidDates::Reject);
+        assert!(matches!(quoted(text, &options), Err(nom::Err::Failure(_))));
+    }
+
     #[cfg(test)]
     mod date_test {
 
@@ -105,36 +139,45 @@ mod tests {
         #[test]
         fn date__decimal_separated_yyyy_mm_date__accepted() {
             let text = "2200.01.01";
-            let (_remainder, parse_output) = date(text).unwrap();
-            assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(2200, 1, 01)));
+            let (_remainder, parse_output) = date(text, &ParseOptions::default()).unwrap();
+            assert_eq!(
+                parse_output,
+                Val::Date(ClausewitzDate::from_ymd(2200, 1, 01))
+            );
         }
 
         #[test]
         fn date__4digit_year__accepted() {
             let text = "2200.01.01";
-            let (_remainder, parse_output) = date(text).unwrap();
-            assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(2200, 1, 01)));
+            let (_remainder, parse_output) = date(text, &ParseOptions::default()).unwrap();
+            assert_eq!(
+                parse_output,
+                Val::Date(ClausewitzDate::from_ymd(2200, 1, 01))
+            );
         }
 
         #[test]
         fn date__3digit_year__accepted() {
             let text = "200.01.01";
-            let (_remainder, parse_output) = date(text).unwrap();
-            assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(200, 1, 01)));
+            let (_remainder, parse_output) = date(text, &ParseOptions::default()).unwrap();
+            assert_eq!(
+                parse_output,
+                Val::Date(ClausewitzDate::from_ymd(200, 1, 01))
+            );
         }
 
         #[test]
         fn date__2digit_year__accepted() {
             let text = "20.01.01";
-            let (_remainder, parse_output) = date(text).unwrap();
-            assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(20, 1, 01)));
+            let (_remainder, parse_output) = date(text, &ParseOptions::default()).unwrap();
+            assert_eq!(parse_output, Val::Date(ClausewitzDate::from_ymd(20, 1, 01)));
         }
 
         #[test]
         fn date__1digit_year__accepted() {
             let text = "2.01.01";
-            let (_remainder, parse_output) = date(text).unwrap();
-            assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(2, 1, 01)));
+            let (_remainder, parse_output) = date(text, &ParseOptions::default()).unwrap();
+            assert_eq!(parse_output, Val::Date(ClausewitzDate::from_ymd(2, 1, 01)));
         }
     }
 
@@ -1,58 +1,55 @@
 use std::{process::exit, time::Duration};
 
-use nom::{combinator::map, FindSubstring, InputTake};
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use regex::Regex;
+use nom::error::{VerboseError, VerboseErrorKind};
+use nom::{FindSubstring, InputTake};
 
-use super::{bracketed::hash_map, val::Val, Res};
+use super::{builder, options::ParseOptions, section::SectionIndex, val::Val, Res};
 #[inline(always)]
 pub fn root<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(hash_map, Val::Dict)(input)
+    root_with_options(input, &ParseOptions::default())
+}
+
+#[inline(always)]
+pub fn root_with_options<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    builder::root(input, options)
 }
 
 #[inline(always)]
 pub fn cheat_root<'a, 'b>(input: &'a str, keys: Vec<&'b str>) -> Res<&'a str, Val<'a>> {
-    let mut last = 0;
-    let mut indices: Vec<&str> = vec![];
-    // "\n\w+=.*\n" may be a better way to split up the file by top-level keys
-    let regex = Regex::new(r"\n\w+=.*|^version=.*").expect("invalid_regex");
-    for mat in regex.find_iter(input) {
-        if mat.start() == 0 {
-            continue;
-        }
-        let start = mat.start() + 1;
-        if start != last {
-            indices.push(&input[last..start])
+    cheat_root_with_options(input, keys, &ParseOptions::default())
+}
+
+#[inline(always)]
+pub fn cheat_root_with_options<'a>(
+    input: &'a str,
+    keys: Vec<&str>,
+    options: &ParseOptions,
+) -> Res<&'a str, Val<'a>> {
+    let failure = |offset: usize| {
+        nom::Err::Failure(VerboseError {
+            errors: vec![(&input[offset..], VerboseErrorKind::Context("section"))],
+        })
+    };
+    let index = SectionIndex::new(input).map_err(|e| failure(e.offset))?;
+    let mut dict = vec![];
+    for (key, section) in index.parse_many(&keys, options) {
+        match section {
+            Ok(val) => dict.push((key, val)),
+            Err(e) => return Err(failure(e.offset)),
         }
-        last = start;
-    }
-    if last < input.len() {
-        indices.push(&input[last..]);
-    }
-    let res = Val::Dict(
-        indices
-            .iter()
-            .filter(|block| {
-                keys.iter()
-                    .any(|k| block.starts_with(format!("{}=", k).as_str()))
-            })
-            .collect::<Vec<_>>()
-            .par_iter()
-            .filter_map(|string| match root(string) {
-                Ok((_, Val::Dict(dict))) => Some(dict),
-                Ok(_) => None,
-                Err(_) => None,
-            })
-            .flat_map(|v| v)
-            .collect(),
-    );
-    let res = Ok(("", res));
-    res
+    }
+    Ok(("", Val::Dict(dict)))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{clausewitz::tests::helper::assert_result_ok, key_value};
+    use crate::{
+        clausewitz::{
+            options::ParseOptions,
+            tests::helper::{assert_result_err, assert_result_ok},
+        },
+        key_value, Dialect, EmptyBlock, InvalidDates, Number,
+    };
     #[test]
     fn root__key_identifier_pairs__ok() {
         let text = r###"dict={
@@ -112,7 +109,7 @@ dict2={
                 flag_days=293
             }
         }"###;
-        let result = key_value(text);
+        let result = key_value(text, &ParseOptions::default());
         println!("{:?}", result);
 
         assert_result_ok(result);
@@ -229,4 +226,107 @@ dict2={
         let result = root(text);
         assert_result_ok(result);
     }
+
+    #[test]
+    fn root__id_larger_than_i64__ok() {
+        let text = r###"id=18446744073709551615
+            income=25.50000"###;
+
+        let (_, val) = root(text).unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![
+                ("id", Val::Integer(Number::new("18446744073709551615"))),
+                ("income", Val::Decimal(Number::new("25.50000"))),
+            ])
+        );
+    }
+
+    #[test]
+    fn root_with_options__raw__keeps_text() {
+        let text = r###"date="2200.05.01"
+            array={
+                1="one"
+                0="zero"
+            }"###;
+
+        let (_, val) = root_with_options(text, &ParseOptions::raw()).unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![
+                ("date", Val::StringLiteral("2200.05.01")),
+                (
+                    "array",
+                    Val::Array(vec![
+                        (1, Val::StringLiteral("one")),
+                        (0, Val::StringLiteral("zero"))
+                    ])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn root_with_options__empty_block_dict__empty_dict() {
+        let text = r###"empty={}"###;
+        let options = ParseOptions::new().empty_block(EmptyBlock::Dict);
+
+        let (_, val) = root_with_options(text, &options).unwrap();
+        assert_eq!(val, Val::Dict(vec![("empty", Val::Dict(vec![]))]));
+    }
+
+    #[test]
+    fn root_with_options__rejected_invalid_date__err() {
+        let text = r###"date="2200.02.30""###;
+        let options = ParseOptions::new().invalid_dates(InvalidDates::Reject);
+
+        let result = root_with_options(text, &options);
+        assert_result_err(result);
+    }
+
+    #[test]
+    fn root_with_options__mod_dialect__script_tokens_accepted() {
+        let text = r###"@cost = 100
+            some-key = $PARAM$
+            name = Rivén_Burrow_Colony_Ship
+            modifier = { trigger = { has-flag = yes } }"###;
+        let options = ParseOptions::new().dialect(&Dialect::STELLARIS_MOD);
+
+        let (_, val) = root_with_options(text, &options).unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![
+                ("@cost", Val::Integer(Number::new("100"))),
+                ("some-key", Val::Identifier("$PARAM$")),
+                ("name", Val::Identifier("Rivén_Burrow_Colony_Ship")),
+                (
+                    "modifier",
+                    Val::Dict(vec![(
+                        "trigger",
+                        Val::Dict(vec![("has-flag", Val::Identifier("yes"))])
+                    )])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn cheat_root__spaced_keys__selected_in_order() {
+        let text = "version=\"v3.4.5\"\nplayer = {\n\tname=\"}\"\n}\nspecies_db =\n{ }\ncountry = { a = b }";
+        let (_, val) = cheat_root(text, vec!["country", "player"]).unwrap();
+        assert_eq!(
+            val,
+            Val::Dict(vec![
+                ("player", Val::Dict(vec![("name", Val::StringLiteral("}"))])),
+                ("country", Val::Dict(vec![("a", Val::Identifier("b"))])),
+            ])
+        );
+    }
+
+    #[test]
+    fn cheat_root__broken_section__err() {
+        let text = "player={ name= }\ncountry={ }";
+        assert_result_err(cheat_root(text, vec!["player", "country"]));
+        assert!(cheat_root(text, vec!["country"]).is_ok());
+    }
 }
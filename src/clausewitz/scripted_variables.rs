@@ -0,0 +1,414 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use super::{number::Number, val::Val};
+
+/// A variable which could not be substituted, with the file and `get_at_path` style path where it
+/// was used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptedVariableError {
+    pub file: String,
+    pub path: String,
+    pub err: String,
+}
+impl Error for ScriptedVariableError {}
+
+impl Display for ScriptedVariableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.file, self.path, self.err)
+    }
+}
+
+/// `@name = value` definitions gathered from `common/scripted_variables/` and the top level of
+/// script files. A later definition replaces an earlier one with the same name.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedVariables<'a> {
+    values: HashMap<&'a str, Val<'a>>,
+}
+
+impl<'a> ScriptedVariables<'a> {
+    pub fn new() -> Self {
+        ScriptedVariables::default()
+    }
+
+    /// Adds the top level definitions of a parsed file. Definitions may refer to variables which
+    /// were defined before them.
+    pub fn collect(&mut self, file: &str, val: &Val<'a>) -> Result<(), Vec<ScriptedVariableError>> {
+        self.define(file, val, &HashMap::new())
+    }
+
+    /// Looks up a variable, with or without its `@`.
+    pub fn get(&self, name: &str) -> Option<&Val<'a>> {
+        self.values.get(name.strip_prefix('@').unwrap_or(name))
+    }
+
+    /// Substitutes every `@name` and `@[ expression ]` in a parsed file. The file's own top level
+    /// definitions are visible to it and take precedence over the collected ones.
+    pub fn resolve(
+        &self,
+        file: &str,
+        val: &Val<'a>,
+    ) -> Result<Val<'a>, Vec<ScriptedVariableError>> {
+        let mut errors = vec![];
+        let mut locals = ScriptedVariables::new();
+        if let Err(mut local_errors) = locals.define(file, val, &self.values) {
+            errors.append(&mut local_errors);
+        }
+        let mut resolver = Resolver {
+            file,
+            variables: &self.values,
+            locals: Some(&locals.values),
+            errors: &mut errors,
+        };
+        let resolved = resolver.resolve(val, &mut vec![]);
+        if errors.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn define(
+        &mut self,
+        file: &str,
+        val: &Val<'a>,
+        globals: &HashMap<&'a str, Val<'a>>,
+    ) -> Result<(), Vec<ScriptedVariableError>> {
+        let mut errors = vec![];
+        for (name, value) in definitions(val) {
+            let mut resolver = Resolver {
+                file,
+                variables: globals,
+                locals: Some(&self.values),
+                errors: &mut errors,
+            };
+            let value = resolver.resolve(value, &mut vec![format!("@{}", name)]);
+            self.values.insert(name, value);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn definitions<'a, 'b>(val: &'b Val<'a>) -> impl Iterator<Item = (&'a str, &'b Val<'a>)> {
+    let entries = match val {
+        Val::Dict(entries) => &entries[..],
+        _ => &[],
+    };
+    entries
+        .iter()
+        .filter_map(|(key, value)| key.strip_prefix('@').map(|name| (name, value)))
+}
+
+struct Resolver<'a, 'b> {
+    file: &'b str,
+    variables: &'b HashMap<&'a str, Val<'a>>,
+    locals: Option<&'b HashMap<&'a str, Val<'a>>>,
+    errors: &'b mut Vec<ScriptedVariableError>,
+}
+
+impl<'a, 'b> Resolver<'a, 'b> {
+    fn lookup(&self, name: &str) -> Option<&'b Val<'a>> {
+        self.locals
+            .and_then(|locals| locals.get(name))
+            .or_else(|| self.variables.get(name))
+    }
+
+    fn error(&mut self, path: &[String], err: String) {
+        self.errors.push(ScriptedVariableError {
+            file: self.file.to_owned(),
+            path: path.join("."),
+            err,
+        });
+    }
+
+    fn resolve(&mut self, val: &Val<'a>, path: &mut Vec<String>) -> Val<'a> {
+        match val {
+            Val::Identifier(id) if id.starts_with("@[") => {
+                let expression = &id[2..id.len() - 1];
+                match evaluate(expression, &|name| self.lookup(name)) {
+                    Ok(value) => number(value),
+                    Err(err) => {
+                        self.error(path, err);
+                        val.clone()
+                    }
+                }
+            }
+            Val::Identifier(id) if id.starts_with('@') => match self.lookup(&id[1..]) {
+                Some(value) => value.clone(),
+                None => {
+                    self.error(path, format!("{} is not defined", id));
+                    val.clone()
+                }
+            },
+            Val::Dict(entries) => Val::Dict(self.resolve_entries(entries, path)),
+            Val::NumberedDict(n, entries) => {
+                Val::NumberedDict(*n, self.resolve_entries(entries, path))
+            }
+            Val::Array(entries) => Val::Array(
+                entries
+                    .iter()
+                    .map(|(i, value)| (*i, self.resolve_child(value, i.to_string(), path)))
+                    .collect(),
+            ),
+            Val::Set(values) => Val::Set(
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| self.resolve_child(value, i.to_string(), path))
+                    .collect(),
+            ),
+            Val::Tagged(tag, value) => Val::Tagged(
+                tag,
+                Box::new(self.resolve_child(value, tag.to_string(), path)),
+            ),
+            Val::Mixed(entries) => Val::Mixed(
+                entries
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (key, value))| {
+                        let segment = key.map_or(i.to_string(), str::to_owned);
+                        (*key, self.resolve_child(value, segment, path))
+                    })
+                    .collect(),
+            ),
+            _ => val.clone(),
+        }
+    }
+
+    fn resolve_entries(
+        &mut self,
+        entries: &[(&'a str, Val<'a>)],
+        path: &mut Vec<String>,
+    ) -> Vec<(&'a str, Val<'a>)> {
+        entries
+            .iter()
+            .map(|(key, value)| (*key, self.resolve_child(value, key.to_string(), path)))
+            .collect()
+    }
+
+    fn resolve_child(&mut self, val: &Val<'a>, segment: String, path: &mut Vec<String>) -> Val<'a> {
+        path.push(segment);
+        let resolved = self.resolve(val, path);
+        path.pop();
+        resolved
+    }
+}
+
+fn number(value: f64) -> Val<'static> {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Val::Integer(Number::from(value as i64))
+    } else {
+        Val::Decimal(Number::from(value))
+    }
+}
+
+/// Evaluates the arithmetic inside `@[ .. ]`: numbers, variables with or without `@`, `+ - * /`,
+/// unary minus and parentheses.
+fn evaluate<'a, 'b>(
+    expression: &str,
+    lookup: &dyn Fn(&str) -> Option<&'b Val<'a>>,
+) -> Result<f64, String>
+where
+    'a: 'b,
+{
+    let mut parser = Expression {
+        rest: expression,
+        lookup,
+    };
+    let value = parser.sum()?;
+    match parser.rest.trim_start() {
+        "" => Ok(value),
+        rest => Err(format!("unexpected {} in @[{}]", rest, expression)),
+    }
+}
+
+struct Expression<'e, 'a, 'b> {
+    rest: &'e str,
+    lookup: &'e dyn Fn(&str) -> Option<&'b Val<'a>>,
+}
+
+impl<'e, 'a, 'b> Expression<'e, 'a, 'b> {
+    fn eat(&mut self, operator: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(operator) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        loop {
+            if self.eat('*') {
+                value *= self.factor()?;
+            } else if self.eat('/') {
+                let divisor = self.factor()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_owned());
+                }
+                value /= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            return Ok(-self.factor()?);
+        }
+        if self.eat('(') {
+            let value = self.sum()?;
+            return if self.eat(')') {
+                Ok(value)
+            } else {
+                Err("missing )".to_owned())
+            };
+        }
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '@')))
+            .unwrap_or(self.rest.len());
+        let (token, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        if token.is_empty() {
+            return Err("expected a number or variable".to_owned());
+        }
+        if let Ok(value) = token.parse() {
+            return Ok(value);
+        }
+        let name = token.strip_prefix('@').unwrap_or(token);
+        match (self.lookup)(name) {
+            Some(Val::Integer(n) | Val::Decimal(n)) => n
+                .as_f64()
+                .ok_or_else(|| format!("{} is not a number", name)),
+            Some(_) => Err(format!("{} is not a number", name)),
+            None => Err(format!("{} is not defined", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{root_with_options, Dialect, ParseOptions};
+
+    fn parse(text: &str) -> Val<'_> {
+        let options = ParseOptions::new().dialect(&Dialect::STELLARIS_MOD);
+        root_with_options(text, &options).unwrap().1
+    }
+
+    #[test]
+    fn resolve__variable_from_other_file__substituted() {
+        let variables_file = parse("@cost = 100\n@upkeep = @cost");
+        let script = parse("ship_size = { cost = @cost upkeep = @upkeep }");
+        let mut variables = ScriptedVariables::new();
+        variables.collect("variables.txt", &variables_file).unwrap();
+
+        let resolved = variables.resolve("ships.txt", &script).unwrap();
+        assert_eq!(resolved, parse("ship_size = { cost = 100 upkeep = 100 }"));
+    }
+
+    #[test]
+    fn resolve__local_definition__shadows_collected() {
+        let mut variables = ScriptedVariables::new();
+        variables
+            .collect("variables.txt", &parse("@cost = 100"))
+            .unwrap();
+
+        let resolved = variables
+            .resolve("ships.txt", &parse("@cost = 5\ncost = @cost"))
+            .unwrap();
+        assert_eq!(resolved, parse("@cost = 5\ncost = 5"));
+    }
+
+    #[test]
+    fn resolve__inline_math__evaluated() {
+        let mut variables = ScriptedVariables::new();
+        variables
+            .collect("variables.txt", &parse("@cost = 100"))
+            .unwrap();
+
+        let resolved = variables
+            .resolve(
+                "ships.txt",
+                &parse("a = @[ cost * 1.5 ]\nb = @[ (@cost - 1) / 4 ]\nc = @[-cost+100]"),
+            )
+            .unwrap();
+        assert_eq!(
+            resolved,
+            Val::Dict(vec![
+                ("a", Val::Integer(Number::from(150))),
+                ("b", Val::Decimal(Number::from(24.75))),
+                ("c", Val::Integer(Number::from(0))),
+            ])
+        );
+    }
+
+    #[test]
+    fn resolve__undefined_variable__located() {
+        let variables = ScriptedVariables::new();
+        let errors = variables
+            .resolve(
+                "ships.txt",
+                &parse("ship_size = { stats = { 5 @missing } math = @[ nope * 2 ] }"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ScriptedVariableError {
+                    file: "ships.txt".to_owned(),
+                    path: "ship_size.stats.1".to_owned(),
+                    err: "@missing is not defined".to_owned(),
+                },
+                ScriptedVariableError {
+                    file: "ships.txt".to_owned(),
+                    path: "ship_size.math".to_owned(),
+                    err: "nope is not defined".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve__division_by_zero__err() {
+        let variables = ScriptedVariables::new();
+        assert!(variables
+            .resolve("ships.txt", &parse("a = @[ 1 / 0 ]"))
+            .is_err());
+    }
+
+    #[test]
+    fn get__with_or_without_at__found() {
+        let mut variables = ScriptedVariables::new();
+        variables
+            .collect("variables.txt", &parse("@cost = 100"))
+            .unwrap();
+        assert!(variables.get("@cost").is_some());
+        assert!(variables.get("cost").is_some());
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use nom::error::{VerboseError, VerboseErrorKind};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    bracketed::{bracketed, key_value},
+    options::ParseOptions,
+    root::root_with_options,
+    skip::{skip_space, string_end, token_end, value_end},
+    val::Val,
+};
+
+/// A section which could not be indexed or parsed, located by byte offset and 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionError {
+    pub key: Option<String>,
+    pub offset: usize,
+    pub line: usize,
+    pub err: String,
+}
+impl Error for SectionError {}
+
+impl Display for SectionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{} (line {}): {}", key, self.line, self.err),
+            None => write!(f, "line {}: {}", self.line, self.err),
+        }
+    }
+}
+
+/// One top level `key=value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section<'a> {
+    pub key: &'a str,
+    /// From the start of the key to the end of the value.
+    pub range: Range<usize>,
+    pub text: &'a str,
+}
+
+/// The top level keys of a document and where their sections are, found without parsing them.
+#[derive(Debug, Clone)]
+pub struct SectionIndex<'a> {
+    input: &'a str,
+    sections: Vec<Section<'a>>,
+}
+
+impl<'a> SectionIndex<'a> {
+    /// Indexes `input` in a single pass which tracks brace depth and skips quoted strings.
+    pub fn new(input: &'a str) -> Result<Self, SectionError> {
+        let bytes = input.as_bytes();
+        let error = |offset: usize, err: &str| SectionError {
+            key: None,
+            offset,
+            line: line(input, offset),
+            err: err.to_owned(),
+        };
+        let mut sections = vec![];
+        let mut i = skip_space(bytes, 0);
+        while i < bytes.len() {
+            let start = i;
+            let key = match bytes[i] {
+                b'"' => {
+                    i = string_end(bytes, i).ok_or_else(|| error(i, "unterminated string"))?;
+                    &input[start + 1..i - 1]
+                }
+                b'{' | b'}' | b'=' => return Err(error(i, "expected a key")),
+                _ => {
+                    i = token_end(bytes, i);
+                    &input[start..i]
+                }
+            };
+            i = skip_space(bytes, i);
+            if bytes.get(i) != Some(&b'=') {
+                return Err(error(i, &format!("expected = after {}", key)));
+            }
+            i = skip_space(bytes, i + 1);
+            if i == bytes.len() {
+                return Err(error(i, &format!("expected a value for {}", key)));
+            }
+            i = value_end(bytes, i).ok_or_else(|| error(i, "unclosed value"))?;
+            sections.push(Section {
+                key,
+                range: start..i,
+                text: &input[start..i],
+            });
+            i = skip_space(bytes, i);
+        }
+        Ok(SectionIndex { input, sections })
+    }
+
+    pub fn sections(&self) -> &[Section<'a>] {
+        &self.sections
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.sections.iter().map(|section| section.key)
+    }
+
+    /// The first section with `key`.
+    pub fn get(&self, key: &str) -> Option<&Section<'a>> {
+        self.sections.iter().find(|section| section.key == key)
+    }
+
+    pub fn parse(&self, key: &str, options: &ParseOptions) -> Result<Val<'a>, SectionError> {
+        match self.get(key) {
+            Some(section) => self.parse_section(section, options),
+            None => Err(SectionError {
+                key: Some(key.to_owned()),
+                offset: self.input.len(),
+                line: line(self.input, self.input.len()),
+                err: "no such section".to_owned(),
+            }),
+        }
+    }
+
+    pub fn parse_section(
+        &self,
+        section: &Section<'a>,
+        options: &ParseOptions,
+    ) -> Result<Val<'a>, SectionError> {
+        let error = |rest: &str, err: String| {
+            let offset = rest.as_ptr() as usize - self.input.as_ptr() as usize;
+            SectionError {
+                key: Some(section.key.to_owned()),
+                offset,
+                line: line(self.input, offset),
+                err,
+            }
+        };
+        if let Ok((rest, Val::Dict(mut entries))) = root_with_options(section.text, options) {
+            if entries.len() == 1 && rest.trim().is_empty() {
+                return Ok(entries.pop().unwrap().1);
+            }
+        }
+        // the combinators say best what went wrong
+        let value = &section.text[value_start(section.text.as_bytes())..];
+        match key_value(section.text, options) {
+            Ok((rest, (_, val))) if rest.trim().is_empty() => Ok(val),
+            Ok((rest, _)) => {
+                let (rest, kind) = furthest(value, options)
+                    .unwrap_or((rest, "unexpected input after the value".to_owned()));
+                Err(error(rest, kind))
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let (rest, kind) = furthest(value, options)
+                    .or_else(|| deepest(&e))
+                    .unwrap_or((section.text, "invalid".to_owned()));
+                Err(error(rest, kind))
+            }
+            Err(nom::Err::Incomplete(_)) => Err(error(
+                &section.text[section.text.len()..],
+                "incomplete".to_owned(),
+            )),
+        }
+    }
+
+    /// Parses every section with one of `keys` in parallel, in document order.
+    pub fn parse_many(
+        &self,
+        keys: &[&str],
+        options: &ParseOptions,
+    ) -> Vec<(&'a str, Result<Val<'a>, SectionError>)> {
+        let sections: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|section| keys.contains(&section.key))
+            .collect();
+        sections
+            .into_par_iter()
+            .map(|section| (section.key, self.parse_section(section, options)))
+            .collect()
+    }
+
+    /// Parses every section in parallel, in document order.
+    pub fn parse_all(
+        &self,
+        options: &ParseOptions,
+    ) -> Vec<(&'a str, Result<Val<'a>, SectionError>)> {
+        (&self.sections[..])
+            .into_par_iter()
+            .map(|section| (section.key, self.parse_section(section, options)))
+            .collect()
+    }
+}
+
+/// The error which got furthest into the section.
+fn deepest<'a>(e: &VerboseError<&'a str>) -> Option<(&'a str, String)> {
+    e.errors
+        .iter()
+        .min_by_key(|(rest, _)| rest.len())
+        .map(|(rest, kind)| {
+            let kind = match kind {
+                VerboseErrorKind::Context(context) => context.to_string(),
+                VerboseErrorKind::Char(c) => format!("expected '{}'", c),
+                VerboseErrorKind::Nom(kind) => format!("{:?}", kind),
+            };
+            (*rest, kind)
+        })
+}
+
+/// Where a block starting at `at`, maybe tagged, stopped parsing. `alt` keeps only the error of
+/// its last branch, which for a block is its opening brace, so the block is parsed again on its
+/// own, and so is any block where that stopped.
+fn furthest<'a>(at: &'a str, options: &ParseOptions) -> Option<(&'a str, String)> {
+    let bytes = at.as_bytes();
+    let open = skip_space(bytes, token_end(bytes, 0));
+    if bytes.get(open) != Some(&b'{') {
+        return None;
+    }
+    let e = match bracketed(&at[open..], options) {
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => e,
+        _ => return None,
+    };
+    let (rest, kind) = deepest(&e)?;
+    furthest(&rest[value_start(rest.as_bytes())..], options).or(Some((rest, kind)))
+}
+
+/// Where the value of the entry at the start of `entry` is, after its key and `=` if it has them.
+fn value_start(entry: &[u8]) -> usize {
+    let token = match entry.first() {
+        Some(b'"') => string_end(entry, 0).unwrap_or(entry.len()),
+        _ => token_end(entry, 0),
+    };
+    match skip_space(entry, token) {
+        equals if entry.get(equals) == Some(&b'=') => skip_space(entry, equals + 1),
+        _ => 0,
+    }
+}
+
+fn line(input: &str, offset: usize) -> usize {
+    input.as_bytes()[..offset.min(input.len())]
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Number;
+
+    #[test]
+    fn new__spaced_and_quoted__all_keys() {
+        let text = "version=\"v3.4.5\"\nplayer = {\n\t{ name=\"a}{\" }\n}\n\"quoted key\"=1\ncolor = rgb { 1 2 3 }\nlast=yes";
+        let index = SectionIndex::new(text).unwrap();
+        assert_eq!(
+            index.keys().collect::<Vec<_>>(),
+            vec!["version", "player", "quoted key", "color", "last"]
+        );
+        assert_eq!(
+            index.get("player").unwrap().text,
+            "player = {\n\t{ name=\"a}{\" }\n}"
+        );
+        assert_eq!(index.get("color").unwrap().text, "color = rgb { 1 2 3 }");
+    }
+
+    #[test]
+    fn new__unclosed_block__err() {
+        let err = SectionIndex::new("a=1\nb={ c=d\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn parse__section__val() {
+        let index = SectionIndex::new("a=1\nb={ c=d }").unwrap();
+        assert_eq!(
+            index.parse("a", &ParseOptions::default()).unwrap(),
+            Val::Integer(Number::new("1"))
+        );
+        assert_eq!(
+            index.parse("b", &ParseOptions::default()).unwrap(),
+            Val::Dict(vec![("c", Val::Identifier("d"))])
+        );
+    }
+
+    #[test]
+    fn parse_many__broken_section__located_err() {
+        let text = "a=1\nb={\n c=d\n e=\n}\nc=3";
+        let index = SectionIndex::new(text).unwrap();
+        let parsed = index.parse_many(&["b", "c"], &ParseOptions::default());
+        assert_eq!(parsed.len(), 2);
+        let (key, result) = &parsed[0];
+        assert_eq!(*key, "b");
+        let err = result.as_ref().unwrap_err();
+        assert_eq!(err.key.as_deref(), Some("b"));
+        assert_eq!(err.line, 4);
+        assert_eq!(parsed[1].1, Ok(Val::Integer(Number::new("3"))));
+    }
+
+    #[test]
+    fn parse__broken_nested_block__innermost_line() {
+        let text = "b=rgb {\n x={\n  y={ z=1 }\n  e=\n }\n}";
+        let index = SectionIndex::new(text).unwrap();
+        let err = index.parse("b", &ParseOptions::default()).unwrap_err();
+        assert_eq!(err.line, 4);
+    }
+
+    #[test]
+    fn parse__missing_key__err() {
+        let index = SectionIndex::new("a=1").unwrap();
+        assert!(index.parse("b", &ParseOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use super::tables::space_table;
+
+/// The kind of container a block looks like from its first complete token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// `{}`
+    Empty,
+    /// `{ a b }`, `{ "a=b" "c" }` or `{ rgb { .. } }`
+    Set,
+    /// `{ 0=a 1=b }`
+    Array,
+    /// `{ key=value }`
+    Dict,
+    /// `{ 14 { key=value } }`
+    NumberedDict,
+    /// `{ { .. } { .. } }`
+    SetOfCollections,
+}
+
+/// Looks at the first token of a block's contents, skipping over quoted strings so that braces and
+/// equals signs inside them are not mistaken for structure.
+#[inline(always)]
+pub fn shape(input: &str) -> Shape {
+    let contents = skip_space(input.as_bytes());
+    let (is_number, rest) = match contents.first() {
+        None => return Shape::Set,
+        Some(b'}') => return Shape::Empty,
+        Some(b'{') => return Shape::SetOfCollections,
+        Some(b'"') => match contents[1..].iter().position(|b| *b == b'"') {
+            Some(end) => (false, &contents[end + 2..]),
+            None => return Shape::Set,
+        },
+        Some(_) => {
+            let end = contents
+                .iter()
+                .position(|b| SPACE[*b as usize] || matches!(b, b'=' | b'{' | b'}' | b'"'))
+                .unwrap_or(contents.len());
+            (is_integer(&contents[..end]), &contents[end..])
+        }
+    };
+
+    match (skip_space(rest).first(), is_number) {
+        (Some(b'='), true) => Shape::Array,
+        (Some(b'='), false) => Shape::Dict,
+        (Some(b'{'), true) => Shape::NumberedDict,
+        _ => Shape::Set,
+    }
+}
+
+const SPACE: [bool; 256] = space_table();
+
+#[inline(always)]
+fn skip_space(input: &[u8]) -> &[u8] {
+    let start = input
+        .iter()
+        .position(|b| !SPACE[*b as usize])
+        .unwrap_or(input.len());
+    &input[start..]
+}
+
+#[inline(always)]
+fn is_integer(token: &[u8]) -> bool {
+    let digits = token.strip_prefix(b"-").unwrap_or(token);
+    !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shape__empty__empty() {
+        assert_eq!(shape("}"), Shape::Empty);
+        assert_eq!(shape(" \n\t}"), Shape::Empty);
+    }
+
+    #[test]
+    fn shape__key_value__dict() {
+        assert_eq!(shape("key=value }"), Shape::Dict);
+        assert_eq!(shape("key = value }"), Shape::Dict);
+        assert_eq!(shape("\"quoted key\"=value }"), Shape::Dict);
+    }
+
+    #[test]
+    fn shape__number_value__array() {
+        assert_eq!(shape("0=a 1=b }"), Shape::Array);
+    }
+
+    #[test]
+    fn shape__number_block__numbered_dict() {
+        assert_eq!(shape("14 { intel=0 } }"), Shape::NumberedDict);
+    }
+
+    #[test]
+    fn shape__block__set_of_collections() {
+        assert_eq!(shape("{ a=b } { c=d } }"), Shape::SetOfCollections);
+    }
+
+    #[test]
+    fn shape__quoted_equals__set() {
+        assert_eq!(shape("\"a=b\" \"c\" }"), Shape::Set);
+    }
+
+    #[test]
+    fn shape__quoted_braces__set() {
+        assert_eq!(shape("\"Ship {1}\" }"), Shape::Set);
+    }
+
+    #[test]
+    fn shape__values_then_pair__set() {
+        assert_eq!(shape("1 2 key=value }"), Shape::Set);
+    }
+
+    #[test]
+    fn shape__tagged_block__set() {
+        assert_eq!(shape("rgb { 1 2 3 } }"), Shape::Set);
+    }
+
+    #[test]
+    fn shape__accented_token__set() {
+        assert_eq!(shape("Rivén Burrow }"), Shape::Set);
+    }
+
+    #[test]
+    fn shape__unterminated_string__set() {
+        assert_eq!(shape("\"never closed"), Shape::Set);
+    }
+}
@@ -5,35 +5,38 @@ use std::arch::x86_64::{
 };
 use std::cmp::min;
 
-//the range of all the characters which should be REJECTED
+//the range of all the characters which should be REJECTED, everything but the bytes of
+//`tables::space_table`
 pub const SPACE_RANGES: &[u8; 16] = &[
-    b'\x00', b'\x08', b'\x0e', b'\x1f', b'!', b'\xff', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00',
+    b'\x00', b'\x08', b'\x0b', b'\x0c', b'\x0e', b'\x1f', b'!', b'\xff', b'\x00', b'\x00', b'\x00',
     b'\x00', b'\x00', b'\x00', b'\x00', b'\x00',
 ];
-pub const NOT_TOKEN_RANGES: &[u8; 16] = &[
-    b'=', b'=', b'{', b'{', b'}', b'}', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00',
-    b'\x00', b'\x00', b'\x00', b'\x00',
-];
 pub const STRING_LITTERAL_CONTENT_RANGES: &[u8; 16] = &[
     b'"', b'"', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00', b'\x00',
     b'\x00', b'\x00', b'\x00', b'\x00', b'\x00',
 ];
 
-pub const IDENTIFIER_RANGES: &[u8; 16] = &[
-    b'\x00', b'\x2d', b'\x2f', b'\x2f', b';', b'@', b'[', b'^', b'`', b'`', b'{', b'\xff', b'\x00',
-    b'\x00', b'\x00', b'\x00',
-];
-
 const CHUNK_SIZE: usize = 16;
 
 use nom::error::{ParseError, VerboseError};
 
-use super::tables::{is_identifier_char, is_space, is_string_litteral_contents, is_token};
+use super::dialect::Dialect;
+use super::tables::{is_space, is_string_litteral_contents};
 use super::Res;
 
+/// Short inputs are scanned a byte at a time against the dialect's table; a dialect allows either
+/// all or none of the non-ASCII bytes, so the split always lands on a character boundary.
 #[inline(always)]
-pub fn take_simd_identifier<'a>(input: &'a str) -> Res<&'a str, &'a str> {
-    take_while_simd::<'a, _, VerboseError<&'a str>>(is_identifier_char, IDENTIFIER_RANGES)(input)
+pub fn take_simd_identifier<'a>(input: &'a str, dialect: &Dialect) -> Res<&'a str, &'a str> {
+    if input.len() >= CHUNK_SIZE {
+        simd_loop16(input, &dialect.identifier_ranges)
+    } else {
+        let end = input
+            .bytes()
+            .position(|b| !dialect.is_identifier_byte(b))
+            .unwrap_or(input.len());
+        Ok((&input[end..], &input[..end]))
+    }
 }
 
 #[inline(always)]
@@ -44,14 +47,6 @@ pub fn take_simd_string_literal<'a>(input: &'a str) -> Res<&'a str, &'a str> {
     )(input)
 }
 
-#[inline(always)]
-pub fn take_simd_not_token<'a>(input: &'a str) -> Res<&'a str, &'a str> {
-    take_while_simd::<'a, _, VerboseError<&'a str>>(
-        move |character| !is_token(character),
-        NOT_TOKEN_RANGES,
-    )(input)
-}
-
 #[inline(always)]
 pub fn take_simd_space<'a>(input: &'a str) -> Res<&'a str, &'a str> {
     take_while_simd::<'a, _, VerboseError<&'a str>>(
@@ -201,4 +196,18 @@ mod tests {
         assert_eq!(remainder, "");
         assert_eq!(parsed, " \t\n\r");
     }
+
+    #[test]
+    fn take_while_simd__vertical_tab_and_form_feed__same_as_space_table() {
+        for odd in ["\x0b", "\x0c"] {
+            for spaces in [1, 20] {
+                let text = format!("{}{}a", "\t".repeat(spaces), odd);
+                let (remainder, parsed) =
+                    take_while_simd::<'_, _, VerboseError<&str>>(is_space, SPACE_RANGES)(&text)
+                        .unwrap();
+                assert_eq!(parsed, "\t".repeat(spaces));
+                assert_eq!(remainder, format!("{}a", odd));
+            }
+        }
+    }
 }
@@ -2,21 +2,25 @@ use std::vec;
 
 use nom::{
     branch::alt,
-    bytes::complete::{take, take_while},
+    bytes::complete::take_while,
     character::complete::{char, digit1},
-    combinator::{cut, map, opt, recognize, verify},
+    combinator::{cut, map, opt, peek, recognize, verify},
     error::{ParseError, VerboseError, VerboseErrorKind},
     multi::separated_list0,
-    sequence::{delimited, preceded, separated_pair, tuple},
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
     AsChar, IResult, InputIter, InputLength, Needed, Parser, Slice,
 };
 const CHUNK_SIZE: usize = 16;
 use super::{
-    simd::{take_simd_identifier, take_simd_not_token, take_simd_space, take_simd_string_literal},
+    dialect::Dialect,
+    shape::{shape, Shape},
+    simd::{take_simd_identifier, take_simd_space, take_simd_string_literal},
     tables::{identifier_table, is_digit},
 };
 pub mod isp;
+pub mod query;
 use isp::*;
+pub use query::{Found, Query, Skim, SkimError, Want};
 type SR<X, PARSED> = IResult<X, PARSED, VerboseError<X>>;
 
 pub fn opt_space<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, ISP<'a, 'b>> {
@@ -88,7 +92,11 @@ pub fn string_literal<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, ISP<'a, 'b>
 ///I think if we reach quoted or unquoted, we've found our value
 pub fn quoted<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>> {
     map(
-        delimited(char('\"'), cut(alt((date, string_literal))), char('\"')),
+        delimited(
+            char('\"'),
+            cut(alt((terminated(date, peek(char('\"'))), string_literal))),
+            char('\"'),
+        ),
         |isp| vec![isp],
     )(input)
 }
@@ -110,7 +118,30 @@ pub fn unquoted<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>>
     map(alt((date, decimal, integer, identifier)), |isp| vec![isp])(input)
 }
 pub fn value<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>> {
-    alt((bracketed, quoted, unquoted))(input)
+    alt((bracketed, quoted, tagged, unquoted))(input)
+}
+
+/// The tag of `rgb { .. }` can be named in the search path, otherwise it is looked through
+pub fn tagged<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>> {
+    let (rem_tag, tag) = terminated(identifier, opt_space)(input)?;
+    if tag.search_path_index < 10 && tag.slice == tag.search_path[tag.search_path_index] {
+        let rem_tag = ISP {
+            slice: rem_tag.slice,
+            search_path: rem_tag.search_path,
+            search_path_index: rem_tag.search_path_index + 1,
+        };
+        let (rem_val, val) = bracketed(rem_tag)?;
+        Ok((
+            ISP {
+                slice: rem_val.slice,
+                search_path: rem_val.search_path,
+                search_path_index: rem_val.search_path_index - 1,
+            },
+            val,
+        ))
+    } else {
+        bracketed(rem_tag)
+    }
 }
 
 pub fn bracketed<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>> {
@@ -211,34 +242,17 @@ pub fn numbered_dict<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a,
 }
 
 pub fn contents<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>> {
-    let (remainder, maybe_key_number_identifier) = not_token(input)?;
-
-    let (_remainder, next_token) = take(1 as usize)(remainder)?;
-
-    if next_token.slice == "}" {
-        return cut(set)(input);
-    } else if next_token.slice == "=" {
-        let (_rem, maybe_ident) = identifier_simd(maybe_key_number_identifier)?;
-        return if let Ok(_) = maybe_ident.slice.parse::<i64>() {
-            cut(array)(input)
-        } else {
-            cut(dict)(input)
-        };
-    } else if next_token.slice == "{" {
-        return if integer(maybe_key_number_identifier).is_ok() {
-            cut(numbered_dict)(input)
-        } else {
-            cut(set_of_collections)(input)
-        };
-    } else {
-        println!("AFTER: {}", input.slice);
-        println!("{}", next_token.slice);
-        panic!("Token = or }} not found, possibly missing a closing brace somewhere?")
-    };
+    match shape(input.slice) {
+        Shape::Empty | Shape::Set => cut(set)(input),
+        Shape::Array => cut(array)(input),
+        Shape::Dict => cut(dict)(input),
+        Shape::NumberedDict => cut(numbered_dict)(input),
+        Shape::SetOfCollections => cut(set_of_collections)(input),
+    }
 }
 
 fn identifier_simd<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, ISP<'a, 'b>> {
-    match take_simd_identifier(input.slice) {
+    match take_simd_identifier(input.slice, &Dialect::STELLARIS_SAVE) {
         Ok((rem, spaces)) => Ok((
             ISP {
                 slice: rem,
@@ -261,29 +275,6 @@ fn identifier_simd<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, ISP<'a, 'b>> {
     }
 }
 
-fn not_token<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, ISP<'a, 'b>> {
-    match take_simd_not_token(input.slice) {
-        Ok((rem, spaces)) => Ok((
-            ISP {
-                slice: rem,
-                search_path: input.search_path,
-                search_path_index: input.search_path_index,
-            },
-            ISP {
-                slice: spaces,
-                search_path: input.search_path,
-                search_path_index: input.search_path_index,
-            },
-        )),
-        Err(e) => Err(e.map(|e| VerboseError {
-            errors: e
-                .errors
-                .into_iter()
-                .map(|(str, vbe)| (input, VerboseErrorKind::Context("whatever")))
-                .collect(),
-        })),
-    }
-}
 fn key_value<'a, 'b>(input: ISP<'a, 'b>) -> SR<ISP<'a, 'b>, Vec<ISP<'a, 'b>>> {
     match preceded(opt_space, key)(input) {
         Ok((mut rem_key, key)) => {
@@ -359,6 +350,22 @@ mod tests {
         assert_eq!(&"25.5", &expected.slice);
     }
 
+    #[test]
+    fn search_document__tagged_block__found() {
+        let str = r###"flag={
+            colors={
+                rgb { 255 0 0 }
+            }
+            background=hsv { 0.5 0.5 1.0 }
+        }"###;
+        let input = ISP::create(&str, "flag.background.hsv");
+
+        let (_rem, found) = search_document(input).unwrap();
+
+        let found = found.iter().map(|isp| isp.slice).collect::<Vec<_>>();
+        assert_eq!(vec!["0.5", "0.5", "1.0"], found);
+    }
+
     #[test]
     fn asdf() {
         let str = r###"country = {
@@ -0,0 +1,364 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::clausewitz::{
+    options::ParseOptions,
+    path::{PathPattern, Step},
+    skip::{token_end, value_end},
+    val::Val,
+    value::value,
+    walk::{self, block, tagged, Visitor, Walked},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkimError {
+    pub offset: usize,
+    pub err: String,
+}
+impl Error for SkimError {}
+
+impl Display for SkimError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.err, self.offset)
+    }
+}
+
+/// What to report about the paths a query matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Want {
+    Value,
+    Span,
+    Exists,
+    Count,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub pattern: PathPattern,
+    pub want: Want,
+}
+
+impl Query {
+    /// A query is answered by its first match unless it has wildcards or counts.
+    fn is_exact(&self) -> bool {
+        self.pattern.is_exact() && self.want != Want::Count
+    }
+}
+
+/// The answer to one query, with matches in document order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Found<'a> {
+    Values(Vec<(Vec<Step<'a>>, Val<'a>)>),
+    Spans(Vec<(Vec<Step<'a>>, Range<usize>)>),
+    Exists(bool),
+    Count(usize),
+}
+
+/// Many path queries answered in one pass over a document. Only the matched values are parsed,
+/// subtrees no query can reach are stepped over, and the pass stops as soon as every query
+/// without wildcards has been answered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Skim {
+    queries: Vec<Query>,
+}
+
+impl Skim {
+    pub fn new() -> Self {
+        Skim::default()
+    }
+
+    pub fn query<P: Into<PathPattern>>(mut self, pattern: P, want: Want) -> Self {
+        self.queries.push(Query {
+            pattern: pattern.into(),
+            want,
+        });
+        self
+    }
+
+    pub fn value<P: Into<PathPattern>>(self, pattern: P) -> Self {
+        self.query(pattern, Want::Value)
+    }
+
+    pub fn span<P: Into<PathPattern>>(self, pattern: P) -> Self {
+        self.query(pattern, Want::Span)
+    }
+
+    pub fn exists<P: Into<PathPattern>>(self, pattern: P) -> Self {
+        self.query(pattern, Want::Exists)
+    }
+
+    pub fn count<P: Into<PathPattern>>(self, pattern: P) -> Self {
+        self.query(pattern, Want::Count)
+    }
+
+    pub fn queries(&self) -> &[Query] {
+        &self.queries
+    }
+
+    /// Answers every query, in the order they were added.
+    pub fn run<'a>(
+        &self,
+        input: &'a str,
+        options: &ParseOptions,
+    ) -> Result<Vec<Found<'a>>, SkimError> {
+        let mut skimmer = Skimmer {
+            input,
+            queries: &self.queries,
+            options,
+            path: vec![],
+            found: self
+                .queries
+                .iter()
+                .map(|query| match query.want {
+                    Want::Value => Found::Values(vec![]),
+                    Want::Span => Found::Spans(vec![]),
+                    Want::Exists => Found::Exists(false),
+                    Want::Count => Found::Count(0),
+                })
+                .collect(),
+            done: vec![false; self.queries.len()],
+            remaining: self.queries.iter().filter(|query| query.is_exact()).count(),
+            open: self.queries.iter().any(|query| !query.is_exact()),
+        };
+        match walk::entries(&mut skimmer, 0, false) {
+            Ok(_) => Ok(skimmer.found),
+            Err((offset, err)) => Err(SkimError {
+                offset,
+                err: err.to_owned(),
+            }),
+        }
+    }
+}
+
+struct Skimmer<'a, 'q> {
+    input: &'a str,
+    queries: &'q [Query],
+    options: &'q ParseOptions,
+    path: Vec<Step<'a>>,
+    found: Vec<Found<'a>>,
+    done: Vec<bool>,
+    /// Exact queries still unanswered.
+    remaining: usize,
+    /// Whether any query can match more than once.
+    open: bool,
+}
+
+impl<'a> Visitor<'a> for Skimmer<'a, '_> {
+    type Item = ();
+
+    fn input(&self) -> &'a str {
+        self.input
+    }
+
+    fn path(&mut self) -> &mut Vec<Step<'a>> {
+        &mut self.path
+    }
+
+    fn visit(&mut self, _: Option<&'a str>, start: usize) -> Walked<(usize, Option<()>)> {
+        Ok((self.answer(start)?, None))
+    }
+
+    fn finished(&self) -> bool {
+        self.remaining == 0 && !self.open
+    }
+}
+
+impl<'a> Skimmer<'a, '_> {
+    fn wanted_below(&self) -> bool {
+        self.queries
+            .iter()
+            .zip(&self.done)
+            .any(|(query, done)| !done && query.pattern.extends(&self.path))
+    }
+
+    /// Answers the queries matching the current path with the value at `start`, then looks
+    /// inside it if any query could match deeper.
+    fn answer(&mut self, start: usize) -> Walked<usize> {
+        let bytes = self.input.as_bytes();
+        let mut parsed: Option<(usize, Val<'a>)> = None;
+        let mut end = None;
+        for (i, query) in self.queries.iter().enumerate() {
+            if self.done[i] || !query.pattern.matches(&self.path) {
+                continue;
+            }
+            match &mut self.found[i] {
+                Found::Values(values) => {
+                    if parsed.is_none() {
+                        let (rest, val) = value(&self.input[start..], self.options)
+                            .map_err(|_| (start, "invalid value"))?;
+                        parsed = Some((self.input.len() - rest.len(), val));
+                    }
+                    let (value_end, val) = parsed.as_ref().unwrap();
+                    end = Some(*value_end);
+                    values.push((self.path.clone(), val.clone()));
+                }
+                Found::Spans(spans) => {
+                    let span_end = match end {
+                        Some(end) => end,
+                        None => value_end(bytes, start).ok_or((start, "unclosed value"))?,
+                    };
+                    end = Some(span_end);
+                    spans.push((self.path.clone(), start..span_end));
+                }
+                Found::Exists(exists) => *exists = true,
+                Found::Count(count) => *count += 1,
+            }
+            if query.is_exact() {
+                self.done[i] = true;
+                self.remaining -= 1;
+            }
+        }
+        if self.finished() {
+            return Ok(start);
+        }
+        match (self.wanted_below(), end) {
+            (true, _) => self.descend(start),
+            (false, Some(end)) => Ok(end),
+            (false, None) => value_end(bytes, start).ok_or((start, "unclosed value")),
+        }
+    }
+
+    fn descend(&mut self, start: usize) -> Walked<usize> {
+        let bytes = self.input.as_bytes();
+        match bytes.get(start) {
+            Some(b'{') => Ok(block(self, start)?.0),
+            Some(b'"') | None => value_end(bytes, start).ok_or((start, "unclosed value")),
+            Some(_) => {
+                let Some((tag, open)) = tagged(self.input, start) else {
+                    return Ok(token_end(bytes, start));
+                };
+                // `color.rgb` names the tag, `color.0` looks through it
+                self.path.push(Step::Key(tag));
+                let named = self.queries.iter().zip(&self.done).any(|(query, done)| {
+                    !done
+                        && (query.pattern.matches(&self.path) || query.pattern.extends(&self.path))
+                });
+                let end = match named {
+                    true => self.answer(open),
+                    false => {
+                        self.path.pop();
+                        return self.descend(open);
+                    }
+                };
+                self.path.pop();
+                end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Number;
+
+    const TEXT: &str = r###"version="v3.4.5"
+country={
+	0={
+		name="Earth"
+		budget={ income=10 }
+		color=rgb { 1 2 3 }
+	}
+	1={
+		name="Mars }"
+		budget={ income=5 }
+	}
+}
+species={ { name=human } { name=blorg } }
+"###;
+
+    fn value<'a>(found: &Found<'a>) -> Vec<Val<'a>> {
+        match found {
+            Found::Values(values) => values.iter().map(|(_, v)| v.clone()).collect(),
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn run__many_paths__answered_in_query_order() {
+        let skim = Skim::new()
+            .value("country.1.budget.income")
+            .value("version")
+            .value("species.1.name")
+            .value("country.0.color.rgb");
+        let found = skim.run(TEXT, &ParseOptions::default()).unwrap();
+        assert_eq!(value(&found[0]), vec![Val::Integer(Number::new("5"))]);
+        assert_eq!(value(&found[1]), vec![Val::StringLiteral("v3.4.5")]);
+        assert_eq!(value(&found[2]), vec![Val::Identifier("blorg")]);
+        assert_eq!(
+            value(&found[3]),
+            vec![Val::Set(vec![
+                Val::Integer(Number::new("1")),
+                Val::Integer(Number::new("2")),
+                Val::Integer(Number::new("3")),
+            ])]
+        );
+    }
+
+    #[test]
+    fn run__wildcard__every_match_with_path() {
+        let found = Skim::new()
+            .value("country.*.name")
+            .run(TEXT, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![Found::Values(vec![
+                (
+                    vec![Step::Key("country"), Step::Key("0"), Step::Key("name")],
+                    Val::StringLiteral("Earth")
+                ),
+                (
+                    vec![Step::Key("country"), Step::Key("1"), Step::Key("name")],
+                    Val::StringLiteral("Mars }")
+                ),
+            ])]
+        );
+    }
+
+    #[test]
+    fn run__exists_and_count__answered() {
+        let found = Skim::new()
+            .exists("country.0.budget")
+            .exists("country.2")
+            .count("country.*")
+            .count("species.*.name")
+            .run(TEXT, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                Found::Exists(true),
+                Found::Exists(false),
+                Found::Count(2),
+                Found::Count(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn run__span__byte_range() {
+        let found = Skim::new()
+            .span("country.0.budget")
+            .run(TEXT, &ParseOptions::default())
+            .unwrap();
+        match &found[0] {
+            Found::Spans(spans) => assert_eq!(&TEXT[spans[0].1.clone()], "{ income=10 }"),
+            _ => panic!("expected spans"),
+        }
+    }
+
+    #[test]
+    fn run__exact_paths_found__stops_before_broken_tail() {
+        let text = "version=1\ncountry={ 0={ name=a } }\nbroken={ {";
+        let skim = Skim::new().value("country.0.name");
+        assert!(skim.run(text, &ParseOptions::default()).is_ok());
+        assert!(skim
+            .count("version")
+            .run(text, &ParseOptions::default())
+            .is_err());
+    }
+}
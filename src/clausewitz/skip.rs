@@ -0,0 +1,97 @@
+//! Byte scanners which step over text without parsing or allocating. Positions are byte offsets
+//! into the scanned slice; `None` means the text ended before the construct did.
+
+use super::tables::space_table;
+
+const SPACE: [bool; 256] = space_table();
+
+#[inline(always)]
+pub fn skip_space(bytes: &[u8], from: usize) -> usize {
+    bytes[from..]
+        .iter()
+        .position(|b| !SPACE[*b as usize])
+        .map_or(bytes.len(), |i| from + i)
+}
+
+/// The end of an unquoted token such as a key, number or identifier.
+#[inline(always)]
+pub fn token_end(bytes: &[u8], from: usize) -> usize {
+    bytes[from..]
+        .iter()
+        .position(|b| SPACE[*b as usize] || matches!(b, b'=' | b'{' | b'}' | b'"'))
+        .map_or(bytes.len(), |i| from + i)
+}
+
+/// The position after the quote closing the string opened at `from`.
+#[inline(always)]
+pub fn string_end(bytes: &[u8], from: usize) -> Option<usize> {
+    bytes[from + 1..]
+        .iter()
+        .position(|b| *b == b'"')
+        .map(|i| from + i + 2)
+}
+
+/// The position after the brace closing the block opened at `from`.
+pub fn block_end(bytes: &[u8], from: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            b'"' => {
+                i = string_end(bytes, i)?;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The end of the value starting at `from`: a string, a block, a token, or a tagged block like
+/// `rgb { .. }`.
+pub fn value_end(bytes: &[u8], from: usize) -> Option<usize> {
+    match bytes.get(from)? {
+        b'{' => block_end(bytes, from),
+        b'"' => string_end(bytes, from),
+        b'}' | b'=' => None,
+        _ => {
+            let end = token_end(bytes, from);
+            let next = skip_space(bytes, end);
+            match bytes.get(next) {
+                Some(b'{') => block_end(bytes, next),
+                _ => Some(end),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_end__block_with_quoted_braces__after_close() {
+        let text = b"{ a=\"}\" b={ c } } rest";
+        assert_eq!(value_end(text, 0), Some(17));
+    }
+
+    #[test]
+    fn value_end__tagged__after_block() {
+        assert_eq!(value_end(b"rgb { 1 2 3 } x", 0), Some(13));
+        assert_eq!(value_end(b"yes\nb=1", 0), Some(3));
+    }
+
+    #[test]
+    fn value_end__unclosed__none() {
+        assert_eq!(value_end(b"{ a={ }", 0), None);
+        assert_eq!(value_end(b"\"open", 0), None);
+    }
+}
@@ -0,0 +1,465 @@
+//! A first pass over a whole document in the style of simdjson's stage 1: 64 bytes at a time,
+//! SSE2 comparisons build bitmasks of quotes, braces, `=` and whitespace, and from those the
+//! offsets of every structural byte and token start outside strings. A second pass pairs up the
+//! braces, so any block can be skipped or handed to the parser on its own.
+//!
+//! The index holds a position for every token, about 69 MiB for a 41 MiB gamestate, which pays
+//! off when blocks are walked again and again. [`super::section::SectionIndex`] and the
+//! [`super::skip`] helpers step over a block once and allocate nothing, so they scan the bytes
+//! themselves rather than build an index first; tokens and strings end where `skip` says.
+//!
+//! Blocks found through the index are parsed by [`super::builder`] from their text, which
+//! tokenizes them again. A parser driven by the index positions alone, with no second look at
+//! the bytes, is out of scope here: the builder's lexer classifies a token while it finds its
+//! end, so walking the positions would save little of its work.
+
+use std::{
+    arch::x86_64::{__m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_set1_epi8},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use nom::error::{ErrorKind, ParseError, VerboseError};
+
+use super::{
+    builder::{Builder, Decline},
+    options::ParseOptions,
+    skip::{string_end, token_end},
+    val::Val,
+    Res,
+};
+
+const BLOCK_SIZE: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructureError {
+    pub offset: usize,
+    pub err: String,
+}
+impl Error for StructureError {}
+
+impl Display for StructureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.err, self.offset)
+    }
+}
+
+/// The structure of a document: where its braces, `=` and tokens are, and which braces match.
+#[derive(Debug, Clone)]
+pub struct StructuralIndex<'a> {
+    input: &'a str,
+    /// Offsets of `{`, `}` and `=` outside strings, and of the first byte of every other token,
+    /// including the opening quote of strings.
+    positions: Vec<u32>,
+    /// For a brace, the index of the brace it matches; for anything else, its own index.
+    partners: Vec<u32>,
+}
+
+impl<'a> StructuralIndex<'a> {
+    pub fn new(input: &'a str) -> Result<Self, StructureError> {
+        if input.len() > u32::MAX as usize {
+            return Err(StructureError {
+                offset: u32::MAX as usize,
+                err: "input too large to index".to_owned(),
+            });
+        }
+        let positions = positions(input.as_bytes())?;
+        let partners = partners(input.as_bytes(), &positions)?;
+        Ok(StructuralIndex {
+            input,
+            positions,
+            partners,
+        })
+    }
+
+    pub fn input(&self) -> &'a str {
+        self.input
+    }
+
+    pub fn positions(&self) -> &[u32] {
+        &self.positions
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// The byte offset of the `i`th position.
+    pub fn offset(&self, i: usize) -> usize {
+        self.positions[i] as usize
+    }
+
+    /// The byte at the `i`th position.
+    pub fn byte(&self, i: usize) -> u8 {
+        self.input.as_bytes()[self.offset(i)]
+    }
+
+    /// The index of the brace matching the brace at `i`.
+    pub fn matching(&self, i: usize) -> Option<usize> {
+        match self.byte(i) {
+            b'{' | b'}' => Some(self.partners[i] as usize),
+            _ => None,
+        }
+    }
+
+    /// The index of the position at byte `offset`.
+    pub fn find(&self, offset: usize) -> Option<usize> {
+        self.positions.binary_search(&(offset as u32)).ok()
+    }
+
+    /// The byte offset just after the block opening at byte `offset`.
+    pub fn block_end(&self, offset: usize) -> Option<usize> {
+        let open = self.find(offset)?;
+        (self.byte(open) == b'{').then(|| self.offset(self.partners[open] as usize) + 1)
+    }
+
+    /// The positions directly inside the block opening at `open`, stepping over nested blocks.
+    pub fn children(&self, open: usize) -> Children<'_, 'a> {
+        Children {
+            index: self,
+            next: open + 1,
+            end: self.partners[open] as usize,
+        }
+    }
+
+    /// The positions outside every block.
+    pub fn top_level(&self) -> Children<'_, 'a> {
+        Children {
+            index: self,
+            next: 0,
+            end: self.len(),
+        }
+    }
+
+    /// The byte ranges of the top level `key=value` entries, the same as
+    /// [`super::section::SectionIndex`] finds.
+    pub fn sections(&self) -> Result<Vec<Range<usize>>, StructureError> {
+        let error = |offset: usize, err: String| StructureError { offset, err };
+        let at = |i: Option<usize>| i.map_or(self.input.len(), |i| self.offset(i));
+        let mut sections = vec![];
+        let mut top_level = self.top_level();
+        while let Some(key) = top_level.next() {
+            let start = self.offset(key);
+            if matches!(self.byte(key), b'{' | b'}' | b'=') {
+                return Err(error(start, "expected a key".to_owned()));
+            }
+            let name = &self.input[start..self.end(key)];
+            match top_level.next() {
+                Some(equals) if self.byte(equals) == b'=' => {}
+                other => return Err(error(at(other), format!("expected = after {}", name))),
+            }
+            let value = match top_level.next() {
+                Some(value) if self.byte(value) != b'=' => value,
+                other => return Err(error(at(other), format!("expected a value for {}", name))),
+            };
+            let mut end = self.end(value);
+            // a tagged block like `rgb { .. }`
+            if let Some(block) = top_level.peek() {
+                if self.byte(block) == b'{' && !matches!(self.byte(value), b'{' | b'"') {
+                    top_level.next();
+                    end = self.end(block);
+                }
+            }
+            sections.push(start..end);
+        }
+        Ok(sections)
+    }
+
+    /// Parses the block opening at `open` on its own.
+    pub fn parse_block(&self, open: usize, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+        let close = self.partners[open] as usize;
+        let text = &self.input[self.offset(open)..=self.offset(close)];
+        match Builder::new(text, options).block(0) {
+            Ok((end, val)) => Ok((&text[end..], val)),
+            Err(Decline) => Err(nom::Err::Error(VerboseError::from_error_kind(
+                text,
+                ErrorKind::Verify,
+            ))),
+        }
+    }
+
+    /// The byte offset just after the token or block at `i`.
+    fn end(&self, i: usize) -> usize {
+        let bytes = self.input.as_bytes();
+        let start = self.offset(i);
+        match bytes[start] {
+            b'{' => self.offset(self.partners[i] as usize) + 1,
+            b'}' | b'=' => start + 1,
+            // `new` has checked every string is closed
+            b'"' => string_end(bytes, start).unwrap_or(bytes.len()),
+            _ => token_end(bytes, start),
+        }
+    }
+}
+
+/// Positions at one depth, see [`StructuralIndex::children`].
+#[derive(Debug, Clone)]
+pub struct Children<'i, 'a> {
+    index: &'i StructuralIndex<'a>,
+    next: usize,
+    end: usize,
+}
+
+impl Children<'_, '_> {
+    fn peek(&self) -> Option<usize> {
+        (self.next < self.end).then_some(self.next)
+    }
+}
+
+impl Iterator for Children<'_, '_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let i = self.peek()?;
+        self.next = match self.index.byte(i) {
+            b'{' => self.index.partners[i] as usize + 1,
+            _ => i + 1,
+        };
+        Some(i)
+    }
+}
+
+/// Bitmasks of one 64 byte block, bit `n` standing for byte `n`.
+struct Masks {
+    quote: u64,
+    open: u64,
+    close: u64,
+    equals: u64,
+    space: u64,
+}
+
+#[inline(always)]
+fn masks(block: &[u8; BLOCK_SIZE]) -> Masks {
+    // SAFETY: SSE2 is required by the crate, and every load reads 16 bytes inside `block`
+    unsafe {
+        let chunks: [__m128i; 4] = [
+            _mm_loadu_si128(block.as_ptr() as *const __m128i),
+            _mm_loadu_si128(block.as_ptr().add(16) as *const __m128i),
+            _mm_loadu_si128(block.as_ptr().add(32) as *const __m128i),
+            _mm_loadu_si128(block.as_ptr().add(48) as *const __m128i),
+        ];
+        let equal_to = |byte: u8| {
+            let needle = _mm_set1_epi8(byte as i8);
+            chunks.iter().enumerate().fold(0u64, |mask, (n, chunk)| {
+                let bits = _mm_movemask_epi8(_mm_cmpeq_epi8(*chunk, needle)) as u16 as u64;
+                mask | bits << (n * 16)
+            })
+        };
+        Masks {
+            quote: equal_to(b'"'),
+            open: equal_to(b'{'),
+            close: equal_to(b'}'),
+            equals: equal_to(b'='),
+            space: equal_to(b' ') | equal_to(b'\t') | equal_to(b'\n') | equal_to(b'\r'),
+        }
+    }
+}
+
+/// Each bit becomes the xor of itself and every bit below it, which turns quote bits into a mask
+/// of the bytes from an opening quote up to, not including, its closing quote.
+#[inline(always)]
+fn prefix_xor(mut bits: u64) -> u64 {
+    bits ^= bits << 1;
+    bits ^= bits << 2;
+    bits ^= bits << 4;
+    bits ^= bits << 8;
+    bits ^= bits << 16;
+    bits ^= bits << 32;
+    bits
+}
+
+fn positions(bytes: &[u8]) -> Result<Vec<u32>, StructureError> {
+    let mut positions = Vec::with_capacity(bytes.len() / 4);
+    // all ones while a string is open across blocks
+    let mut in_string_carry = 0u64;
+    // whether the last byte of the previous block was part of a token
+    let mut token_carry = 0u64;
+    let mut padded = [b' '; BLOCK_SIZE];
+    for (n, chunk) in bytes.chunks(BLOCK_SIZE).enumerate() {
+        let block = match <&[u8; BLOCK_SIZE]>::try_from(chunk) {
+            Ok(block) => block,
+            Err(_) => {
+                padded[..chunk.len()].copy_from_slice(chunk);
+                &padded
+            }
+        };
+        let masks = masks(block);
+        let in_string = prefix_xor(masks.quote) ^ in_string_carry;
+        in_string_carry = ((in_string as i64) >> 63) as u64;
+
+        let operators = masks.open | masks.close | masks.equals;
+        let token = !(operators | masks.space | masks.quote) & !in_string;
+        let token_start = token & !(token << 1 | token_carry);
+        token_carry = token >> 63;
+        let opening_quote = masks.quote & in_string;
+
+        let mut structural = (operators & !in_string) | token_start | opening_quote;
+        let base = (n * BLOCK_SIZE) as u32;
+        while structural != 0 {
+            positions.push(base + structural.trailing_zeros());
+            structural &= structural - 1;
+        }
+    }
+    if in_string_carry != 0 {
+        return Err(StructureError {
+            offset: bytes.iter().rposition(|b| *b == b'"').unwrap_or(0),
+            err: "unterminated string".to_owned(),
+        });
+    }
+    Ok(positions)
+}
+
+fn partners(bytes: &[u8], positions: &[u32]) -> Result<Vec<u32>, StructureError> {
+    let mut partners: Vec<u32> = (0..positions.len() as u32).collect();
+    let mut open = vec![];
+    for (i, offset) in positions.iter().enumerate() {
+        match bytes[*offset as usize] {
+            b'{' => open.push(i),
+            b'}' => {
+                let start = open.pop().ok_or_else(|| StructureError {
+                    offset: *offset as usize,
+                    err: "unexpected }".to_owned(),
+                })?;
+                partners[start] = i as u32;
+                partners[i] = start as u32;
+            }
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some(start) => Err(StructureError {
+            offset: positions[start] as usize,
+            err: "unclosed {".to_owned(),
+        }),
+        None => Ok(partners),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clausewitz::bracketed::bracketed, Number, SectionIndex};
+
+    fn tokens<'a>(index: &StructuralIndex<'a>) -> Vec<&'a str> {
+        index
+            .positions()
+            .iter()
+            .enumerate()
+            .map(|(i, _)| &index.input()[index.offset(i)..index.end(i).max(index.offset(i) + 1)])
+            .collect()
+    }
+
+    #[test]
+    fn new__short_document__tokens_and_operators() {
+        let index = StructuralIndex::new("a=1 b = { c=\"x y\" }").unwrap();
+        assert_eq!(
+            tokens(&index)
+                .iter()
+                .map(|token| &token[..1])
+                .collect::<Vec<_>>(),
+            vec!["a", "=", "1", "b", "=", "{", "c", "=", "\"", "}"]
+        );
+        assert_eq!(index.matching(5), Some(9));
+        assert_eq!(index.matching(0), None);
+    }
+
+    #[test]
+    fn new__braces_in_strings_across_blocks__ignored() {
+        let padding = " ".repeat(60);
+        let text = format!("a={{{}name=\"{{ }} {} }}\" }}", padding, padding);
+        let index = StructuralIndex::new(&text).unwrap();
+        assert_eq!(index.block_end(2), Some(text.len()));
+        assert_eq!(index.children(2).count(), 3);
+    }
+
+    #[test]
+    fn new__token_across_blocks__one_start() {
+        let text = format!("{}=abcdefgh", "k".repeat(60));
+        let index = StructuralIndex::new(&text).unwrap();
+        assert_eq!(index.positions(), &[0, 60, 61]);
+    }
+
+    #[test]
+    fn new__unbalanced__err() {
+        assert_eq!(StructuralIndex::new("a={ b={ }").unwrap_err().offset, 2);
+        assert_eq!(StructuralIndex::new("a=1 }").unwrap_err().offset, 4);
+        assert!(StructuralIndex::new("a=\"open").is_err());
+    }
+
+    #[test]
+    fn sections__spaced_and_tagged__ranges() {
+        let text = "version=\"v3.4.5\"\nplayer = {\n\t{ name=\"a}{\" }\n}\ncolor = rgb { 1 2 3 }\nlast=yes";
+        let index = StructuralIndex::new(text).unwrap();
+        let sections = index
+            .sections()
+            .unwrap()
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![
+                "version=\"v3.4.5\"",
+                "player = {\n\t{ name=\"a}{\" }\n}",
+                "color = rgb { 1 2 3 }",
+                "last=yes"
+            ]
+        );
+    }
+
+    #[test]
+    fn sections__bare_value_or_missing_equals__located_err() {
+        let sections = |text| StructuralIndex::new(text).unwrap().sections();
+        assert_eq!(sections("a=1\n5\nb=2").unwrap_err().offset, 6);
+        assert_eq!(sections("{ b=2 }").unwrap_err().offset, 0);
+        assert_eq!(sections("a=1 b").unwrap_err().offset, 5);
+        assert_eq!(sections("a=1 b=").unwrap_err().offset, 6);
+        assert_eq!(sections("a==1").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn sections__document__same_as_section_index() {
+        let text = "a=1\n\"quoted key\"={ x=\"}\" }\ncolor = rgb { 1 2 3 }\nname=\"v\" last={ }";
+        let ranges = StructuralIndex::new(text).unwrap().sections().unwrap();
+        let sections = SectionIndex::new(text).unwrap();
+        assert_eq!(
+            ranges,
+            sections
+                .sections()
+                .iter()
+                .map(|section| section.range.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_block__nested_block__parsed_alone() {
+        let index = StructuralIndex::new("a={ b={ c=1 } d=2 }").unwrap();
+        let open = index.find(6).unwrap();
+        let (_, val) = index.parse_block(open, &ParseOptions::default()).unwrap();
+        assert_eq!(val, Val::Dict(vec![("c", Val::Integer(Number::new("1")))]));
+    }
+
+    #[test]
+    fn parse_block__mixed_numbered_and_broken__same_as_bracketed() {
+        let options = ParseOptions::default();
+        for text in [
+            "{ 0=a b=c }",
+            "{ 14 { b=1 } }",
+            "{ a=rgb { 1 2 3 } \"2200.01.01\" }",
+            "{ a= }",
+        ] {
+            let index = StructuralIndex::new(text).unwrap();
+            let parsed = index.parse_block(0, &options);
+            match bracketed(text, &options) {
+                Ok(expected) => assert_eq!(parsed, Ok(expected), "{}", text),
+                Err(_) => assert!(parsed.is_err(), "{}", text),
+            }
+        }
+    }
+}
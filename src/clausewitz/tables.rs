@@ -76,23 +76,9 @@ pub const fn identifier_table() -> [bool; 256] {
     table
 }
 
-#[inline(always)]
-pub const fn token_table() -> [bool; 256] {
-    let mut table = [false; 256];
-    table[b'=' as usize] = true;
-    table[b'{' as usize] = true;
-    table[b'}' as usize] = true;
-    table
-}
-
 #[inline(always)]
 pub fn is_string_litteral_contents(char: char) -> bool {
-    string_literal_content_table()[char as usize]
-}
-
-#[inline(always)]
-pub fn is_identifier_char(char: char) -> bool {
-    identifier_table()[char as usize]
+    !char.is_ascii() || string_literal_content_table()[char as usize]
 }
 
 #[inline(always)]
@@ -107,15 +93,10 @@ pub const fn space_table() -> [bool; 256] {
 
 #[inline(always)]
 pub fn is_space(c: char) -> bool {
-    space_table()[c as usize]
+    c.is_ascii() && space_table()[c as usize]
 }
 
 #[inline(always)]
 pub fn is_digit(char: char) -> bool {
     char.is_digit(10)
 }
-
-#[inline(always)]
-pub fn is_token(char: char) -> bool {
-    token_table()[char as usize]
-}
@@ -0,0 +1,960 @@
+use std::borrow::Cow;
+
+use nom::error::{ErrorKind, ParseError, VerboseError};
+use serde::{
+    ser::{SerializeMap, SerializeSeq, SerializeTuple},
+    Serialize, Serializer,
+};
+
+use super::{
+    bracketed::{key_value, mixed_entry},
+    builder::{index, Builder, Built, Container, Decline, Fits},
+    date::ClausewitzDate,
+    lexer::{Token, TokenKind},
+    number::Number,
+    options::ParseOptions,
+    shape::{shape, Shape},
+    skip::skip_space,
+    val::{serialize_date, serialize_decimal, serialize_integer, IndexError, Val},
+    value::value,
+    Res,
+};
+
+/// Where some text is in the input, kept as two `u32`s so a node fits in 16 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub(super) struct Span {
+    pub(super) start: u32,
+    pub(super) len: u32,
+}
+
+/// One entry of a [`Tape`]. A container is followed by everything inside it, and counts those
+/// nodes so a lookup can step over it. A key or index is followed by the value it labels.
+///
+/// The layout is fixed so that a cache can be read in place: a `u32` discriminant, then the
+/// fields from byte 4, or byte 8 for the 64 bit ones, in 16 bytes aligned to 8.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub(super) enum Node {
+    Dict(u32) = 0,
+    /// The length, then the number.
+    NumberedDict(u32, i64) = 1,
+    Array(u32) = 2,
+    Set(u32) = 3,
+    Mixed(u32) = 4,
+    /// Followed by the tagged container.
+    Tagged(Span, u32) = 5,
+    Key(Span) = 6,
+    Index(u64) = 7,
+    StringLiteral(Span) = 8,
+    Date(ClausewitzDate) = 9,
+    Decimal(Span) = 10,
+    Integer(Span) = 11,
+    Boolean(bool) = 12,
+    Identifier(Span) = 13,
+}
+
+const _: () = assert!(size_of::<Node>() == 16 && align_of::<Node>() == 8);
+
+impl Node {
+    /// How many nodes this one and everything inside it take up.
+    #[inline(always)]
+    pub(super) fn size(&self) -> usize {
+        1 + match self {
+            Node::Dict(len)
+            | Node::NumberedDict(len, _)
+            | Node::Array(len)
+            | Node::Set(len)
+            | Node::Mixed(len)
+            | Node::Tagged(_, len) => *len as usize,
+            _ => 0,
+        }
+    }
+
+    /// Whether this is a container written as a block, rather than a tag or a scalar.
+    #[inline(always)]
+    fn is_block(&self) -> bool {
+        matches!(
+            self,
+            Node::Dict(_) | Node::NumberedDict(..) | Node::Array(_) | Node::Set(_) | Node::Mixed(_)
+        )
+    }
+
+    #[inline(always)]
+    fn set_len(&mut self, to: usize) {
+        match self {
+            Node::Dict(len)
+            | Node::NumberedDict(len, _)
+            | Node::Array(len)
+            | Node::Set(len)
+            | Node::Mixed(len)
+            | Node::Tagged(_, len) => *len = to as u32,
+            _ => {}
+        }
+    }
+}
+
+/// A document as one flat list of 16 byte nodes pointing into the input, instead of a [`Val`]
+/// with an allocation for every container. Inputs are limited to 4 GiB. The nodes are borrowed
+/// when they come from a cache.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape<'a> {
+    pub(super) input: &'a str,
+    pub(super) nodes: Cow<'a, [Node]>,
+}
+
+impl<'a> Tape<'a> {
+    pub fn input(&self) -> &'a str {
+        self.input
+    }
+
+    /// The number of nodes, counting keys and indices.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn root(&self) -> Cursor<'_, 'a> {
+        Cursor { tape: self, at: 0 }
+    }
+
+    pub fn to_val(&self) -> Val<'a> {
+        self.root().to_val()
+    }
+
+    #[inline(always)]
+    fn text(&self, span: Span) -> &'a str {
+        &self.input[span.start as usize..][..span.len as usize]
+    }
+}
+
+/// Parses a document straight into a [`Tape`], giving the same values as
+/// [`super::root::root_with_options`].
+pub fn root_tape<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Tape<'a>> {
+    if u32::try_from(input.len()).is_err() {
+        return Err(nom::Err::Failure(VerboseError::from_error_kind(
+            input,
+            ErrorKind::TooLarge,
+        )));
+    }
+    Writer {
+        builder: Builder::new(input, options),
+        // saves take a node for every 8 bytes or so, and unused capacity is never touched
+        nodes: Vec::with_capacity(input.len() / 6),
+    }
+    .root()
+}
+
+/// Walks the input like [`Builder`], writing nodes instead of values. A token the builder declines
+/// is parsed by the combinators and written in place.
+struct Writer<'a, 'o> {
+    builder: Builder<'a, 'o>,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Writer<'a, '_> {
+    fn root(mut self) -> Res<&'a str, Tape<'a>> {
+        let (input, options) = (self.builder.input, self.builder.options);
+        self.nodes.push(Node::Dict(0));
+        let mut end = 0;
+        let mut first = true;
+        loop {
+            let start = match first {
+                true => end,
+                false => {
+                    let start = self.builder.lexer.skip_space(end);
+                    if start == end {
+                        break;
+                    }
+                    start
+                }
+            };
+            let mark = self.nodes.len();
+            match self.entry(start) {
+                Ok(next) => end = next,
+                Err(Decline) => {
+                    self.nodes.truncate(mark);
+                    match key_value(&input[start..], options) {
+                        Ok((rest, (key, val))) => {
+                            self.push(Node::Key, key);
+                            self.push_val(val);
+                            end = input.len() - rest.len();
+                        }
+                        Err(nom::Err::Error(_)) => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            first = false;
+        }
+        self.close(0);
+        Ok((&input[end..], self.finish()))
+    }
+
+    fn finish(self) -> Tape<'a> {
+        Tape {
+            input: self.builder.input,
+            nodes: Cow::Owned(self.nodes),
+        }
+    }
+
+    /// Pushes a node for `text`, which is always a slice of the input.
+    #[inline(always)]
+    fn push(&mut self, node: fn(Span) -> Node, text: &str) {
+        let span = self.span(text);
+        self.nodes.push(node(span));
+    }
+
+    #[inline(always)]
+    fn span(&self, text: &str) -> Span {
+        let start = text.as_ptr() as usize - self.builder.input.as_ptr() as usize;
+        debug_assert!(start + text.len() <= self.builder.input.len());
+        Span {
+            start: start as u32,
+            len: text.len() as u32,
+        }
+    }
+
+    #[inline(always)]
+    fn text(&self, span: Span) -> &'a str {
+        &self.builder.input[span.start as usize..][..span.len as usize]
+    }
+
+    #[inline(always)]
+    fn close(&mut self, header: usize) {
+        let len = self.nodes.len() - header - 1;
+        self.nodes[header].set_len(len);
+    }
+
+    /// Writes a value the combinators or the builder parsed.
+    fn push_val(&mut self, val: Val<'a>) {
+        let header = self.nodes.len();
+        match val {
+            Val::Dict(entries) => {
+                self.nodes.push(Node::Dict(0));
+                self.push_entries(entries);
+            }
+            Val::NumberedDict(number, entries) => {
+                self.nodes.push(Node::NumberedDict(0, number));
+                self.push_entries(entries);
+            }
+            Val::Array(pairs) => {
+                self.nodes.push(Node::Array(0));
+                for (index, val) in pairs {
+                    self.nodes.push(Node::Index(index));
+                    self.push_val(val);
+                }
+            }
+            Val::Set(vals) => {
+                self.nodes.push(Node::Set(0));
+                vals.into_iter().for_each(|val| self.push_val(val));
+            }
+            Val::Mixed(entries) => {
+                self.nodes.push(Node::Mixed(0));
+                for (key, val) in entries {
+                    if let Some(key) = key {
+                        self.push(Node::Key, key);
+                    }
+                    self.push_val(val);
+                }
+            }
+            Val::Tagged(tag, val) => {
+                self.push(|span| Node::Tagged(span, 0), tag);
+                self.push_val(*val);
+            }
+            Val::StringLiteral(text) => self.push(Node::StringLiteral, text),
+            Val::Date(date) => self.nodes.push(Node::Date(date)),
+            Val::Decimal(number) => self.push(Node::Decimal, number.as_str()),
+            Val::Integer(number) => self.push(Node::Integer, number.as_str()),
+            Val::Boolean(b) => self.nodes.push(Node::Boolean(b)),
+            Val::Identifier(text) => self.push(Node::Identifier, text),
+        }
+        self.close(header);
+    }
+
+    fn push_entries(&mut self, entries: Vec<(&'a str, Val<'a>)>) {
+        for (key, val) in entries {
+            self.push(Node::Key, key);
+            self.push_val(val);
+        }
+    }
+
+    #[inline(always)]
+    fn entry(&mut self, at: usize) -> Built<usize> {
+        let (key, at) = self.builder.key(at)?;
+        self.push(Node::Key, key);
+        self.value(at)
+    }
+
+    /// A value, written like [`Builder`] reads one.
+    #[inline(always)]
+    fn value(&mut self, at: usize) -> Built<usize> {
+        let token = self.builder.token(at)?;
+        self.token_value(token)
+    }
+
+    #[inline(always)]
+    fn token_value(&mut self, token: Token<'a>) -> Built<usize> {
+        let at = token.offset;
+        match token.kind {
+            TokenKind::Open => return self.block(at),
+            TokenKind::String => {
+                if let Ok(val) = self.builder.quoted(token.text) {
+                    self.push_val(val);
+                    return Ok(token.end);
+                }
+            }
+            TokenKind::Close | TokenKind::Equals => return Err(Decline),
+            TokenKind::Other => {}
+            _ => match self.builder.tag(&token) {
+                Ok(Some(block)) => {
+                    let header = self.nodes.len();
+                    self.push(|span| Node::Tagged(span, 0), token.text);
+                    let end = self.block(block)?;
+                    self.close(header);
+                    return Ok(end);
+                }
+                Ok(None) => {
+                    if let Ok(val) = self.builder.scalar(&token) {
+                        self.push_val(val);
+                        return Ok(token.end);
+                    }
+                }
+                Err(Decline) => {}
+            },
+        }
+        let (end, val) = self.builder.combinators(at, value)?;
+        self.push_val(val);
+        Ok(end)
+    }
+
+    /// A pair or a bare value, as an element of a block.
+    #[inline(always)]
+    fn mixed_entry(&mut self, at: usize) -> Built<usize> {
+        let token = self.builder.token(at)?;
+        if let Some((key, at)) = self.builder.pair(&token) {
+            self.push(Node::Key, key);
+            return self.value(at);
+        }
+        if token.kind != TokenKind::Other {
+            return self.token_value(token);
+        }
+        let (end, (key, val)) = self.builder.combinators(at, mixed_entry)?;
+        if let Some(key) = key {
+            self.push(Node::Key, key);
+        }
+        self.push_val(val);
+        Ok(end)
+    }
+
+    /// The block opening at `at`, written as a mixed block and then narrowed to the container its
+    /// first token suggests if every entry fits it.
+    fn block(&mut self, at: usize) -> Built<usize> {
+        let body = self.builder.lexer.skip_space(at + 1);
+        let shape = shape(&self.builder.input[at + 1..]);
+        let header = self.nodes.len();
+        let numbered = match shape {
+            Shape::NumberedDict => self.builder.numbered(body).ok(),
+            _ => None,
+        };
+        let close = match numbered {
+            Some((number, inner)) => self.numbered_dict(header, body, number, inner)?,
+            None => {
+                self.nodes.push(Node::Mixed(0));
+                let close = self.list(body, true, Self::mixed_entry)?;
+                self.narrow(header, shape);
+                close
+            }
+        };
+        self.close(header);
+        self.builder.expect(close, b'}')
+    }
+
+    /// The body of `{ 14 { key=value } }`. Its pairs are written straight after the header, and if
+    /// it turns out to be a mixed block, the number and the inner block's header are put before
+    /// them.
+    fn numbered_dict(
+        &mut self,
+        header: usize,
+        body: usize,
+        number: i64,
+        inner: usize,
+    ) -> Built<usize> {
+        self.nodes.push(Node::NumberedDict(0, number));
+        let inner_close = self.list(inner, true, Self::mixed_entry)?;
+        let close = self.builder.lexer.skip_space(inner_close + 1);
+        if self.builder.lexer.byte(close) == Some(b'}') && self.fits(header, Shape::Dict).fit() {
+            return Ok(close);
+        }
+        let number = self.span(self.builder.token(body)?.text);
+        self.nodes.splice(
+            header + 1..header + 1,
+            [Node::Integer(number), Node::Mixed(0)],
+        );
+        self.narrow(header + 2, shape(&self.builder.input[inner..]));
+        self.close(header + 2);
+        self.nodes[header] = Node::Mixed(0);
+        self.list(inner_close + 1, false, Self::mixed_entry)
+    }
+
+    /// Whether the elements of the container at `header` fit the container `shape` suggests.
+    fn fits(&self, header: usize, shape: Shape) -> Fits {
+        let mut fits = Fits::new(shape);
+        let mut at = header + 1;
+        while at < self.nodes.len() {
+            let key = match self.nodes[at] {
+                Node::Key(key) => Some(self.text(key)),
+                _ => None,
+            };
+            let value = at + key.is_some() as usize;
+            fits.add(key, self.nodes[value].is_block());
+            at = value + self.nodes[value].size();
+        }
+        fits
+    }
+
+    /// Turns the mixed block at `header` into the container its elements fit.
+    fn narrow(&mut self, header: usize, shape: Shape) {
+        let container = self.fits(header, shape).container(self.builder.options);
+        self.nodes[header] = match container {
+            Container::Dict => Node::Dict(0),
+            Container::Array => Node::Array(0),
+            Container::Set => Node::Set(0),
+            Container::Mixed => Node::Mixed(0),
+        };
+        if container != Container::Array {
+            return;
+        }
+        let mut at = header + 1;
+        while at < self.nodes.len() {
+            if let Node::Key(key) = self.nodes[at] {
+                self.nodes[at] = Node::Index(index(self.text(key)).unwrap_or_default());
+            }
+            at += 1 + self.nodes[at + 1].size();
+        }
+        if self.builder.options.sort_arrays {
+            self.sort(header);
+        }
+    }
+
+    /// Elements separated by whitespace up to the closing brace, whose position is returned.
+    /// `first` is whether no element has been written before `from`.
+    #[inline(always)]
+    fn list(
+        &mut self,
+        from: usize,
+        mut first: bool,
+        element: impl Fn(&mut Self, usize) -> Built<usize>,
+    ) -> Built<usize> {
+        let mut end = from;
+        loop {
+            let start = match first {
+                true => end,
+                false => {
+                    let start = self.builder.lexer.skip_space(end);
+                    if start == end {
+                        break;
+                    }
+                    start
+                }
+            };
+            if matches!(self.builder.lexer.byte(start), None | Some(b'}')) {
+                break;
+            }
+            end = element(self, start)?;
+            first = false;
+        }
+        let close = self.builder.lexer.skip_space(end);
+        self.builder.expect(close, b'}')?;
+        Ok(close)
+    }
+
+    /// Stably sorts the elements of the array at `header` by index. Lengths are relative, so
+    /// elements can be moved as they are.
+    fn sort(&mut self, header: usize) {
+        let mut elements = vec![];
+        let mut at = header + 1;
+        while at < self.nodes.len() {
+            let index = match self.nodes[at] {
+                Node::Index(index) => index,
+                _ => unreachable!("array elements start with their index"),
+            };
+            let end = at + 1 + self.nodes[at + 1].size();
+            elements.push((index, at..end));
+            at = end;
+        }
+        if elements.windows(2).all(|pair| pair[0].0 <= pair[1].0) {
+            return;
+        }
+        elements.sort_by_key(|(index, _)| *index);
+        let sorted = elements
+            .into_iter()
+            .flat_map(|(_, range)| self.nodes[range].to_vec())
+            .collect::<Vec<_>>();
+        self.nodes.truncate(header + 1);
+        self.nodes.extend(sorted);
+    }
+}
+
+/// A value in a [`Tape`], with the lookups of [`crate::ClausewitzValue`].
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor<'t, 'a> {
+    tape: &'t Tape<'a>,
+    at: usize,
+}
+
+impl<'t, 'a> Cursor<'t, 'a> {
+    #[inline(always)]
+    fn node(&self) -> Node {
+        self.tape.nodes[self.at]
+    }
+
+    #[inline(always)]
+    fn label(&self) -> Option<Node> {
+        self.at.checked_sub(1).map(|at| self.tape.nodes[at])
+    }
+
+    /// The key this value is stored under, if it is in a dict.
+    pub fn key(&self) -> Option<&'a str> {
+        match self.label() {
+            Some(Node::Key(key)) => Some(self.tape.text(key)),
+            _ => None,
+        }
+    }
+
+    /// The index this value is stored under, if it is in an array.
+    pub fn index(&self) -> Option<u64> {
+        match self.label() {
+            Some(Node::Index(index)) => Some(index),
+            _ => None,
+        }
+    }
+
+    pub(super) fn input(&self) -> &'a str {
+        self.tape.input
+    }
+
+    /// Where the text of this value starts in the input: at its own text, after the key it is
+    /// stored under, or else at the first value inside it with either. Containers keep no position
+    /// of their own, and dates and booleans in arrays and sets have none.
+    pub(super) fn offset(&self) -> Option<usize> {
+        match self.node() {
+            Node::Tagged(span, _)
+            | Node::StringLiteral(span)
+            | Node::Decimal(span)
+            | Node::Integer(span)
+            | Node::Identifier(span) => return Some(span.start as usize),
+            _ => {}
+        }
+        if let Some(Node::Key(key)) = self.label() {
+            let bytes = self.tape.input.as_bytes();
+            let mut end = (key.start + key.len) as usize;
+            if bytes.get(end) == Some(&b'"') {
+                end += 1;
+            }
+            let equals = skip_space(bytes, end);
+            return Some(skip_space(bytes, equals + 1).min(bytes.len()));
+        }
+        self.children().find_map(|child| child.offset())
+    }
+
+    /// The values directly inside this one, in document order.
+    pub fn children(&self) -> Elements<'t, 'a> {
+        Elements {
+            tape: self.tape,
+            next: self.at + 1,
+            end: self.at + self.node().size(),
+        }
+    }
+
+    pub fn get(&self, p: &str) -> Result<Cursor<'t, 'a>, IndexError> {
+        let by_key = || {
+            self.children()
+                .find(|child| child.key() == Some(p))
+                .ok_or(IndexError {
+                    err: format!("Expected to find value with key {}", p),
+                })
+        };
+        match self.node() {
+            Node::Dict(_) | Node::NumberedDict(..) | Node::Mixed(_) => by_key(),
+            Node::Array(_) => {
+                let index = p.parse::<u64>().map_err(|_| IndexError {
+                    err: format!("Cannot index an array with {}", p),
+                })?;
+                self.children()
+                    .find(|child| child.index() == Some(index))
+                    .ok_or(IndexError {
+                        err: format!("Expected to find value with index {}", p),
+                    })
+            }
+            // `color.rgb` names the tag explicitly, `color.0` looks through it
+            Node::Tagged(tag, _) if self.tape.text(tag) == p => Ok(self.inner()),
+            Node::Tagged(..) => self.inner().get(p),
+            Node::Set(_) => Err(IndexError {
+                err: format!("Cannot index a set with index {}", p),
+            }),
+            _ => Err(IndexError {
+                err: "Cannot index terminal values!".to_owned(),
+            }),
+        }
+    }
+
+    fn inner(&self) -> Cursor<'t, 'a> {
+        Cursor {
+            tape: self.tape,
+            at: self.at + 1,
+        }
+    }
+
+    pub fn get_at_path(&self, path: &str) -> Result<Cursor<'t, 'a>, IndexError> {
+        path.split('.').try_fold(*self, |cursor, p| cursor.get(p))
+    }
+
+    fn expect<T>(
+        &self,
+        path: &str,
+        what: &str,
+        f: impl FnOnce(Cursor<'t, 'a>) -> Option<T>,
+    ) -> Result<T, IndexError> {
+        f(self.get_at_path(path)?).ok_or(IndexError {
+            err: format!("{} is not the {} you are looking for!", path, what),
+        })
+    }
+
+    pub fn get_set_at_path(&self, path: &str) -> Result<Cursor<'t, 'a>, IndexError> {
+        self.expect(path, "set", |c| {
+            matches!(c.node(), Node::Set(_)).then_some(c)
+        })
+    }
+
+    pub fn get_date_at_path(&self, path: &str) -> Result<ClausewitzDate, IndexError> {
+        self.expect(path, "date", |c| match c.node() {
+            Node::Date(date) => Some(date),
+            _ => None,
+        })
+    }
+
+    pub fn get_encoded_date_at_path(&self, path: &str) -> Result<ClausewitzDate, IndexError> {
+        self.expect(path, "encoded date", |c| match c.node() {
+            Node::Integer(encoded) => Number::new(c.tape.text(encoded))
+                .as_i64()
+                .and_then(ClausewitzDate::from_encoded),
+            _ => None,
+        })
+    }
+
+    pub fn get_boolean_at_path(&self, path: &str) -> Result<bool, IndexError> {
+        self.expect(path, "boolean", |c| match c.node() {
+            Node::Boolean(b) => Some(b),
+            _ => None,
+        })
+    }
+
+    pub fn get_string_at_path(&self, path: &str) -> Result<&'a str, IndexError> {
+        self.expect(path, "string", |c| match c.node() {
+            Node::StringLiteral(s) => Some(c.tape.text(s)),
+            _ => None,
+        })
+    }
+
+    pub fn get_identifier_at_path(&self, path: &str) -> Result<&'a str, IndexError> {
+        self.expect(path, "identifier", |c| match c.node() {
+            Node::Identifier(s) => Some(c.tape.text(s)),
+            _ => None,
+        })
+    }
+
+    pub fn get_decimal_at_path(&self, path: &str) -> Result<f64, IndexError> {
+        self.expect(path, "decimal", |c| match c.node() {
+            Node::Decimal(n) => Number::new(c.tape.text(n)).as_f64(),
+            _ => None,
+        })
+    }
+
+    pub fn get_integer_at_path(&self, path: &str) -> Result<i64, IndexError> {
+        self.expect(path, "integer", |c| match c.node() {
+            Node::Integer(n) => Number::new(c.tape.text(n)).as_i64(),
+            _ => None,
+        })
+    }
+
+    pub fn get_number_at_path(&self, path: &str) -> Result<f64, IndexError> {
+        self.expect(path, "integer or decimal", |c| match c.node() {
+            Node::Integer(n) | Node::Decimal(n) => Number::new(c.tape.text(n)).as_f64(),
+            _ => None,
+        })
+    }
+
+    pub fn get_raw_number_at_path(&self, path: &str) -> Result<Number<'a>, IndexError> {
+        self.expect(path, "integer or decimal", |c| match c.node() {
+            Node::Integer(n) | Node::Decimal(n) => Some(Number::new(c.tape.text(n))),
+            _ => None,
+        })
+    }
+
+    pub fn get_tagged_at_path(&self, path: &str) -> Result<(&'a str, Cursor<'t, 'a>), IndexError> {
+        self.expect(path, "tagged block", |c| match c.node() {
+            Node::Tagged(tag, _) => Some((c.tape.text(tag), c.inner())),
+            _ => None,
+        })
+    }
+
+    pub fn get_mixed_at_path(&self, path: &str) -> Result<Cursor<'t, 'a>, IndexError> {
+        self.expect(path, "mixed container", |c| {
+            matches!(c.node(), Node::Mixed(_)).then_some(c)
+        })
+    }
+
+    pub fn get_array_at_path(&self, path: &str) -> Result<Cursor<'t, 'a>, IndexError> {
+        self.expect(path, "array", |c| {
+            matches!(c.node(), Node::Array(_)).then_some(c)
+        })
+    }
+
+    pub fn get_dict_at_path(&self, path: &str) -> Result<Cursor<'t, 'a>, IndexError> {
+        self.expect(path, "dict", |c| {
+            matches!(c.node(), Node::Dict(_)).then_some(c)
+        })
+    }
+
+    pub fn get_numbered_dict_at_path(
+        &self,
+        path: &str,
+    ) -> Result<(i64, Cursor<'t, 'a>), IndexError> {
+        self.expect(path, "numbered dict", |c| match c.node() {
+            Node::NumberedDict(_, number) => Some((number, c)),
+            _ => None,
+        })
+    }
+
+    /// The value as a [`Val`], allocating its containers.
+    pub fn to_val(&self) -> Val<'a> {
+        let entries = || {
+            self.children()
+                .map(|child| (child.key().unwrap_or_default(), child.to_val()))
+                .collect()
+        };
+        let text = |span| self.tape.text(span);
+        match self.node() {
+            Node::Dict(_) => Val::Dict(entries()),
+            Node::NumberedDict(_, number) => Val::NumberedDict(number, entries()),
+            Node::Array(_) => Val::Array(
+                self.children()
+                    .map(|child| (child.index().unwrap_or_default(), child.to_val()))
+                    .collect(),
+            ),
+            Node::Set(_) => Val::Set(self.children().map(|child| child.to_val()).collect()),
+            Node::Mixed(_) => Val::Mixed(
+                self.children()
+                    .map(|child| (child.key(), child.to_val()))
+                    .collect(),
+            ),
+            Node::Tagged(tag, _) => Val::Tagged(text(tag), Box::new(self.inner().to_val())),
+            Node::StringLiteral(s) => Val::StringLiteral(text(s)),
+            Node::Date(date) => Val::Date(date),
+            Node::Decimal(n) => Val::Decimal(Number::new(text(n))),
+            Node::Integer(n) => Val::Integer(Number::new(text(n))),
+            Node::Boolean(b) => Val::Boolean(b),
+            Node::Identifier(s) => Val::Identifier(text(s)),
+            Node::Key(_) | Node::Index(_) => unreachable!("cursors only point at values"),
+        }
+    }
+}
+
+/// Serializes like the [`Val`] it stands for, without building it.
+impl Serialize for Cursor<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let text = |span| self.tape.text(span);
+        match self.node() {
+            Node::Dict(_) => Entries(*self).serialize(serializer),
+            Node::NumberedDict(_, number) => {
+                let mut tup = serializer.serialize_tuple(2)?;
+                tup.serialize_element(&number)?;
+                tup.serialize_element(&Entries(*self))?;
+                tup.end()
+            }
+            Node::Array(_) => {
+                let mut seq = serializer.serialize_seq(Some(self.children().count()))?;
+                for child in self.children() {
+                    seq.serialize_element(&(child.index().unwrap_or_default(), child))?;
+                }
+                seq.end()
+            }
+            Node::Set(_) => {
+                let mut tup = serializer.serialize_tuple(self.children().count())?;
+                for child in self.children() {
+                    tup.serialize_element(&child)?;
+                }
+                tup.end()
+            }
+            Node::Mixed(_) => {
+                let mut seq = serializer.serialize_seq(Some(self.children().count()))?;
+                for child in self.children() {
+                    match child.key() {
+                        Some(key) => seq.serialize_element(&Single(key, child))?,
+                        None => seq.serialize_element(&child)?,
+                    }
+                }
+                seq.end()
+            }
+            Node::Tagged(tag, _) => Single(text(tag), self.inner()).serialize(serializer),
+            Node::StringLiteral(s) | Node::Identifier(s) => serializer.serialize_str(text(s)),
+            Node::Date(date) => serialize_date(&date, serializer),
+            Node::Decimal(n) => serialize_decimal(&Number::new(text(n)), serializer),
+            Node::Integer(n) => serialize_integer(&Number::new(text(n)), serializer),
+            Node::Boolean(b) => serializer.serialize_bool(b),
+            Node::Key(_) | Node::Index(_) => unreachable!("cursors only point at values"),
+        }
+    }
+}
+
+/// The keyed children of a container as a map.
+struct Entries<'t, 'a>(Cursor<'t, 'a>);
+
+impl Serialize for Entries<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.children().count()))?;
+        for child in self.0.children() {
+            map.serialize_entry(child.key().unwrap_or_default(), &child)?;
+        }
+        map.end()
+    }
+}
+
+/// A map with one entry, as tags and keyed values of mixed blocks are written.
+struct Single<'t, 'a>(&'a str, Cursor<'t, 'a>);
+
+impl Serialize for Single<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.0, &self.1)?;
+        map.end()
+    }
+}
+
+/// The values directly inside a container, stepping over the nodes inside each of them.
+#[derive(Debug, Clone)]
+pub struct Elements<'t, 'a> {
+    tape: &'t Tape<'a>,
+    next: usize,
+    end: usize,
+}
+
+impl<'t, 'a> Iterator for Elements<'t, 'a> {
+    type Item = Cursor<'t, 'a>;
+
+    fn next(&mut self) -> Option<Cursor<'t, 'a>> {
+        if self.next >= self.end {
+            return None;
+        }
+        let at = match self.tape.nodes[self.next] {
+            Node::Key(_) | Node::Index(_) => self.next + 1,
+            _ => self.next,
+        };
+        self.next = at + self.tape.nodes[at].size();
+        Some(Cursor {
+            tape: self.tape,
+            at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clausewitz::root::root_with_options;
+
+    const TEXT: &str = r###"version="v3.4.5"
+date="2290.03.05"
+country={
+	0={
+		name="Earth" flags={ a b } color=rgb { 1 2 3 }
+		budget={ income=10.5 debt=-2 }
+		numbered={ 14 { key=value } }
+		sets={ { a=1 } { b=2 } } mixed={ 1 2 key=value }
+	}
+	1={ name="Mars" started=2200.01.01 }
+}
+array={ 1=one 0=zero }
+"###;
+
+    #[test]
+    fn root_tape__document__same_as_val() {
+        for options in [ParseOptions::default(), ParseOptions::raw()] {
+            let (rest, tape) = root_tape(TEXT, &options).unwrap();
+            let (val_rest, val) = root_with_options(TEXT, &options).unwrap();
+            assert_eq!(rest, val_rest);
+            assert_eq!(tape.to_val(), val);
+        }
+    }
+
+    #[test]
+    fn cursor__paths__same_lookups_as_val() {
+        let (_, tape) = root_tape(TEXT, &ParseOptions::default()).unwrap();
+        let root = tape.root();
+        assert_eq!(root.get_string_at_path("country.1.name").unwrap(), "Mars");
+        assert_eq!(
+            root.get_number_at_path("country.0.budget.income").unwrap(),
+            10.5
+        );
+        assert_eq!(
+            root.get_integer_at_path("country.0.budget.debt").unwrap(),
+            -2
+        );
+        assert_eq!(
+            root.get_identifier_at_path("country.0.numbered.key")
+                .unwrap(),
+            "value"
+        );
+        assert_eq!(
+            root.get_date_at_path("country.1.started").unwrap(),
+            ClausewitzDate::from_ymd(2200, 1, 1)
+        );
+        let (tag, rgb) = root.get_tagged_at_path("country.0.color").unwrap();
+        assert_eq!((tag, rgb.children().count()), ("rgb", 3));
+        assert_eq!(
+            root.get_identifier_at_path("country.0.mixed.key").unwrap(),
+            "value"
+        );
+        assert_eq!(root.get_identifier_at_path("array.1").unwrap(), "one");
+        assert!(root.get_string_at_path("country.2.name").is_err());
+        assert!(root.get_set_at_path("country.0.name").is_err());
+    }
+
+    #[test]
+    fn cursor__children__keys_and_indices() {
+        let (_, tape) = root_tape("a={ x=1 y={ 2 } } b={ 3=c }", &ParseOptions::default()).unwrap();
+        let keys = tape
+            .root()
+            .get_dict_at_path("a")
+            .unwrap()
+            .children()
+            .map(|child| child.key())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![Some("x"), Some("y")]);
+        let element = tape.root().get_at_path("b.3").unwrap();
+        assert_eq!(element.index(), Some(3));
+    }
+
+    #[test]
+    fn serialize__cursor__same_json_as_val() {
+        let (_, tape) = root_tape(TEXT, &ParseOptions::default()).unwrap();
+        let (_, val) = root_with_options(TEXT, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&tape.root()).unwrap(),
+            serde_json::to_string(&val).unwrap()
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::{self, Error, ErrorKind, Write};
+
+use rayon::{
+    prelude::{IntoParallelIterator, ParallelIterator},
+    ThreadPool,
+};
+
+use super::{
+    options::ParseOptions,
+    section::{Section, SectionIndex},
+    tape::{root_tape, Cursor},
+};
+
+/// Writes a document as JSON, the way [`super::val::Val`] serializes, without parsing it into one
+/// tree. Top level sections are parsed into small tapes on a rayon pool a batch at a time, and
+/// written out in document order, so memory grows with the batch rather than with the document.
+#[derive(Debug, Clone, Copy)]
+pub struct Transcoder<'p> {
+    ndjson: bool,
+    batch: usize,
+    pool: Option<&'p ThreadPool>,
+}
+
+impl Default for Transcoder<'_> {
+    fn default() -> Self {
+        Transcoder {
+            ndjson: false,
+            batch: 1 << 25,
+            pool: None,
+        }
+    }
+}
+
+impl<'p> Transcoder<'p> {
+    pub fn new() -> Self {
+        Transcoder::default()
+    }
+
+    /// Writes every top level entry as an object of its own, one per line, instead of a single
+    /// object.
+    pub fn ndjson(mut self) -> Self {
+        self.ndjson = true;
+        self
+    }
+
+    /// Parses about this many bytes of sections before writing them out.
+    pub fn batch(mut self, bytes: usize) -> Self {
+        self.batch = bytes.max(1);
+        self
+    }
+
+    /// Parses on `pool` rather than on rayon's global pool.
+    pub fn pool(mut self, pool: &'p ThreadPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Writes `input` to `writer`. A section which does not parse is an
+    /// [`ErrorKind::InvalidData`] error carrying its [`super::section::SectionError`], and
+    /// whatever came before it has been written by then.
+    pub fn write<W: Write>(
+        &self,
+        input: &str,
+        options: &ParseOptions,
+        mut writer: W,
+    ) -> io::Result<()> {
+        let index = SectionIndex::new(input).ok();
+        let mut first = true;
+        if !self.ndjson {
+            writer.write_all(b"{")?;
+        }
+        match index {
+            Some(index) => {
+                for batch in self.batches(index.sections()) {
+                    let rendered: Vec<_> = self.install(|| {
+                        batch
+                            .into_par_iter()
+                            .map(|section| self.section(&index, section, options))
+                            .collect()
+                    });
+                    for json in rendered {
+                        self.separate(&mut writer, &mut first)?;
+                        writer.write_all(&json?)?;
+                    }
+                }
+            }
+            // the sections could not be told apart, so the whole document is parsed at once
+            None => {
+                let (rest, tape) = root_tape(input, options)
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "the document is not valid"))?;
+                if !rest.trim().is_empty() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "the document is not valid at byte {}",
+                            input.len() - rest.len()
+                        ),
+                    ));
+                }
+                for entry in tape.root().children() {
+                    self.separate(&mut writer, &mut first)?;
+                    writer.write_all(&self.entry(entry)?)?;
+                }
+            }
+        }
+        if !self.ndjson {
+            writer.write_all(b"}")?;
+        }
+        writer.flush()
+    }
+
+    /// Consecutive sections of about [`Transcoder::batch`] bytes.
+    fn batches<'s, 'a>(&self, sections: &'s [Section<'a>]) -> Vec<&'s [Section<'a>]> {
+        let mut batches = vec![];
+        let mut start = 0;
+        let mut bytes = 0;
+        for (i, section) in sections.iter().enumerate() {
+            bytes += section.text.len();
+            if bytes >= self.batch {
+                batches.push(&sections[start..=i]);
+                start = i + 1;
+                bytes = 0;
+            }
+        }
+        if start < sections.len() {
+            batches.push(&sections[start..]);
+        }
+        batches
+    }
+
+    /// The JSON of one section, parsed as a tape when it is a single entry, and otherwise as a
+    /// value which says best what went wrong.
+    fn section(
+        &self,
+        index: &SectionIndex,
+        section: &Section,
+        options: &ParseOptions,
+    ) -> io::Result<Vec<u8>> {
+        if let Ok((rest, tape)) = root_tape(section.text, options) {
+            let mut entries = tape.root().children();
+            if let (Some(entry), None) = (entries.next(), entries.next()) {
+                if rest.trim().is_empty() {
+                    return self.entry(entry);
+                }
+            }
+        }
+        let val = index
+            .parse_section(section, options)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let mut json = self.open();
+        serde_json::to_writer(&mut json, section.key)?;
+        json.push(b':');
+        serde_json::to_writer(&mut json, &val)?;
+        Ok(self.close(json))
+    }
+
+    fn entry(&self, entry: Cursor) -> io::Result<Vec<u8>> {
+        let mut json = self.open();
+        serde_json::to_writer(&mut json, entry.key().unwrap_or_default())?;
+        json.push(b':');
+        serde_json::to_writer(&mut json, &entry)?;
+        Ok(self.close(json))
+    }
+
+    /// Separates entries of the single object with commas.
+    fn separate<W: Write>(&self, writer: &mut W, first: &mut bool) -> io::Result<()> {
+        if !self.ndjson && !*first {
+            writer.write_all(b",")?;
+        }
+        *first = false;
+        Ok(())
+    }
+
+    fn open(&self) -> Vec<u8> {
+        match self.ndjson {
+            true => b"{".to_vec(),
+            false => vec![],
+        }
+    }
+
+    fn close(&self, mut json: Vec<u8>) -> Vec<u8> {
+        if self.ndjson {
+            json.extend_from_slice(b"}\n");
+        }
+        json
+    }
+
+    fn install<T: Send>(&self, parse: impl FnOnce() -> T + Send) -> T {
+        match self.pool {
+            Some(pool) => pool.install(parse),
+            None => parse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::ThreadPoolBuilder;
+
+    use super::*;
+    use crate::clausewitz::root::root_with_options;
+
+    const TEXT: &str = r###"version="v3.4.5"
+date=2290.03.05
+country={
+	0={ name="Earth" color=rgb { 1 2 3 } budget={ income=10.50 debt=-2 } }
+	1={ numbered={ 14 { key=value } } mixed={ 1 2 key=value } flags={ a b } }
+}
+array={ 1=one 0=zero }
+version="again"
+"###;
+
+    fn transcoded(transcoder: Transcoder, input: &str) -> io::Result<String> {
+        let mut json = vec![];
+        transcoder.write(input, &ParseOptions::default(), &mut json)?;
+        Ok(String::from_utf8(json).unwrap())
+    }
+
+    #[test]
+    fn write__json__same_as_serialized_val() {
+        let (_, val) = root_with_options(TEXT, &ParseOptions::default()).unwrap();
+        let expected = serde_json::to_string(&val).unwrap();
+
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        for batch in [1, 64, 1 << 20] {
+            let transcoder = Transcoder::new().batch(batch).pool(&pool);
+            assert_eq!(transcoded(transcoder, TEXT).unwrap(), expected);
+        }
+        assert_eq!(transcoded(Transcoder::new(), "").unwrap(), "{}");
+    }
+
+    #[test]
+    fn write__ndjson__one_line_per_entry() {
+        let json = transcoded(Transcoder::new().ndjson().batch(16), TEXT).unwrap();
+
+        let lines: Vec<_> = json.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], r#"{"version":"v3.4.5"}"#);
+        assert_eq!(lines[1], r#"{"date":[2290,3,5]}"#);
+        assert_eq!(lines[3], r#"{"array":[[0,"zero"],[1,"one"]]}"#);
+        assert_eq!(lines[4], r#"{"version":"again"}"#);
+    }
+
+    #[test]
+    fn write__unbalanced_braces__invalid_data() {
+        let text = "a=1 b=2 }";
+        assert_eq!(
+            root_with_options(text, &ParseOptions::default()).unwrap().0,
+            " }"
+        );
+
+        let err = transcoded(Transcoder::new(), text).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "the document is not valid at byte 7");
+    }
+
+    #[test]
+    fn write__invalid_section__invalid_data() {
+        let err = transcoded(Transcoder::new(), "a=1\nb={ c=\"x\"d }\n").unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
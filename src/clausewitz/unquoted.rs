@@ -1,94 +1,168 @@
-use chrono::NaiveDate;
 use nom::{
+    bytes::complete::{is_not, tag},
     character::complete::{char, digit1},
-    combinator::{map, map_res, opt, recognize, verify},
-    error::VerboseError,
+    combinator::{map, opt, recognize, verify},
     sequence::tuple,
 };
 
-use super::{quoted::map_to_date, simd::take_simd_identifier, tables::is_digit, val::Val, Res};
+use super::{
+    number::Number, options::ParseOptions, quoted::date_or_raw, simd::take_simd_identifier,
+    tables::is_digit, val::Val, Res,
+};
 
 #[inline(always)]
-pub fn date<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(
-        map_res(
-            recognize(tuple((digit1, char('.'), digit1, char('.'), digit1))),
-            map_to_date,
-        ),
-        |date: NaiveDate| Val::Date(date),
-    )(input)
+pub fn date<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    date_or_raw(input, options, Val::Identifier)
 }
 #[inline(always)]
 pub fn decimal<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
     map(
-        map_res(
-            recognize(tuple((opt(char('-')), digit1, char('.'), digit1))),
-            str::parse,
-        ),
-        |float: f64| Val::Decimal(float),
+        recognize(tuple((opt(char('-')), digit1, char('.'), digit1))),
+        |lexeme: &str| Val::Decimal(Number::new(lexeme)),
     )(input)
 }
 
 #[inline(always)]
-pub fn int<'a>(input: &'a str) -> Res<&'a str, i64> {
-    map_res(
-        verify(recognize(tuple((opt(char('-')), digit1))), |s: &str| {
-            !s.is_empty()
-        }),
-        str::parse,
-    )(input)
+pub fn int<'a>(input: &'a str) -> Res<&'a str, &'a str> {
+    verify(recognize(tuple((opt(char('-')), digit1))), |s: &str| {
+        !s.is_empty()
+    })(input)
 }
 
 #[inline(always)]
 pub fn integer<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    map(int, |integer: i64| Val::Integer(integer))(input)
+    map(int, |lexeme: &str| Val::Integer(Number::new(lexeme)))(input)
 }
 
+/// Inline math like `@[ cost * 1.5 ]` is kept as a single identifier for
+/// [`crate::ScriptedVariables`] to evaluate; it is only recognised by dialects which allow `@`.
 #[inline(always)]
-pub fn identifier<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
+pub fn inline_math<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    verify(
+        map(
+            recognize(tuple((tag("@["), is_not("]\n"), char(']')))),
+            Val::Identifier,
+        ),
+        |_| options.dialect.is_identifier_byte(b'@'),
+    )(input)
+}
+
+#[inline(always)]
+pub fn identifier<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
     map(
-        verify(take_simd_identifier, |s: &str| {
-            !s.is_empty() && !(is_digit(s.chars().next().unwrap()))
-        }),
-        |s: &str| Val::Identifier(s),
+        verify(
+            |i| take_simd_identifier(i, options.dialect),
+            |s: &str| !s.is_empty() && !(is_digit(s.chars().next().unwrap())),
+        ),
+        |s: &str| match s {
+            "yes" if options.booleans => Val::Boolean(true),
+            "no" if options.booleans => Val::Boolean(false),
+            _ => Val::Identifier(s),
+        },
     )(input)
 }
 use nom::branch::alt;
 
 #[inline(always)]
-pub fn unquoted<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    alt((date, decimal, integer, identifier))(input)
+pub fn unquoted<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    alt((
+        |i| inline_math(i, options),
+        |i| date(i, options),
+        decimal,
+        integer,
+        |i| identifier(i, options),
+    ))(input)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use chrono::NaiveDate;
+    use crate::{ClausewitzDate, Dialect};
 
     use super::*;
     #[test]
     fn unquoted__integer__integer() {
         let text = "0";
-        let (_remainder, parse_output) = unquoted(text).unwrap();
-        assert_eq!(parse_output, Val::Integer(0));
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(parse_output, Val::Integer(Number::new("0")));
     }
     #[test]
     fn unquoted__decimal__decimal() {
         let text = "0.0";
-        let (_remainder, parse_output) = unquoted(text).unwrap();
-        assert_eq!(parse_output, Val::Decimal(0.0));
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(parse_output, Val::Decimal(Number::new("0.0")));
     }
     #[test]
     fn unquoted__identifier__identifier() {
         let text = "zer0";
-        let (_remainder, parse_output) = unquoted(text).unwrap();
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
         assert_eq!(parse_output, Val::Identifier("zer0"));
     }
     #[test]
     fn unquoted__date__identifier() {
         let text = "2200.02.02";
-        let (_remainder, parse_output) = unquoted(text).unwrap();
-        assert_eq!(parse_output, Val::Date(NaiveDate::from_ymd(2200, 2, 2)));
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            parse_output,
+            Val::Date(ClausewitzDate::from_ymd(2200, 2, 2))
+        );
+    }
+    #[test]
+    fn unquoted__date_with_hour__date() {
+        let text = "1936.1.1.12";
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            parse_output,
+            Val::Date(ClausewitzDate::from_ymdh_opt(1936, 1, 1, 12).unwrap())
+        );
+    }
+    #[test]
+    fn unquoted__negative_year_date__date() {
+        let text = "-12.1.1";
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(parse_output, Val::Date(ClausewitzDate::from_ymd(-12, 1, 1)));
+    }
+    #[test]
+    fn unquoted__invalid_date_with_raw_invalid_dates__identifier() {
+        let text = "2200.02.29";
+        let options = ParseOptions::new().invalid_dates(crate::InvalidDates::Raw);
+        let (_remainder, parse_output) = unquoted(text, &options).unwrap();
+        assert_eq!(parse_output, Val::Identifier("2200.02.29"));
+    }
+    #[test]
+    fn unquoted__yes__identifier() {
+        let text = "yes";
+        let (_remainder, parse_output) = unquoted(text, &ParseOptions::default()).unwrap();
+        assert_eq!(parse_output, Val::Identifier("yes"));
+    }
+    #[test]
+    fn unquoted__yes_no_with_booleans__boolean() {
+        let options = ParseOptions::new().booleans(true);
+        let (_remainder, yes) = unquoted("yes", &options).unwrap();
+        let (_remainder, no) = unquoted("no", &options).unwrap();
+        assert_eq!(yes, Val::Boolean(true));
+        assert_eq!(no, Val::Boolean(false));
+    }
+    #[test]
+    fn unquoted__variable_with_mod_dialect__identifier() {
+        let options = ParseOptions::new().dialect(&Dialect::STELLARIS_MOD);
+        let (_remainder, variable) = unquoted("@my_var", &options).unwrap();
+        let (_remainder, parameter) = unquoted("$PARAM$", &options).unwrap();
+        let (_remainder, accented) = unquoted("Rivén", &options).unwrap();
+        assert_eq!(variable, Val::Identifier("@my_var"));
+        assert_eq!(parameter, Val::Identifier("$PARAM$"));
+        assert_eq!(accented, Val::Identifier("Rivén"));
+    }
+    #[test]
+    fn unquoted__inline_math_with_mod_dialect__identifier() {
+        let options = ParseOptions::new().dialect(&Dialect::STELLARIS_MOD);
+        let (remainder, parse_output) = unquoted("@[ cost * 1.5 ] }", &options).unwrap();
+        assert_eq!(parse_output, Val::Identifier("@[ cost * 1.5 ]"));
+        assert_eq!(remainder, " }");
+    }
+    #[test]
+    fn unquoted__variable_with_save_dialect__rejected() {
+        assert!(unquoted("@my_var", &ParseOptions::default()).is_err());
     }
 
     #[cfg(test)]
@@ -99,7 +173,7 @@ mod tests {
         #[test]
         fn identifire__alphanumeric_with_underscore_and_colon__accepted() {
             let text = "alpha_:numeric1234567890";
-            let (remainder, parse_output) = identifier(text).unwrap();
+            let (remainder, parse_output) = identifier(text, &ParseOptions::default()).unwrap();
             assert_eq!(parse_output, Val::Identifier(text));
             assert!(remainder.is_empty());
         }
@@ -107,13 +181,13 @@ mod tests {
         #[test]
         fn identifire__begins_with_number__rejected() {
             let text = "0alpha_numeric1234567890";
-            assert!(identifier(text).is_err());
+            assert!(identifier(text, &ParseOptions::default()).is_err());
         }
 
         #[test]
         fn identifire__empty__rejectec() {
             let text = "";
-            assert!(identifier(text).is_err());
+            assert!(identifier(text, &ParseOptions::default()).is_err());
         }
     }
     #[cfg(test)]
@@ -130,7 +204,7 @@ mod tests {
         fn integer__zero__accepted() {
             let text = "0";
             let (remainder, parse_output) = integer(text).unwrap();
-            assert_eq!(parse_output, Val::Integer(0));
+            assert_eq!(parse_output, Val::Integer(Number::new("0")));
             assert!(remainder.is_empty());
         }
 
@@ -138,7 +212,7 @@ mod tests {
         fn integer__negative_number__accepted() {
             let text = "-1";
             let (remainder, parse_output) = integer(text).unwrap();
-            assert_eq!(parse_output, Val::Integer(-1));
+            assert_eq!(parse_output, Val::Integer(Number::new("-1")));
             assert!(remainder.is_empty());
         }
 
@@ -146,7 +220,15 @@ mod tests {
         fn integer__all_digits__accepted() {
             let text = "1234567890";
             let (remainder, parse_output) = integer(text).unwrap();
-            assert_eq!(parse_output, Val::Integer(1234567890));
+            assert_eq!(parse_output, Val::Integer(Number::new("1234567890")));
+            assert!(remainder.is_empty());
+        }
+
+        #[test]
+        fn integer__larger_than_i64__accepted() {
+            let text = "18446744073709551615";
+            let (remainder, parse_output) = integer(text).unwrap();
+            assert_eq!(parse_output, Val::Integer(Number::new(text)));
             assert!(remainder.is_empty());
         }
 
@@ -154,7 +236,7 @@ mod tests {
         fn integer__dots__accepted_up_to_dot_then_remainder() {
             let text = "-12345.6789";
             let (remainder, parse_output) = integer(text).unwrap();
-            assert_eq!(parse_output, Val::Integer(-12345));
+            assert_eq!(parse_output, Val::Integer(Number::new("-12345")));
             assert_eq!(remainder, ".6789");
         }
 
@@ -162,7 +244,7 @@ mod tests {
         fn integer__letters__int_up_to_letter_then_remainder() {
             let text = "-1234567d89.098098";
             let (remainder, parse_output) = integer(text).unwrap();
-            assert_eq!(parse_output, Val::Integer(-1234567));
+            assert_eq!(parse_output, Val::Integer(Number::new("-1234567")));
             assert_eq!(remainder, "d89.098098");
         }
     }
@@ -176,7 +258,10 @@ mod tests {
         fn decimal__small_number__accepted() {
             let text = "0.00001011110110132";
             let (remainder, parse_output) = decimal(text).unwrap();
-            assert_eq!(parse_output, Val::Decimal(0.00001011110110132));
+            assert_eq!(
+                parse_output,
+                Val::Decimal(Number::new("0.00001011110110132"))
+            );
             assert!(remainder.is_empty());
         }
 
@@ -184,7 +269,7 @@ mod tests {
         fn decimal__negative_number__accepted() {
             let text = "-0.1";
             let (remainder, parse_output) = decimal(text).unwrap();
-            assert_eq!(parse_output, Val::Decimal(-0.1));
+            assert_eq!(parse_output, Val::Decimal(Number::new("-0.1")));
             assert!(remainder.is_empty());
         }
 
@@ -192,7 +277,15 @@ mod tests {
         fn decimal__all_digits__accepted() {
             let text = "-12345.6789";
             let (remainder, parse_output) = decimal(text).unwrap();
-            assert_eq!(parse_output, Val::Decimal(-12345.6789));
+            assert_eq!(parse_output, Val::Decimal(Number::new("-12345.6789")));
+            assert!(remainder.is_empty());
+        }
+
+        #[test]
+        fn decimal__trailing_zeros__kept() {
+            let text = "25.50000";
+            let (remainder, parse_output) = decimal(text).unwrap();
+            assert_eq!(parse_output, Val::Decimal(Number::new("25.50000")));
             assert!(remainder.is_empty());
         }
 
@@ -200,7 +293,7 @@ mod tests {
         fn decimal__too_many_dots__accepted_with_remainder() {
             let text = "-12345.6789.098098";
             let (remainder, parse_output) = decimal(text).unwrap();
-            assert_eq!(parse_output, Val::Decimal(-12345.6789));
+            assert_eq!(parse_output, Val::Decimal(Number::new("-12345.6789")));
             assert_eq!(remainder, ".098098");
         }
 
@@ -208,7 +301,7 @@ mod tests {
         fn decimal__letters__float_up_to_letter_then_remainder() {
             let text = "-12345.67d89.098098";
             let (remainder, parse_output) = decimal(text).unwrap();
-            assert_eq!(parse_output, Val::Decimal(-12345.67));
+            assert_eq!(parse_output, Val::Decimal(Number::new("-12345.67")));
             assert_eq!(remainder, "d89.098098");
         }
     }
@@ -3,13 +3,12 @@ use std::{
     fmt::{self, Debug, Display, Formatter},
 };
 
-use chrono::{Datelike, NaiveDate};
 use serde::{
     ser::{SerializeMap, SerializeSeq, SerializeTuple},
     Serialize, Serializer,
 };
 
-use crate::ClausewitzValue;
+use crate::{ClausewitzDate, ClausewitzValue, Number};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Val<'a> {
@@ -18,11 +17,13 @@ pub enum Val<'a> {
     Array(Vec<(u64, Val<'a>)>),
     Set(Vec<Val<'a>>),
     StringLiteral(&'a str),
-    // #[serde(serialize_with = "serialize_naive_date")]
-    Date(NaiveDate),
-    Decimal(f64),
-    Integer(i64),
+    Date(ClausewitzDate),
+    Decimal(Number<'a>),
+    Integer(Number<'a>),
+    Boolean(bool),
     Identifier(&'a str),
+    Tagged(&'a str, Box<Val<'a>>),
+    Mixed(Vec<(Option<&'a str>, Val<'a>)>),
 }
 
 impl<'a> Serialize for Val<'a> {
@@ -31,33 +32,84 @@ impl<'a> Serialize for Val<'a> {
         S: Serializer,
     {
         match self {
-            Val::Dict(seq) => serialize_dict(seq, serializer),
+            Val::Dict(seq) => Entries(seq).serialize(serializer),
             Val::NumberedDict(n, seq) => {
                 let mut tup = serializer.serialize_tuple(2)?;
                 tup.serialize_element(n)?;
-                tup.serialize_element(&Val::Dict(seq.clone()))?;
+                tup.serialize_element(&Entries(seq))?;
                 tup.end()
             }
             Val::Array(arr) => serialize_array(arr, serializer),
             Val::Set(set) => serialize_set(set, serializer),
             Val::StringLiteral(str) => serializer.serialize_str(str),
-            Val::Date(date) => serialize_naive_date(date, serializer),
-            Val::Decimal(dec) => serializer.serialize_f64(*dec),
-            Val::Integer(int) => serializer.serialize_i64(*int),
+            Val::Date(date) => serialize_date(date, serializer),
+            Val::Decimal(dec) => serialize_decimal(dec, serializer),
+            Val::Integer(int) => serialize_integer(int, serializer),
+            Val::Boolean(b) => serializer.serialize_bool(*b),
             Val::Identifier(id) => serializer.serialize_str(id),
+            Val::Mixed(entries) => serialize_mixed(entries, serializer),
+            Val::Tagged(tag, val) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(tag, val)?;
+                map.end()
+            }
+        }
+    }
+}
+
+/// Serializes pairs as a map without building a [`Val::Dict`] around them.
+struct Entries<'r, K, V>(&'r [(K, V)]);
+
+impl<K: Serialize, V: Serialize> Serialize for Entries<'_, K, V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in self.0 {
+            map.serialize_entry(k, v)?;
         }
+        map.end()
     }
 }
 
-fn serialize_dict<S>(dict: &Vec<(&str, Val)>, serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn serialize_decimal<S>(dec: &Number, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let mut map = serializer.serialize_map(Some(dict.len()))?;
-    for (k, v) in dict {
-        map.serialize_entry(k, v)?;
+    match dec.as_f64() {
+        Some(float) => serializer.serialize_f64(float),
+        None => serializer.serialize_str(dec.as_str()),
     }
-    map.end()
+}
+
+pub(crate) fn serialize_integer<S>(int: &Number, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    if let Some(int) = int.as_i64() {
+        serializer.serialize_i64(int)
+    } else if let Some(int) = int.as_u64() {
+        serializer.serialize_u64(int)
+    } else if let Some(int) = int.as_i128() {
+        serializer.serialize_i128(int)
+    } else {
+        serializer.serialize_str(int.as_str())
+    }
+}
+
+fn serialize_mixed<S>(entries: &Vec<(Option<&str>, Val)>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut seq = serializer.serialize_seq(Some(entries.len()))?;
+    for (key, val) in entries {
+        match key {
+            Some(key) => seq.serialize_element(&Entries(&[(key, val)]))?,
+            None => seq.serialize_element(val)?,
+        }
+    }
+    seq.end()
 }
 fn serialize_set<S>(set: &Vec<Val>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -80,21 +132,24 @@ where
     seq.end()
 }
 
-pub fn serialize_naive_date<S>(date: &NaiveDate, ser: S) -> Result<S::Ok, S::Error>
+pub fn serialize_date<S>(date: &ClausewitzDate, ser: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let val = (date.year(), date.month(), date.day());
-    let mut state = ser.serialize_tuple(3)?;
+    let val = (date.year(), date.month(), date.day(), date.hour());
+    let mut state = ser.serialize_tuple(if val.3 == 0 { 3 } else { 4 })?;
     state.serialize_element(&val.0)?;
     state.serialize_element(&val.1)?;
     state.serialize_element(&val.2)?;
+    if val.3 != 0 {
+        state.serialize_element(&val.3)?;
+    }
     state.end()
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IndexError {
-    err: String,
+    pub(crate) err: String,
 }
 impl Error for IndexError {}
 
@@ -105,155 +160,72 @@ impl Display for IndexError {
 }
 
 impl<'a> ClausewitzValue<'a> for Val<'a> {
-    fn get_set_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Vec<Val<'a>>, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Set(s) => Ok(s),
-            _ => Err(IndexError {
-                err: format!("{} is not the set you are looking for!", path),
-            }),
-        }
-    }
-    fn get_date_at_path<'b>(&'a self, path: &'b str) -> Result<&'a NaiveDate, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Date(d) => Ok(d),
-            _ => Err(IndexError {
-                err: format!("{} is not the date you are looking for!", path),
-            }),
-        }
-    }
-    fn get_string_at_path<'b>(&'a self, path: &'b str) -> Result<&'a str, IndexError> {
-        match self.get_at_path(path)? {
-            Val::StringLiteral(s) => Ok(s),
-            _ => Err(IndexError {
-                err: format!("{} is not the string you are looking for!", path),
-            }),
-        }
-    }
-    fn get_identifier_at_path<'b>(&'a self, path: &'b str) -> Result<&'a str, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Identifier(s) => Ok(s),
-            _ => Err(IndexError {
-                err: format!("{} is not the identifier you are looking for!", path),
-            }),
-        }
-    }
-    fn get_decimal_at_path<'b>(&'a self, path: &'b str) -> Result<&'a f64, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Decimal(f) => Ok(f),
-            _ => Err(IndexError {
-                err: format!("{} is not the decimal you are looking for!", path),
-            }),
-        }
-    }
-    fn get_integer_at_path<'b>(&'a self, path: &'b str) -> Result<&'a i64, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Integer(f) => Ok(f),
-            _ => Err(IndexError {
-                err: format!("{} is not the integer you are looking for!", path),
-            }),
-        }
-    }
-
-    fn get_number_at_path<'b>(&'a self, path: &'b str) -> Result<f64, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Integer(f) => Ok(*f as f64),
-            Val::Decimal(f) => Ok(*f),
-            _ => Err(IndexError {
-                err: format!(
-                    "{} is not the integer or decimal you are looking for!",
-                    path
-                ),
-            }),
-        }
-    }
-    fn get_array_at_path<'b>(
-        &'a self,
-        path: &'b str,
-    ) -> Result<&'a Vec<(u64, Val<'a>)>, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Array(v) => Ok(v),
-            _ => Err(IndexError {
-                err: format!("{} is not the array you are looking for!", path),
-            }),
-        }
-    }
-    fn get_dict_at_path<'b>(
-        &'a self,
-        path: &'b str,
-    ) -> Result<&'a Vec<(&'a str, Val<'a>)>, IndexError> {
-        match self.get_at_path(path)? {
-            Val::Dict(v) => Ok(v),
-            _ => Err(IndexError {
-                err: format!("{} is not the dict you are looking for!", path),
-            }),
-        }
-    }
-    fn get_numbered_dict_at_path<'b>(
-        &'a self,
-        path: &'b str,
-    ) -> Result<(&'a i64, &'a Vec<(&'a str, Val<'a>)>), IndexError> {
-        match self.get_at_path(path)? {
-            Val::NumberedDict(n, v) => Ok((n, v)),
-            _ => Err(IndexError {
-                err: format!("{} is not the numbered dict you are looking for!", path),
-            }),
-        }
-    }
-
     fn get_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Val<'a>, IndexError> {
         let path_components = path.split(".").collect::<Vec<_>>();
         path_components
             .into_iter()
-            .fold(Ok(self), |result, p| match result {
-                Ok(Val::Dict(dict_inner)) => {
-                    let filtered_values = dict_inner
-                        .iter()
-                        .filter_map(|(k, v)| if k == &p { Some(v) } else { None })
-                        .collect::<Vec<_>>();
-                    let val_for_key = filtered_values.first();
-
-                    match val_for_key {
-                        Some(val) => Ok(val),
-                        None => Err(IndexError {
-                            err: format!("Expected to find value with key {}", p),
-                        }),
-                    }
-                }
-
-                Ok(Val::NumberedDict(_number, num_dict_inner)) => {
-                    let filtered_values = num_dict_inner
-                        .iter()
-                        .filter_map(|(k, v)| if k == &p { Some(v) } else { None })
-                        .collect::<Vec<_>>();
-                    let dict_value = filtered_values.first();
-                    match dict_value {
-                        Some(val) => Ok(val),
-                        None => Err(IndexError {
-                            err: format!("Expected to find value with key {}", p),
-                        }),
-                    }
-                }
-
-                Ok(Val::Array(vec)) => {
-                    let index = p.parse::<u64>().unwrap();
-                    let element = vec
-                        .iter()
-                        .find_map(|(i, v)| if i == &index { Some(v) } else { None });
-                    match element {
-                        Some(val) => Ok(&val),
-                        None => Err(IndexError {
-                            err: format!("Expected to find value with index {}", p),
-                        }),
-                    }
-                }
-                Ok(Val::Set(_)) => Err(IndexError {
-                    err: format!("Cannot index a set with index {}", p),
+            .fold(Ok(self), |result, p| result.and_then(|val| index(val, p)))
+    }
+}
+
+fn index<'a>(val: &'a Val<'a>, p: &str) -> Result<&'a Val<'a>, IndexError> {
+    match val {
+        Val::Dict(dict_inner) => {
+            let filtered_values = dict_inner
+                .iter()
+                .filter_map(|(k, v)| if k == &p { Some(v) } else { None })
+                .collect::<Vec<_>>();
+            let val_for_key = filtered_values.first();
+
+            match val_for_key {
+                Some(val) => Ok(val),
+                None => Err(IndexError {
+                    err: format!("Expected to find value with key {}", p),
                 }),
-                Err(e) => Err(e),
-                _ => Err(IndexError {
-                    err: format!("Cannot index terminal values!"),
+            }
+        }
+
+        Val::NumberedDict(_number, num_dict_inner) => {
+            let filtered_values = num_dict_inner
+                .iter()
+                .filter_map(|(k, v)| if k == &p { Some(v) } else { None })
+                .collect::<Vec<_>>();
+            let dict_value = filtered_values.first();
+            match dict_value {
+                Some(val) => Ok(val),
+                None => Err(IndexError {
+                    err: format!("Expected to find value with key {}", p),
+                }),
+            }
+        }
+
+        Val::Array(vec) => {
+            let index = p.parse::<u64>().unwrap();
+            let element = vec
+                .iter()
+                .find_map(|(i, v)| if i == &index { Some(v) } else { None });
+            match element {
+                Some(val) => Ok(&val),
+                None => Err(IndexError {
+                    err: format!("Expected to find value with index {}", p),
                 }),
-            })
+            }
+        }
+        Val::Mixed(entries) => entries
+            .iter()
+            .find_map(|(k, v)| if k == &Some(p) { Some(v) } else { None })
+            .ok_or(IndexError {
+                err: format!("Expected to find value with key {}", p),
+            }),
+        // `color.rgb` names the tag explicitly, `color.0` looks through it
+        Val::Tagged(tag, inner) if tag == &p => Ok(inner),
+        Val::Tagged(_tag, inner) => index(inner, p),
+        Val::Set(_) => Err(IndexError {
+            err: format!("Cannot index a set with index {}", p),
+        }),
+        _ => Err(IndexError {
+            err: format!("Cannot index terminal values!"),
+        }),
     }
 }
 #[cfg(test)]
@@ -274,30 +246,144 @@ mod tests {
     }
     #[test]
     fn val_dict__given_key__returns_val_result() {
-        let val = Val::Dict(vec![("key", Val::Integer(10))]);
+        let val = Val::Dict(vec![("key", Val::Integer(Number::new("10")))]);
         let index = "key";
 
         let dict_val = val.get_at_path(index);
 
-        assert_eq!(Ok(&Val::Integer(10)), dict_val);
+        assert_eq!(Ok(&Val::Integer(Number::new("10"))), dict_val);
     }
     #[test]
     fn val_numbered_dict__given_key__returns_val_result() {
-        let val = Val::NumberedDict(0, vec![("key", Val::Integer(10))]);
+        let val = Val::NumberedDict(0, vec![("key", Val::Integer(Number::new("10")))]);
         let index = "key";
 
         let dict_val = val.get_at_path(index);
 
-        assert_eq!(Ok(&Val::Integer(10)), dict_val);
+        assert_eq!(Ok(&Val::Integer(Number::new("10"))), dict_val);
+    }
+    #[test]
+    fn val_dict__given_encoded_date_key__returns_date() {
+        let val = Val::Dict(vec![("flag_date", Val::Integer(Number::new("63568248")))]);
+
+        let date = val.get_encoded_date_at_path("flag_date");
+
+        assert_eq!(Ok(ClausewitzDate::from_ymd(2256, 8, 26)), date);
+        assert!(val.get_date_at_path("flag_date").is_err());
+    }
+    #[test]
+    fn val_dict__given_u64_key__returns_raw_number() {
+        let val = Val::Dict(vec![(
+            "id",
+            Val::Integer(Number::new("18446744073709551615")),
+        )]);
+
+        let number = val.get_raw_number_at_path("id").unwrap();
+
+        assert_eq!(Some(u64::MAX), number.as_u64());
+        assert!(val.get_integer_at_path("id").is_err());
+    }
+    #[test]
+    fn serialize__numbers__keep_precision() {
+        let val = Val::Dict(vec![
+            ("id", Val::Integer(Number::new("18446744073709551615"))),
+            ("income", Val::Decimal(Number::new("25.50000"))),
+        ]);
+
+        let json = serde_json::to_string(&val).unwrap();
+
+        assert_eq!(r#"{"id":18446744073709551615,"income":25.5}"#, json);
+    }
+    #[test]
+    fn serialize__numbered_dict_and_mixed__number_then_map() {
+        let val = Val::Dict(vec![
+            (
+                "numbered",
+                Val::NumberedDict(14, vec![("key", Val::Identifier("value"))]),
+            ),
+            (
+                "mixed",
+                Val::Mixed(vec![
+                    (None, Val::Integer(Number::new("1"))),
+                    (Some("key"), Val::Identifier("value")),
+                ]),
+            ),
+        ]);
+
+        let json = serde_json::to_string(&val).unwrap();
+
+        assert_eq!(
+            r#"{"numbered":[14,{"key":"value"}],"mixed":[1,{"key":"value"}]}"#,
+            json
+        );
+    }
+    #[test]
+    fn val_tagged__given_tag__returns_block() {
+        let block = Val::Set(vec![Val::Integer(Number::new("255"))]);
+        let val = Val::Dict(vec![("color", Val::Tagged("rgb", Box::new(block.clone())))]);
+
+        assert_eq!(Ok(&block), val.get_at_path("color.rgb"));
+        assert_eq!(Ok(("rgb", &block)), val.get_tagged_at_path("color"));
+    }
+    #[test]
+    fn val_tagged__given_key_of_block__looks_through_tag() {
+        let val = Val::Tagged(
+            "LIST",
+            Box::new(Val::Dict(vec![("key", Val::Integer(Number::new("10")))])),
+        );
+
+        assert_eq!(Ok(&Val::Integer(Number::new("10"))), val.get_at_path("key"));
+    }
+    #[test]
+    fn serialize__tagged__single_entry_map() {
+        let val = Val::Tagged(
+            "rgb",
+            Box::new(Val::Set(vec![
+                Val::Integer(Number::new("255")),
+                Val::Integer(Number::new("0")),
+                Val::Integer(Number::new("0")),
+            ])),
+        );
+
+        let json = serde_json::to_string(&val).unwrap();
+
+        assert_eq!(r#"{"rgb":[255,0,0]}"#, json);
+    }
+    #[test]
+    fn val_mixed__given_key__returns_val_result() {
+        let val = Val::Dict(vec![(
+            "mixed",
+            Val::Mixed(vec![
+                (None, Val::Integer(Number::new("1"))),
+                (Some("key"), Val::Integer(Number::new("10"))),
+            ]),
+        )]);
+
+        assert_eq!(
+            Ok(&Val::Integer(Number::new("10"))),
+            val.get_at_path("mixed.key")
+        );
+        assert_eq!(2, val.get_mixed_at_path("mixed").unwrap().len());
+    }
+    #[test]
+    fn serialize__mixed__values_and_single_entry_maps() {
+        let val = Val::Mixed(vec![
+            (None, Val::Integer(Number::new("1"))),
+            (Some("key"), Val::Identifier("value")),
+        ]);
+
+        let json = serde_json::to_string(&val).unwrap();
+
+        assert_eq!(r#"[1,{"key":"value"}]"#, json);
     }
     #[test]
     fn val_array__given_index__returns_val_result() {
-        let val = Val::Array(vec![(0, Val::Integer(10))]);
+        let val = Val::Array(vec![(0, Val::Integer(Number::new("10")))]);
         let index = "0";
 
         let dict_val = val.get_at_path(index);
 
-        assert_eq!(Ok(&Val::Integer(10)), dict_val);
+        assert_eq!(Ok(&Val::Integer(Number::new("10"))), dict_val);
     }
 
     #[test]
@@ -1,6 +1,18 @@
-use super::{bracketed::bracketed, quoted::quoted, unquoted::unquoted, val::Val, Res};
+use super::{
+    bracketed::{bracketed, tagged},
+    options::ParseOptions,
+    quoted::quoted,
+    unquoted::unquoted,
+    val::Val,
+    Res,
+};
 use nom::branch::alt;
 #[inline(always)]
-pub fn value<'a>(input: &'a str) -> Res<&'a str, Val<'a>> {
-    alt((bracketed, quoted, unquoted))(input)
+pub fn value<'a>(input: &'a str, options: &ParseOptions) -> Res<&'a str, Val<'a>> {
+    alt((
+        |i| bracketed(i, options),
+        |i| quoted(i, options),
+        |i| tagged(i, options),
+        |i| unquoted(i, options),
+    ))(input)
 }
@@ -0,0 +1,204 @@
+//! The walk over blocks shared by [`super::skim::Skim`] and [`super::projection::Projection`].
+//! Entries are found with the [`super::skip`] scanners, and a [`Visitor`] decides for each value
+//! whether to parse it, step over it or look inside it.
+
+use super::{
+    path::Step,
+    shape::{shape, Shape},
+    skip::{skip_space, string_end, token_end, value_end},
+};
+
+/// A walk fails with the byte offset and what was wrong there.
+pub(crate) type Walked<T> = Result<T, (usize, &'static str)>;
+
+/// What a visitor kept of the entries of a block, `None` keys for bare values.
+pub(crate) type Entries<'a, T> = Vec<(Option<&'a str>, T)>;
+
+pub(crate) trait Visitor<'a> {
+    /// What is kept of a visited value.
+    type Item;
+
+    fn input(&self) -> &'a str;
+
+    /// The path to the value being visited, kept up to date by the walk.
+    fn path(&mut self) -> &mut Vec<Step<'a>>;
+
+    /// Handles the value at `start`, returning where it ends and what to keep of it.
+    fn visit(&mut self, key: Option<&'a str>, start: usize) -> Walked<(usize, Option<Self::Item>)>;
+
+    /// Whether the walk can stop before the next entry.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// A block as a full parse would see it, with the entries the visitor kept.
+pub(crate) enum Block<'a, T> {
+    Empty,
+    /// `{ 14 { key=value } }`, whose entries are visited as if the number was not there.
+    Numbered(&'a str, Entries<'a, T>),
+    Shaped(Shape, Entries<'a, T>),
+}
+
+/// Visits the entries of a block body starting at `from`, returning where it ends: at the
+/// closing brace when `closing`, otherwise at the end of the input.
+pub(crate) fn entries<'a, V: Visitor<'a>>(
+    visitor: &mut V,
+    from: usize,
+    closing: bool,
+) -> Walked<(usize, Entries<'a, V::Item>)> {
+    let input = visitor.input();
+    let bytes = input.as_bytes();
+    let mut entries = vec![];
+    let mut index = 0;
+    let mut i = skip_space(bytes, from);
+    while !visitor.finished() {
+        match bytes.get(i) {
+            None if !closing => break,
+            Some(b'}') if closing => break,
+            None => return Err((i, "unclosed block")),
+            Some(b'}') | Some(b'=') => return Err((i, "expected a key or value")),
+            _ => {}
+        }
+        let token = match bytes[i] {
+            b'"' => string_end(bytes, i).ok_or((i, "unterminated string"))?,
+            b'{' => i,
+            _ => token_end(bytes, i),
+        };
+        let equals = skip_space(bytes, token);
+        let (key, start) = if token > i && bytes.get(equals) == Some(&b'=') {
+            let key = match bytes[i] {
+                b'"' => &input[i + 1..token - 1],
+                _ => &input[i..token],
+            };
+            visitor.path().push(Step::Key(key));
+            (Some(key), skip_space(bytes, equals + 1))
+        } else {
+            visitor.path().push(Step::Index(index));
+            index += 1;
+            (None, i)
+        };
+        let visited = visitor.visit(key, start);
+        visitor.path().pop();
+        let (end, item) = visited?;
+        if let Some(item) = item {
+            entries.push((key, item));
+        }
+        i = skip_space(bytes, end);
+    }
+    Ok((i, entries))
+}
+
+/// Visits the entries of the block opening at `open`, returning where the block ends.
+pub(crate) fn block<'a, V: Visitor<'a>>(
+    visitor: &mut V,
+    open: usize,
+) -> Walked<(usize, Block<'a, V::Item>)> {
+    let input = visitor.input();
+    let bytes = input.as_bytes();
+    let body = open + 1;
+    match shape(&input[body..]) {
+        Shape::Empty => {
+            let end = value_end(bytes, open).ok_or((open, "unclosed block"))?;
+            Ok((end, Block::Empty))
+        }
+        Shape::NumberedDict => {
+            let number_start = skip_space(bytes, body);
+            let number_end = token_end(bytes, number_start);
+            let inner = skip_space(bytes, number_end);
+            let (close, entries) = entries(visitor, inner + 1, true)?;
+            let block = Block::Numbered(&input[number_start..number_end], entries);
+            if visitor.finished() {
+                return Ok((close, block));
+            }
+            let end = skip_space(bytes, close + 1);
+            match bytes.get(end) {
+                Some(b'}') => Ok((end + 1, block)),
+                _ => Err((end, "expected }")),
+            }
+        }
+        shape => {
+            let (close, entries) = entries(visitor, body, true)?;
+            Ok((close + 1, Block::Shaped(shape, entries)))
+        }
+    }
+}
+
+/// The tag and opening brace of a tagged block like `rgb { .. }` starting at `from`.
+pub(crate) fn tagged(input: &str, from: usize) -> Option<(&str, usize)> {
+    let bytes = input.as_bytes();
+    if matches!(bytes.get(from), None | Some(b'{' | b'"')) {
+        return None;
+    }
+    let tag_end = token_end(bytes, from);
+    let open = skip_space(bytes, tag_end);
+    (bytes.get(open) == Some(&b'{')).then(|| (&input[from..tag_end], open))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looks inside every block, keeping the path of each value.
+    struct Paths<'a> {
+        input: &'a str,
+        path: Vec<Step<'a>>,
+        seen: Vec<Vec<Step<'a>>>,
+    }
+
+    impl<'a> Visitor<'a> for Paths<'a> {
+        type Item = ();
+
+        fn input(&self) -> &'a str {
+            self.input
+        }
+
+        fn path(&mut self) -> &mut Vec<Step<'a>> {
+            &mut self.path
+        }
+
+        fn visit(&mut self, _: Option<&'a str>, start: usize) -> Walked<(usize, Option<()>)> {
+            self.seen.push(self.path.clone());
+            let open = tagged(self.input, start).map_or(start, |(_, open)| open);
+            match self.input.as_bytes()[open] {
+                b'{' => Ok((block(self, open)?.0, None)),
+                _ => Ok((value_end(self.input.as_bytes(), start).unwrap(), None)),
+            }
+        }
+    }
+
+    #[test]
+    fn entries__numbered_and_tagged__looked_through() {
+        let input = "a={ 14 { b=1 } } \"c d\"=rgb { 1 } e";
+        let mut paths = Paths {
+            input,
+            path: vec![],
+            seen: vec![],
+        };
+        let (end, _) = entries(&mut paths, 0, false).unwrap();
+        assert_eq!(end, input.len());
+        assert_eq!(
+            paths.seen,
+            vec![
+                vec![Step::Key("a")],
+                vec![Step::Key("a"), Step::Key("b")],
+                vec![Step::Key("c d")],
+                vec![Step::Key("c d"), Step::Index(0)],
+                vec![Step::Index(0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn entries__unclosed_block__located_err() {
+        let mut paths = Paths {
+            input: "a={ b={ }",
+            path: vec![],
+            seen: vec![],
+        };
+        assert_eq!(
+            entries(&mut paths, 0, false).map(|_| ()),
+            Err((9, "unclosed block"))
+        );
+    }
+}
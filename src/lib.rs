@@ -1,32 +1,184 @@
 mod clausewitz;
+pub mod localisation;
+pub mod stellaris;
 
-use chrono::NaiveDate;
+pub use bumpalo::Bump;
 pub use clausewitz::{
+    arena::{root_arena, ArenaVal},
     bracketed::key_value,
-    root::{cheat_root, root},
+    cache::{CacheError, CachedDocument},
+    date::{ClausewitzDate, DateParseError},
+    dialect::Dialect,
+    document::Document,
+    header::{Encoding, Game, Header, HeaderError},
+    incremental::{Changes, Incremental},
+    inline_script::{Expanded, InlineScriptError, InlineScripts, Location},
+    intern::{Interner, Symbol},
+    lazy::LazyVal,
+    lexer::{Lexer, Token, TokenKind},
+    number::{Fixed, Number},
+    options::{EmptyBlock, InvalidDates, ParseOptions},
+    parallel::Parallel,
+    path::{PathPattern, Step},
+    projection::{root_projected, Projection},
+    root::{cheat_root, cheat_root_with_options, root, root_with_options},
+    scripted_variables::{ScriptedVariableError, ScriptedVariables},
+    section::{Section, SectionError, SectionIndex},
     skim,
+    structure::{Children, StructuralIndex, StructureError},
+    tape::{root_tape, Cursor, Elements, Tape},
+    transcode::Transcoder,
     val::{IndexError, Val},
 };
 
 pub trait ClausewitzValue<'a> {
-    fn get_set_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Vec<Val<'a>>, IndexError>;
-    fn get_date_at_path<'b>(&'a self, path: &'b str) -> Result<&'a NaiveDate, IndexError>;
-    fn get_string_at_path<'b>(&'a self, path: &'b str) -> Result<&'a str, IndexError>;
-    fn get_identifier_at_path<'b>(&'a self, path: &'b str) -> Result<&'a str, IndexError>;
-    fn get_decimal_at_path<'b>(&'a self, path: &'b str) -> Result<&'a f64, IndexError>;
-    fn get_integer_at_path<'b>(&'a self, path: &'b str) -> Result<&'a i64, IndexError>;
-    fn get_number_at_path<'b>(&'a self, path: &'b str) -> Result<f64, IndexError>;
+    fn get_set_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Vec<Val<'a>>, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Set(s) => Ok(s),
+            _ => Err(IndexError {
+                err: format!("{} is not the set you are looking for!", path),
+            }),
+        }
+    }
+    fn get_date_at_path<'b>(&'a self, path: &'b str) -> Result<ClausewitzDate, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Date(d) => Ok(*d),
+            _ => Err(IndexError {
+                err: format!("{} is not the date you are looking for!", path),
+            }),
+        }
+    }
+    /// A date written as the integer some fields use, e.g. `flag_date=63568248`.
+    fn get_encoded_date_at_path<'b>(&'a self, path: &'b str) -> Result<ClausewitzDate, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Integer(encoded) => encoded.as_i64().and_then(ClausewitzDate::from_encoded),
+            _ => None,
+        }
+        .ok_or(IndexError {
+            err: format!("{} is not the encoded date you are looking for!", path),
+        })
+    }
+    fn get_boolean_at_path<'b>(&'a self, path: &'b str) -> Result<&'a bool, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Boolean(b) => Ok(b),
+            _ => Err(IndexError {
+                err: format!("{} is not the boolean you are looking for!", path),
+            }),
+        }
+    }
+    fn get_string_at_path<'b>(&'a self, path: &'b str) -> Result<&'a str, IndexError> {
+        match self.get_at_path(path)? {
+            Val::StringLiteral(s) => Ok(s),
+            _ => Err(IndexError {
+                err: format!("{} is not the string you are looking for!", path),
+            }),
+        }
+    }
+    fn get_identifier_at_path<'b>(&'a self, path: &'b str) -> Result<&'a str, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Identifier(s) => Ok(s),
+            _ => Err(IndexError {
+                err: format!("{} is not the identifier you are looking for!", path),
+            }),
+        }
+    }
+    fn get_decimal_at_path<'b>(&'a self, path: &'b str) -> Result<f64, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Decimal(f) => f.as_f64(),
+            _ => None,
+        }
+        .ok_or(IndexError {
+            err: format!("{} is not the decimal you are looking for!", path),
+        })
+    }
+    fn get_integer_at_path<'b>(&'a self, path: &'b str) -> Result<i64, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Integer(i) => i.as_i64(),
+            _ => None,
+        }
+        .ok_or(IndexError {
+            err: format!("{} is not the integer you are looking for!", path),
+        })
+    }
+
+    fn get_number_at_path<'b>(&'a self, path: &'b str) -> Result<f64, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Integer(n) | Val::Decimal(n) => n.as_f64(),
+            _ => None,
+        }
+        .ok_or(IndexError {
+            err: format!(
+                "{} is not the integer or decimal you are looking for!",
+                path
+            ),
+        })
+    }
+    fn get_raw_number_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Number<'a>, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Integer(n) | Val::Decimal(n) => Ok(n),
+            _ => Err(IndexError {
+                err: format!(
+                    "{} is not the integer or decimal you are looking for!",
+                    path
+                ),
+            }),
+        }
+    }
+    fn get_tagged_at_path<'b>(
+        &'a self,
+        path: &'b str,
+    ) -> Result<(&'a str, &'a Val<'a>), IndexError> {
+        match self.get_at_path(path)? {
+            Val::Tagged(tag, val) => Ok((tag, val)),
+            _ => Err(IndexError {
+                err: format!("{} is not the tagged block you are looking for!", path),
+            }),
+        }
+    }
+    fn get_mixed_at_path<'b>(
+        &'a self,
+        path: &'b str,
+    ) -> Result<&'a Vec<(Option<&'a str>, Val<'a>)>, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Mixed(v) => Ok(v),
+            _ => Err(IndexError {
+                err: format!("{} is not the mixed container you are looking for!", path),
+            }),
+        }
+    }
     fn get_array_at_path<'b>(
         &'a self,
         path: &'b str,
-    ) -> Result<&'a Vec<(u64, Val<'a>)>, IndexError>;
+    ) -> Result<&'a Vec<(u64, Val<'a>)>, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Array(v) => Ok(v),
+            _ => Err(IndexError {
+                err: format!("{} is not the array you are looking for!", path),
+            }),
+        }
+    }
     fn get_dict_at_path<'b>(
         &'a self,
         path: &'b str,
-    ) -> Result<&'a Vec<(&'a str, Val<'a>)>, IndexError>;
+    ) -> Result<&'a Vec<(&'a str, Val<'a>)>, IndexError> {
+        match self.get_at_path(path)? {
+            Val::Dict(v) => Ok(v),
+            _ => Err(IndexError {
+                err: format!("{} is not the dict you are looking for!", path),
+            }),
+        }
+    }
     fn get_numbered_dict_at_path<'b>(
         &'a self,
         path: &'b str,
-    ) -> Result<(&'a i64, &'a Vec<(&'a str, Val<'a>)>), IndexError>;
+    ) -> Result<(&'a i64, &'a Vec<(&'a str, Val<'a>)>), IndexError> {
+        match self.get_at_path(path)? {
+            Val::NumberedDict(n, v) => Ok((n, v)),
+            _ => Err(IndexError {
+                err: format!("{} is not the numbered dict you are looking for!", path),
+            }),
+        }
+    }
+
     fn get_at_path<'b>(&'a self, path: &'b str) -> Result<&'a Val<'a>, IndexError>;
 }
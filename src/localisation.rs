@@ -0,0 +1,621 @@
+//! Paradox localisation files: a `l_english:` header followed by `key:0 "text"` entries.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Location;
+
+#[derive(Debug)]
+pub struct LocalisationError {
+    err: String,
+}
+impl Error for LocalisationError {}
+
+impl Display for LocalisationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.err, f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub version: Option<u32>,
+    /// The text with `\n`, `\"` and `\\` unescaped.
+    pub text: String,
+    pub location: Location,
+}
+
+/// Problems found while loading or by [`Localisation::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// A key defined twice outside of a `replace` directory; the later definition wins.
+    Duplicate {
+        key: String,
+        first: Location,
+        second: Location,
+    },
+    /// A `$reference$` to a key which is not defined.
+    BrokenReference {
+        key: String,
+        reference: String,
+        location: Location,
+    },
+    /// Keys which reference each other through `$key$`, in the order they are reached.
+    Cycle { keys: Vec<String> },
+    /// A line which is neither blank, a comment nor an entry.
+    Malformed { location: Location },
+}
+
+/// A piece of localised text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// `§Y`
+    Color(char),
+    /// `§!`
+    EndColor,
+    /// `[Root.GetName]`
+    Command(&'a str),
+    /// `£energy£`
+    Icon(&'a str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Colors and icons are dropped and commands are kept as `[Command]`.
+    Plain,
+    /// HTML, with colors and commands as `<span>`s and icons as `<img>`s.
+    Html,
+}
+
+/// Every entry of one language.
+#[derive(Debug, Clone, Default)]
+pub struct Localisation {
+    language: String,
+    entries: HashMap<String, Entry>,
+    issues: Vec<Issue>,
+}
+
+impl Localisation {
+    pub fn new(language: &str) -> Self {
+        Localisation {
+            language: language.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads every `.yml` file below `dir` with a `l_<language>:` header. Files inside a `replace`
+    /// directory are loaded last and override other definitions.
+    pub fn load_dir<P: AsRef<Path>>(dir: P, language: &str) -> Result<Self, LocalisationError> {
+        let mut files = vec![];
+        yml_files(dir.as_ref(), &mut files)?;
+        files.sort_by_key(|path| {
+            (
+                path.components().any(|c| c.as_os_str() == "replace"),
+                path.clone(),
+            )
+        });
+
+        let mut localisation = Localisation::new(language);
+        for path in files {
+            let bytes = fs::read(&path).map_err(|e| LocalisationError {
+                err: format!("can not read {}: {}", path.display(), e),
+            })?;
+            let text = String::from_utf8_lossy(&bytes);
+            let replace = path.components().any(|c| c.as_os_str() == "replace");
+            localisation.add_str(&path, &text, replace);
+        }
+        Ok(localisation)
+    }
+
+    /// Adds the entries of one file, if its header is for this language. Returns whether it was.
+    pub fn add_str<P: AsRef<Path>>(&mut self, path: P, text: &str, replace: bool) -> bool {
+        let path = path.as_ref();
+        let mut lines = text.trim_start_matches('\u{feff}').lines().enumerate();
+        let header = lines.find(|(_, line)| !is_blank(line));
+        let header = header.map(|(_, line)| line.trim());
+        if header != Some(format!("l_{}:", self.language).as_str()) {
+            return false;
+        }
+
+        for (index, line) in lines {
+            if is_blank(line) {
+                continue;
+            }
+            let location = |column| Location {
+                file: path.to_owned(),
+                line: index + 1,
+                column,
+            };
+            let Some((key, version, text)) = parse_entry(line) else {
+                self.issues.push(Issue::Malformed {
+                    location: location(1),
+                });
+                continue;
+            };
+            let entry = Entry {
+                version,
+                text: unescape(text),
+                location: location(line.len() - line.trim_start().len() + 1),
+            };
+            if let Some(previous) = self.entries.get(key) {
+                if !replace {
+                    self.issues.push(Issue::Duplicate {
+                        key: key.to_owned(),
+                        first: previous.location.clone(),
+                        second: entry.location.clone(),
+                    });
+                }
+            }
+            self.entries.insert(key.to_owned(), entry);
+        }
+        true
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The text of `key` with every `$reference$` to another key replaced by that key's resolved
+    /// text. References to keys which do not exist, like `$VALUE$` filled in by the game, are kept.
+    pub fn resolve(&self, key: &str) -> Result<String, LocalisationError> {
+        let mut resolved = HashMap::new();
+        self.resolve_key(key, &mut vec![], &mut resolved)
+            .map_err(|err| match err {
+                Unresolved::Missing(key) => LocalisationError {
+                    err: format!("{} is not defined", key),
+                },
+                Unresolved::Cycle(keys) => LocalisationError {
+                    err: format!("{} reference each other", keys.join(" -> ")),
+                },
+            })?;
+        Ok(resolved.remove(key).unwrap_or_default())
+    }
+
+    pub fn render(&self, key: &str, style: Style) -> Result<String, LocalisationError> {
+        Ok(render(&self.resolve(key)?, style))
+    }
+
+    /// The duplicates and malformed lines found while loading, then every broken reference and
+    /// cycle.
+    pub fn check(&self) -> Vec<Issue> {
+        let mut issues = self.issues.clone();
+        let mut keys: Vec<_> = self.entries.keys().map(String::as_str).collect();
+        keys.sort();
+        for key in &keys {
+            let entry = &self.entries[*key];
+            for (reference, _) in references(&entry.text) {
+                if !self.entries.contains_key(reference) {
+                    issues.push(Issue::BrokenReference {
+                        key: key.to_string(),
+                        reference: reference.to_owned(),
+                        location: entry.location.clone(),
+                    });
+                }
+            }
+        }
+        let mut search = Search::default();
+        for key in keys {
+            self.find_cycles(key, &mut search);
+        }
+        issues.extend(search.cycles.into_iter().map(|keys| Issue::Cycle { keys }));
+        issues
+    }
+
+    /// Follows the references of `key` depth first without building any text, keeping each cycle
+    /// found once, from its smallest key.
+    fn find_cycles<'s>(&'s self, key: &str, search: &mut Search<'s>) {
+        let Some((key, entry)) = self.entries.get_key_value(key) else {
+            return;
+        };
+        if search.done.contains(key.as_str()) {
+            return;
+        }
+        if search.on_stack.contains(key.as_str()) {
+            let start = search.stack.iter().position(|k| *k == key).unwrap_or(0);
+            let cycle = &search.stack[start..];
+            let smallest = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap_or(0);
+            let keys: Vec<_> = cycle[smallest..]
+                .iter()
+                .chain(&cycle[..smallest])
+                .map(|k| k.to_string())
+                .collect();
+            if !search.cycles.contains(&keys) {
+                search.cycles.push(keys);
+            }
+            return;
+        }
+        search.stack.push(key.as_str());
+        search.on_stack.insert(key);
+        for (reference, _) in references(&entry.text) {
+            self.find_cycles(reference, search);
+        }
+        search.stack.pop();
+        search.on_stack.remove(key.as_str());
+        search.done.insert(key);
+    }
+
+    /// Resolves `key` into `resolved`, where the text of every key resolved on the way is kept so
+    /// that each is only resolved once.
+    fn resolve_key<'s>(
+        &'s self,
+        key: &str,
+        stack: &mut Vec<&'s str>,
+        resolved: &mut HashMap<&'s str, String>,
+    ) -> Result<(), Unresolved> {
+        if resolved.contains_key(key) {
+            return Ok(());
+        }
+        if let Some(start) = stack.iter().position(|k| *k == key) {
+            let cycle = stack[start..].iter().map(|k| k.to_string()).collect();
+            return Err(Unresolved::Cycle(cycle));
+        }
+        let (key, entry) = self
+            .entries
+            .get_key_value(key)
+            .ok_or_else(|| Unresolved::Missing(key.to_owned()))?;
+        stack.push(key);
+        let mut text = String::with_capacity(entry.text.len());
+        let mut copied = 0;
+        for (reference, range) in references(&entry.text) {
+            if !self.entries.contains_key(reference) {
+                continue;
+            }
+            self.resolve_key(reference, stack, resolved)?;
+            text.push_str(&entry.text[copied..range.start]);
+            text.push_str(&resolved[reference]);
+            copied = range.end;
+        }
+        text.push_str(&entry.text[copied..]);
+        stack.pop();
+        resolved.insert(key, text);
+        Ok(())
+    }
+}
+
+/// The state of the depth first search of [`Localisation::check`] for cycles.
+#[derive(Default)]
+struct Search<'s> {
+    /// Keys whose references have all been followed.
+    done: HashSet<&'s str>,
+    /// The keys being followed, outermost first, and the same as a set.
+    stack: Vec<&'s str>,
+    on_stack: HashSet<&'s str>,
+    cycles: Vec<Vec<String>>,
+}
+
+enum Unresolved {
+    Missing(String),
+    Cycle(Vec<String>),
+}
+
+/// Splits text into colors, commands, icons and the plain text between them.
+pub fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find(['§', '[', '£']) {
+        let (before, special) = rest.split_at(start);
+        let mut chars = special.chars();
+        let (segment, len) = match chars.next() {
+            Some('§') => match chars.next() {
+                Some('!') => (Some(Segment::EndColor), '§'.len_utf8() + 1),
+                Some(color) => (
+                    Some(Segment::Color(color)),
+                    '§'.len_utf8() + color.len_utf8(),
+                ),
+                None => (None, special.len()),
+            },
+            Some('[') => match special.find(']') {
+                Some(end) => (Some(Segment::Command(&special[1..end])), end + 1),
+                None => (None, special.len()),
+            },
+            _ => match special['£'.len_utf8()..].find(['£', ' ']) {
+                Some(end) if special['£'.len_utf8() + end..].starts_with('£') => (
+                    Some(Segment::Icon(
+                        &special['£'.len_utf8()..'£'.len_utf8() + end],
+                    )),
+                    end + 2 * '£'.len_utf8(),
+                ),
+                _ => (None, '£'.len_utf8()),
+            },
+        };
+        match segment {
+            Some(segment) => {
+                if !before.is_empty() {
+                    segments.push(Segment::Text(before));
+                }
+                segments.push(segment);
+            }
+            None => segments.push(Segment::Text(&rest[..start + len])),
+        }
+        rest = &special[len..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    segments
+}
+
+/// Renders resolved text, closing any colors which were left open.
+pub fn render(text: &str, style: Style) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut open = 0;
+    for segment in segments(text) {
+        match (style, segment) {
+            (Style::Plain, Segment::Text(text)) => out.push_str(text),
+            (Style::Plain, Segment::Command(command)) => {
+                out.push('[');
+                out.push_str(command);
+                out.push(']');
+            }
+            (Style::Plain, _) => {}
+            (Style::Html, Segment::Text(text)) => out.push_str(&escape_html(text)),
+            (Style::Html, Segment::Color(color)) => {
+                open += 1;
+                out.push_str(&format!(
+                    "<span class=\"color-{}\">",
+                    escape_html(&color.to_string())
+                ));
+            }
+            (Style::Html, Segment::EndColor) if open > 0 => {
+                open -= 1;
+                out.push_str("</span>");
+            }
+            (Style::Html, Segment::EndColor) => {}
+            (Style::Html, Segment::Command(command)) => out.push_str(&format!(
+                "<span class=\"command\">{}</span>",
+                escape_html(command)
+            )),
+            (Style::Html, Segment::Icon(icon)) => out.push_str(&format!(
+                "<img class=\"icon\" alt=\"{}\">",
+                escape_html(icon)
+            )),
+        }
+    }
+    for _ in 0..open {
+        out.push_str("</span>");
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>")
+}
+
+fn is_blank(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// `key:0 "text"`, where the text runs to the last quote on the line.
+fn parse_entry(line: &str) -> Option<(&str, Option<u32>, &str)> {
+    let (key, rest) = line.trim().split_once(':')?;
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let version = rest[..digits].parse().ok();
+    let text = rest[digits..].trim_start().strip_prefix('"')?;
+    let end = text.rfind('"')?;
+    Some((key, version, &text[..end]))
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some(escaped @ ('"' | '\\'))) => {
+                out.push(escaped);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The `$key$` references in text, ignoring any `|format` after the key.
+fn references(text: &str) -> impl Iterator<Item = (&str, std::ops::Range<usize>)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || loop {
+        let start = offset + text[offset..].find('$')?;
+        let end = start + 1 + text[start + 1..].find('$')?;
+        let inner = &text[start + 1..end];
+        let key = inner.split('|').next().unwrap_or(inner);
+        if !key.is_empty() && !key.contains(char::is_whitespace) {
+            offset = end + 1;
+            return Some((key, start..end + 1));
+        }
+        offset = end;
+    })
+}
+
+fn yml_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), LocalisationError> {
+    let entries = fs::read_dir(dir).map_err(|e| LocalisationError {
+        err: format!("can not read {}: {}", dir.display(), e),
+    })?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            yml_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "yml") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english(text: &str) -> Localisation {
+        let mut localisation = Localisation::new("english");
+        assert!(localisation.add_str("test_l_english.yml", text, false));
+        localisation
+    }
+
+    #[test]
+    fn add_str__entries__unescaped() {
+        let localisation = english(
+            "\u{feff}l_english:\n # comment\n greeting:0 \"Hello \\\"World\\\"\\nBye\"\n bare: \"no version\" # trailing\n",
+        );
+        let entry = localisation.get("greeting").unwrap();
+        assert_eq!(entry.version, Some(0));
+        assert_eq!(entry.text, "Hello \"World\"\nBye");
+        assert_eq!(entry.location.line, 3);
+        assert_eq!(localisation.get("bare").unwrap().version, None);
+        assert_eq!(localisation.get("bare").unwrap().text, "no version");
+    }
+
+    #[test]
+    fn add_str__other_language__skipped() {
+        let mut localisation = Localisation::new("english");
+        assert!(!localisation.add_str("x_l_french.yml", "l_french:\n a:0 \"b\"", false));
+        assert!(localisation.is_empty());
+    }
+
+    #[test]
+    fn resolve__nested_references__substituted() {
+        let localisation = english(
+            "l_english:\n a:0 \"$b$ and $c|Y$ and $VALUE$\"\n b:0 \"B with $c$\"\n c:0 \"C\"",
+        );
+        assert_eq!(
+            localisation.resolve("a").unwrap(),
+            "B with C and C and $VALUE$"
+        );
+    }
+
+    #[test]
+    fn resolve__cycle__err() {
+        let localisation = english("l_english:\n a:0 \"$b$\"\n b:0 \"$a$\"");
+        assert!(localisation.resolve("a").is_err());
+        assert_eq!(
+            localisation.check(),
+            vec![Issue::Cycle {
+                keys: vec!["a".to_owned(), "b".to_owned()]
+            }]
+        );
+    }
+
+    #[test]
+    fn check__references_doubling_each_key__fast() {
+        let mut text = "l_english:\n".to_owned();
+        for i in 0..64 {
+            text.push_str(&format!(" k{}:0 \"$k{}$ $k{}$\"\n", i, i + 1, i + 1));
+        }
+        text.push_str(" k64:0 \"$k0$\"\n");
+        let localisation = english(&text);
+
+        let issues = localisation.check();
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0], Issue::Cycle { keys } if keys.len() == 65 && keys[0] == "k0"));
+        assert!(localisation.resolve("k0").is_err());
+    }
+
+    #[test]
+    fn resolve__shared_references__resolved_once() {
+        let localisation = english("l_english:\n a:0 \"$b$$b$\"\n b:0 \"$c$-$c$\"\n c:0 \"C\"");
+        assert_eq!(localisation.resolve("a").unwrap(), "C-CC-C");
+    }
+
+    #[test]
+    fn check__duplicates_and_broken_references__reported() {
+        let localisation = english("l_english:\n a:0 \"x\"\n a:1 \"$missing$\"\n not an entry");
+        let issues = localisation.check();
+        assert_eq!(issues.len(), 3);
+        assert!(matches!(&issues[0], Issue::Duplicate { key, first, second }
+            if key == "a" && first.line == 2 && second.line == 3));
+        assert!(matches!(&issues[1], Issue::Malformed { location } if location.line == 4));
+        assert!(
+            matches!(&issues[2], Issue::BrokenReference { reference, .. } if reference == "missing")
+        );
+    }
+
+    #[test]
+    fn segments__markup__split() {
+        assert_eq!(
+            segments("§YHi§! [Root.GetName] costs £energy£ 5"),
+            vec![
+                Segment::Color('Y'),
+                Segment::Text("Hi"),
+                Segment::EndColor,
+                Segment::Text(" "),
+                Segment::Command("Root.GetName"),
+                Segment::Text(" costs "),
+                Segment::Icon("energy"),
+                Segment::Text(" 5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn render__plain_and_html__styled() {
+        let localisation =
+            english("l_english:\n a:0 \"§YBig <ship>§! for [Root.GetName] £energy£\"");
+        assert_eq!(
+            localisation.render("a", Style::Plain).unwrap(),
+            "Big <ship> for [Root.GetName] "
+        );
+        assert_eq!(
+            localisation.render("a", Style::Html).unwrap(),
+            "<span class=\"color-Y\">Big &lt;ship&gt;</span> for <span class=\"command\">Root.GetName</span> <img class=\"icon\" alt=\"energy\">"
+        );
+    }
+
+    #[test]
+    fn render__unclosed_color__closed() {
+        assert_eq!(
+            render("§Rred", Style::Html),
+            "<span class=\"color-R\">red</span>"
+        );
+    }
+
+    #[test]
+    fn load_dir__replace__overrides_without_duplicate() {
+        let dir = std::env::temp_dir().join(format!(
+            "clausewitz_localisation_load_dir_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(dir.join("replace")).unwrap();
+        fs::write(dir.join("a_l_english.yml"), "l_english:\n a:0 \"old\"").unwrap();
+        fs::write(
+            dir.join("replace/a_l_english.yml"),
+            "l_english:\n a:0 \"new\"",
+        )
+        .unwrap();
+        fs::write(dir.join("a_l_german.yml"), "l_german:\n a:0 \"alt\"").unwrap();
+
+        let localisation = Localisation::load_dir(&dir, "english");
+        fs::remove_dir_all(&dir).unwrap();
+        let localisation = localisation.unwrap();
+        assert_eq!(localisation.get("a").unwrap().text, "new");
+        assert!(localisation.check().is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+    str::FromStr,
+};
+
+use serde_derive::Serialize;
+
+use crate::{root, ClausewitzDate, ClausewitzValue, IndexError, Val};
+
+#[derive(Debug)]
+pub struct MetaError {
+    err: String,
+}
+impl Error for MetaError {}
+
+impl Display for MetaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.err, f)
+    }
+}
+
+impl From<IndexError> for MetaError {
+    fn from(e: IndexError) -> Self {
+        MetaError { err: e.err }
+    }
+}
+
+/// A game version like `Cepheus v3.4.5`, with the build from `version_control_revision`. Versions
+/// compare by number, then build, with no build before any build; the release name is not
+/// compared.
+#[derive(Debug, Clone, Serialize)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub build: Option<u64>,
+    /// The release name, like `Cepheus`.
+    pub name: String,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+            build: None,
+            name: String::new(),
+        }
+    }
+
+    /// Whether saves from this version can be loaded by `other`, which needs the same major and
+    /// minor version.
+    pub fn is_compatible_with(&self, other: &Version) -> bool {
+        self.major == other.major && self.minor == other.minor
+    }
+}
+
+impl Version {
+    fn number(&self) -> (u32, u32, u32, Option<u64>) {
+        (self.major, self.minor, self.patch, self.build)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.number() == other.number()
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.number().cmp(&other.number())
+    }
+}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.number().hash(state);
+    }
+}
+
+impl FromStr for Version {
+    type Err = MetaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || MetaError {
+            err: format!("{} is not a version", s),
+        };
+        let (name, number) = match s.rsplit_once(' ') {
+            Some((name, number)) => (name, number),
+            None => ("", s),
+        };
+        let mut parts = number
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse::<u32>().map_err(|_| err()));
+        let major = parts.next().ok_or_else(err)??;
+        let minor = parts.next().unwrap_or(Ok(0))?;
+        let patch = parts.next().unwrap_or(Ok(0))?;
+        if parts.next().is_some() {
+            return Err(err());
+        }
+        Ok(Version {
+            major,
+            minor,
+            patch,
+            build: None,
+            name: name.to_owned(),
+        })
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.name.is_empty() {
+            write!(f, "{} ", self.name)?;
+        }
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(build) = self.build {
+            write!(f, " ({})", build)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlagImage {
+    pub category: String,
+    pub file: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Flag {
+    pub icon: FlagImage,
+    pub background: FlagImage,
+    pub colors: Vec<String>,
+}
+
+/// The `meta` file of a Stellaris save.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Meta {
+    pub version: Version,
+    pub name: String,
+    #[serde(serialize_with = "crate::clausewitz::val::serialize_date")]
+    pub date: ClausewitzDate,
+    pub required_dlcs: Vec<String>,
+    pub player_portrait: Option<String>,
+    pub flag: Option<Flag>,
+    pub meta_fleets: Option<i64>,
+    pub meta_planets: Option<i64>,
+}
+
+impl Meta {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MetaError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| MetaError {
+            err: format!("can not read {}: {}", path.display(), e),
+        })?;
+        text.parse()
+    }
+
+    pub fn from_val(val: &Val) -> Result<Self, MetaError> {
+        let mut version: Version = val.get_string_at_path("version")?.parse()?;
+        version.build = val
+            .get_integer_at_path("version_control_revision")
+            .ok()
+            .and_then(|build| u64::try_from(build).ok());
+        Ok(Meta {
+            version,
+            name: val.get_string_at_path("name")?.to_owned(),
+            date: val.get_date_at_path("date")?,
+            required_dlcs: match val.get_at_path("required_dlcs") {
+                Ok(_) => strings(val.get_set_at_path("required_dlcs")?),
+                Err(_) => vec![],
+            },
+            player_portrait: val
+                .get_string_at_path("player_portrait")
+                .ok()
+                .map(str::to_owned),
+            flag: flag(val),
+            meta_fleets: val.get_integer_at_path("meta_fleets").ok(),
+            meta_planets: val.get_integer_at_path("meta_planets").ok(),
+        })
+    }
+
+    /// The required DLCs which are not in `owned`.
+    pub fn missing_dlcs<S: AsRef<str>>(&self, owned: &[S]) -> Vec<&str> {
+        self.required_dlcs
+            .iter()
+            .filter(|dlc| !owned.iter().any(|o| o.as_ref() == dlc.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Whether the save can be loaded with the DLCs in `owned`.
+    pub fn is_playable_with<S: AsRef<str>>(&self, owned: &[S]) -> bool {
+        self.missing_dlcs(owned).is_empty()
+    }
+}
+
+impl FromStr for Meta {
+    type Err = MetaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (_, val) = root(s).map_err(|e| {
+            let at = match e {
+                nom::Err::Error(e) | nom::Err::Failure(e) => {
+                    e.errors.first().map(|(rest, _)| s.len() - rest.len())
+                }
+                nom::Err::Incomplete(_) => None,
+            };
+            MetaError {
+                err: format!(
+                    "the meta file is not valid at byte {}",
+                    at.unwrap_or(s.len())
+                ),
+            }
+        })?;
+        Meta::from_val(&val)
+    }
+}
+
+fn strings(vals: &[Val]) -> Vec<String> {
+    vals.iter()
+        .filter_map(|val| match val {
+            Val::StringLiteral(s) | Val::Identifier(s) => Some(s.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn flag(val: &Val) -> Option<Flag> {
+    let image = |path: &str| -> Option<FlagImage> {
+        Some(FlagImage {
+            category: val
+                .get_string_at_path(&format!("flag.{}.category", path))
+                .ok()?
+                .to_owned(),
+            file: val
+                .get_string_at_path(&format!("flag.{}.file", path))
+                .ok()?
+                .to_owned(),
+        })
+    };
+    Some(Flag {
+        icon: image("icon")?,
+        background: image("background")?,
+        colors: strings(val.get_set_at_path("flag.colors").ok()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const META: &str = r###"version="Cepheus v3.4.5"
+version_control_revision=95132
+name="mp_Custodianship"
+date="2290.03.05"
+required_dlcs={
+	"Apocalypse"
+	"Utopia"
+}
+player_portrait="sd_hum_robot"
+flag={
+	icon={
+		category="human"
+		file="flag_human_8.dds"
+	}
+	background={
+		category="backgrounds"
+		file="00_solid.dds"
+	}
+	colors={
+		"blue"
+		"black"
+		"null"
+		"null"
+	}
+}
+meta_fleets=120
+meta_planets=13
+"###;
+
+    #[test]
+    fn from_str__meta__typed() {
+        let meta: Meta = META.parse().unwrap();
+        assert_eq!(meta.version.to_string(), "Cepheus v3.4.5 (95132)");
+        assert_eq!(meta.name, "mp_Custodianship");
+        assert_eq!(meta.date, ClausewitzDate::from_ymd(2290, 3, 5));
+        assert_eq!(meta.required_dlcs, vec!["Apocalypse", "Utopia"]);
+        assert_eq!(meta.flag.as_ref().unwrap().icon.file, "flag_human_8.dds");
+        assert_eq!(meta.flag.as_ref().unwrap().colors.len(), 4);
+        assert_eq!(meta.meta_fleets, Some(120));
+    }
+
+    #[test]
+    fn from_str__minimal_meta__optional_fields_empty() {
+        let meta: Meta = r#"version="Orion v2.0.1" name="x" date="2200.01.01""#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            meta.version,
+            Version {
+                name: "Orion".to_owned(),
+                ..Version::new(2, 0, 1)
+            }
+        );
+        assert_eq!(meta.version.name, "Orion");
+        assert!(meta.required_dlcs.is_empty());
+        assert_eq!(meta.flag, None);
+    }
+
+    #[test]
+    fn from_str__missing_name__err() {
+        assert!(r#"version="Orion v2.0.1" date="2200.01.01""#.parse::<Meta>().is_err());
+    }
+
+    #[test]
+    fn missing_dlcs__owned_subset__missing_listed() {
+        let meta: Meta = META.parse().unwrap();
+        assert_eq!(meta.missing_dlcs(&["Utopia"]), vec!["Apocalypse"]);
+        assert!(!meta.is_playable_with(&["Utopia"]));
+        assert!(meta.is_playable_with(&["Utopia", "Apocalypse", "Megacorp"]));
+    }
+
+    #[test]
+    fn version__ordering__numeric() {
+        let old: Version = "Cepheus v3.4.5".parse().unwrap();
+        let new: Version = "Canis Minor v3.10.0".parse().unwrap();
+        assert!(old < new);
+        assert!(!old.is_compatible_with(&new));
+        assert!(old.is_compatible_with(&"v3.4.3".parse().unwrap()));
+        assert!("Cepheus 3.x".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn version__release_name__not_compared() {
+        let named: Version = "Cepheus v3.4.5".parse().unwrap();
+        assert_eq!(named, Version::new(3, 4, 5));
+        assert_eq!(
+            named.cmp(&"Aardvark v3.4.5".parse().unwrap()),
+            Ordering::Equal
+        );
+        let built = Version {
+            build: Some(95132),
+            ..Version::new(3, 4, 5)
+        };
+        assert!(named < built);
+        assert!(built < Version::new(3, 4, 6));
+    }
+
+    #[test]
+    fn serialize__meta__json() {
+        let meta: Meta = META.parse().unwrap();
+        let json = serde_json::to_value(&meta).unwrap();
+        assert_eq!(json["date"], serde_json::json!([2290, 3, 5]));
+        assert_eq!(json["version"]["minor"], 4);
+    }
+}
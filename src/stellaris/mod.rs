@@ -0,0 +1,7 @@
+//! Shapes which are specific to Stellaris saves.
+
+mod meta;
+mod name;
+
+pub use meta::{Flag, FlagImage, Meta, MetaError, Version};
+pub use name::{NameTemplate, StringTable};
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use crate::{
+    localisation::{render, Localisation, Style},
+    ClausewitzValue, IndexError, Val,
+};
+
+/// Somewhere to look up the localised text of a key.
+pub trait StringTable {
+    fn lookup(&self, key: &str) -> Option<String>;
+}
+
+impl StringTable for HashMap<String, String> {
+    fn lookup(&self, key: &str) -> Option<String> {
+        self.get(key).cloned()
+    }
+}
+
+impl StringTable for Localisation {
+    fn lookup(&self, key: &str) -> Option<String> {
+        self.resolve(key).ok()
+    }
+}
+
+/// A name like `name={ key="%ADJ%" variables={ { key="adjective" value={ key="SPEC_Human" } } } }`,
+/// or a plain string from an older save.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameTemplate<'a> {
+    pub key: &'a str,
+    /// Set for names typed in by a player, whose key is the text itself.
+    pub literal: bool,
+    /// Variables without a value render as nothing.
+    pub variables: Vec<(&'a str, Option<NameTemplate<'a>>)>,
+}
+
+impl<'a> NameTemplate<'a> {
+    pub fn from_val(val: &Val<'a>) -> Option<Self> {
+        let entries = match val {
+            Val::StringLiteral(s) | Val::Identifier(s) => {
+                return Some(NameTemplate {
+                    key: s,
+                    literal: true,
+                    variables: vec![],
+                })
+            }
+            Val::Dict(entries) => entries,
+            _ => return None,
+        };
+        let get = |key: &str| entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v);
+        let key = match get("key")? {
+            Val::StringLiteral(s) | Val::Identifier(s) => *s,
+            _ => return None,
+        };
+        let literal = matches!(
+            get("literal"),
+            Some(Val::Identifier("yes") | Val::Boolean(true))
+        );
+        let variables = match get("variables") {
+            Some(Val::Set(variables)) => variables
+                .iter()
+                .map(|variable| match variable {
+                    Val::Dict(entries) => {
+                        let get =
+                            |key: &str| entries.iter().find(|(k, _)| *k == key).map(|(_, v)| v);
+                        let name = match get("key")? {
+                            Val::StringLiteral(s) | Val::Identifier(s) => *s,
+                            _ => return None,
+                        };
+                        let value = match get("value") {
+                            Some(value) => Some(NameTemplate::from_val(value)?),
+                            None => None,
+                        };
+                        Some((name, value))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+            Some(Val::Dict(empty)) if empty.is_empty() => vec![],
+            None => vec![],
+            _ => return None,
+        };
+        Some(NameTemplate {
+            key,
+            literal,
+            variables,
+        })
+    }
+
+    pub fn from_path(val: &'a Val<'a>, path: &str) -> Result<Self, IndexError> {
+        NameTemplate::from_val(val.get_at_path(path)?).ok_or(IndexError {
+            err: format!("{} is not the name you are looking for!", path),
+        })
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&NameTemplate<'a>> {
+        self.variables
+            .iter()
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, value)| value.as_ref())
+    }
+
+    /// Renders the name with its keys looked up in `table`; keys which are not in the table are
+    /// used as they are.
+    pub fn render<T: StringTable + ?Sized>(&self, table: &T) -> String {
+        render(&self.render_raw(table), Style::Plain)
+    }
+
+    /// Renders the name using the keys themselves as their text.
+    pub fn render_literal(&self) -> String {
+        self.render(&HashMap::new())
+    }
+
+    fn render_raw<T: StringTable + ?Sized>(&self, table: &T) -> String {
+        if self.literal {
+            return self.key.to_owned();
+        }
+        let variable = |name: &str| {
+            self.variable(name)
+                .map(|value| value.render_raw(table))
+                .unwrap_or_default()
+        };
+        match self.key {
+            "%ADJ%" => join(&[variable("adjective"), variable("1")]),
+            "%ADJECTIVE%" => variable("adjective"),
+            "%ACRONYM%" => variable("base")
+                .split_whitespace()
+                .filter_map(|word| word.chars().next())
+                .flat_map(char::to_uppercase)
+                .collect(),
+            "%SEQ%" => {
+                let number = variable("num");
+                let n = number.parse::<u32>().ok();
+                variable("fmt")
+                    .replace("$ORD$", &n.map_or(number.clone(), ordinal))
+                    .replace("$R$", &n.map_or(number.clone(), roman))
+                    .replace("$C$", &number)
+            }
+            key if key.len() > 1 && key.starts_with('%') && key.ends_with('%') => join(
+                &self
+                    .variables
+                    .iter()
+                    .map(|(_, value)| {
+                        value
+                            .as_ref()
+                            .map(|v| v.render_raw(table))
+                            .unwrap_or_default()
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            key => {
+                let mut text = table.lookup(key).unwrap_or_else(|| key.to_owned());
+                for (name, value) in &self.variables {
+                    let value = value
+                        .as_ref()
+                        .map(|v| v.render_raw(table))
+                        .unwrap_or_default();
+                    for placeholder in [
+                        format!("<{}>", name),
+                        format!("[{}]", name),
+                        format!("${}$", name),
+                    ] {
+                        text = text.replace(&placeholder, &value);
+                    }
+                }
+                text
+            }
+        }
+    }
+}
+
+fn join(parts: &[String]) -> String {
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+fn roman(mut n: u32) -> String {
+    let numerals = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in numerals {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::root;
+
+    fn table(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn render__format_with_variables__substituted() {
+        let text = r###"country={
+            0={
+                name={
+                    key="format.gen_imp.1"
+                    variables={
+                        {
+                            key="generic_imp_desc"
+                            value={
+                                key="United"
+                            }
+                        }
+ {
+                            key="generic_states"
+                            value={
+                                key="Nations"
+                            }
+                        }
+ {
+                            key="This.GetHomeWorldName"
+                            value={
+                                key="NAME_Earth"
+                            }
+                        }
+                    }
+                }
+            }
+        }"###;
+        let (_, val) = root(text).unwrap();
+        let name = NameTemplate::from_path(&val, "country.0.name").unwrap();
+        let table = table(&[
+            (
+                "format.gen_imp.1",
+                "§Y<generic_imp_desc>§! <generic_states> of [This.GetHomeWorldName]",
+            ),
+            ("NAME_Earth", "Earth"),
+        ]);
+        assert_eq!(name.render(&table), "United Nations of Earth");
+        assert_eq!(name.render_literal(), "format.gen_imp.1");
+    }
+
+    #[test]
+    fn render__adj__adjective_then_noun() {
+        let text = r###"name={ key="%ADJ%" variables={ { key="adjective" value={ key="SPEC_Human" } } { key="1" value={ key="Commonwealth" } } } }"###;
+        let (_, val) = root(text).unwrap();
+        let name = NameTemplate::from_path(&val, "name").unwrap();
+        let table = table(&[("SPEC_Human", "Human")]);
+        assert_eq!(name.render(&table), "Human Commonwealth");
+    }
+
+    #[test]
+    fn render__acronym__initials() {
+        let text = r###"ship_prefix={ key="%ACRONYM%" variables={ { key="base" value={ key="format.hive_mind.1" variables={ { key="hive_mind" value={ key="Hive" } } { key="This.GetSpeciesName" value={ key="SPEC_Armonican" } } } } } } }"###;
+        let (_, val) = root(text).unwrap();
+        let name = NameTemplate::from_path(&val, "ship_prefix").unwrap();
+        let table = table(&[
+            ("format.hive_mind.1", "[This.GetSpeciesName] <hive_mind>"),
+            ("SPEC_Armonican", "Armonican"),
+        ]);
+        assert_eq!(name.render(&table), "AH");
+    }
+
+    #[test]
+    fn render__seq__ordinal_and_roman() {
+        let text = r###"name={ key="%SEQ%" variables={ { key="fmt" value={ key="HUMAN_FLEET_SEQ" } } { key="num" value={ key="3" } } } }"###;
+        let (_, val) = root(text).unwrap();
+        let name = NameTemplate::from_path(&val, "name").unwrap();
+        let table = table(&[("HUMAN_FLEET_SEQ", "$ORD$ Fleet $R$")]);
+        assert_eq!(name.render(&table), "3rd Fleet III");
+    }
+
+    #[test]
+    fn render__literal__key_kept() {
+        let text = r###"name={ key="Im Daddy" literal=yes }"###;
+        let (_, val) = root(text).unwrap();
+        let name = NameTemplate::from_path(&val, "name").unwrap();
+        assert_eq!(name.render(&table(&[("Im Daddy", "nope")])), "Im Daddy");
+    }
+
+    #[test]
+    fn render__variable_without_value__empty() {
+        let text = r###"adjective={ key="%ADJECTIVE%" variables={ { key="adjective" } } }"###;
+        let (_, val) = root(text).unwrap();
+        let name = NameTemplate::from_path(&val, "adjective").unwrap();
+        assert_eq!(name.render_literal(), "");
+    }
+
+    #[test]
+    fn from_path__plain_string__literal() {
+        let (_, val) = root(r###"name="Old Name""###).unwrap();
+        let name = NameTemplate::from_path(&val, "name").unwrap();
+        assert_eq!(name.render_literal(), "Old Name");
+    }
+
+    #[test]
+    fn from_path__not_a_name__err() {
+        let (_, val) = root("name=5").unwrap();
+        assert!(NameTemplate::from_path(&val, "name").is_err());
+    }
+}
@@ -0,0 +1,32 @@
+#[cfg(test)]
+mod corpus_test {
+    use std::fs;
+
+    use clausewitz_parser::root;
+
+    /// Every file in `tests/corpus` is a block which has tripped up the parser at some point.
+    #[test]
+    fn corpus__every_file__parses_completely() {
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+        let mut paths = fs::read_dir(corpus)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let text = fs::read_to_string(&path).unwrap();
+            let result = root(&text);
+
+            assert!(result.is_ok(), "{:?} failed: {:?}", path, result);
+            let (remainder, _) = result.unwrap();
+            assert!(
+                remainder.trim().is_empty(),
+                "{:?} stopped at {:?}",
+                path,
+                remainder
+            );
+        }
+    }
+}